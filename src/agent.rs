@@ -0,0 +1,1504 @@
+use anyhow::Result;
+use std::future::Future;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
+
+use crate::checkpoint::CheckpointStore;
+use crate::compaction::Compactor;
+use crate::permissions::{Decision, PermissionPolicy};
+use crate::provider::retry::RetryNotice;
+use crate::provider::{LLMProvider, Message, StreamChunk, ToolCall};
+use crate::session::Session;
+use crate::tools::{ToolContext, ToolOutput, ToolRegistry};
+
+/// Callbacks for everything a front-end may want to show while the agent works.
+/// All methods default to doing nothing.
+pub trait AgentEvents: Send {
+    /// A completion request with this many messages is about to be sent
+    fn on_request(&mut self, _message_count: usize) {}
+
+    /// Every raw chunk received from the provider
+    fn on_chunk(&mut self, _chunk: &StreamChunk) {}
+
+    /// A request failed and will be sent again after a delay
+    fn on_retry(&mut self, _notice: &RetryNotice) {}
+
+    /// Streamed assistant text
+    fn on_text(&mut self, _text: &str) {}
+
+    /// The assistant's response finished streaming
+    fn on_response_end(&mut self) {}
+
+    /// The assistant requested tool calls, which are about to run
+    fn on_tools_start(&mut self, _calls: &[ToolCall]) {}
+
+    fn on_tool_call(&mut self, _call: &ToolCall) {}
+
+    /// The permission policy asks the user to confirm this call. `preview` is what
+    /// the call would change (a unified diff for file edits). Rejects by default.
+    fn approve_tool_call(
+        &mut self,
+        _call: &ToolCall,
+        _reason: &str,
+        _preview: Option<&str>,
+    ) -> Approval {
+        Approval::Reject
+    }
+
+    fn on_tool_result(&mut self, _call: &ToolCall, _result: &ToolOutput) {}
+
+    fn on_tool_error(&mut self, _call: &ToolCall, _error: &anyhow::Error) {}
+
+    fn on_compaction_start(&mut self) {}
+
+    /// Number of messages summarized, or why compaction failed
+    fn on_compaction_end(&mut self, _result: &Result<usize>) {}
+
+    /// The turn was cancelled by the user
+    fn on_interrupted(&mut self) {}
+}
+
+/// The user's answer when a tool call needs confirmation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    Approve,
+    Reject,
+    /// Approve, and stop asking about edits to this file for the rest of the session
+    AlwaysAllowFile,
+}
+
+/// How a turn ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnOutcome {
+    Completed,
+    Interrupted,
+}
+
+/// Recorded in the session wherever a turn was cut short
+const INTERRUPTED: &str = "Interrupted by user";
+
+/// Appended to the text of a response that failed part-way
+const RESPONSE_FAILED: &str = "Response cut off by a provider error";
+
+/// Drives the conversation: sends the history to the provider, streams the reply,
+/// runs requested tools and records everything in the session
+pub struct AgentRunner {
+    provider: Box<dyn LLMProvider>,
+    tools: ToolRegistry,
+    tool_definitions: Vec<serde_json::Value>,
+    session: Session,
+    system_prompt: String,
+    compactor: Compactor,
+    messages: Vec<Message>,
+    permissions: PermissionPolicy,
+    context: ToolContext,
+    retry_notices: Option<UnboundedReceiver<RetryNotice>>,
+}
+
+impl AgentRunner {
+    pub fn new(
+        provider: Box<dyn LLMProvider>,
+        tools: ToolRegistry,
+        session: Session,
+        system_prompt: String,
+        compactor: Compactor,
+    ) -> Self {
+        let tool_definitions = tools
+            .list_definitions()
+            .iter()
+            .map(|def| serde_json::to_value(def).unwrap())
+            .collect();
+
+        let context = ToolContext::new(&session.info.directory, false);
+
+        Self {
+            provider,
+            tools,
+            tool_definitions,
+            session,
+            system_prompt,
+            compactor,
+            messages: Vec::new(),
+            permissions: PermissionPolicy::default(),
+            context,
+            retry_notices: None,
+        }
+    }
+
+    /// Reject tool paths outside the session directory
+    pub fn set_strict_workspace(&mut self, strict: bool) {
+        self.context.set_strict(strict);
+    }
+
+    /// Snapshot files before tools edit them, one checkpoint per turn
+    pub fn set_checkpoints(&mut self, store: CheckpointStore) {
+        self.context.set_checkpoints(store);
+    }
+
+    pub fn checkpoints(&self) -> Option<&CheckpointStore> {
+        self.context.checkpoints()
+    }
+
+    /// Policy deciding which tool calls run, need confirmation or are refused
+    pub fn set_permissions(&mut self, permissions: PermissionPolicy) {
+        self.permissions = permissions;
+    }
+
+    /// Retry notices from the provider, passed on to `AgentEvents::on_retry`
+    pub fn set_retry_notices(&mut self, notices: UnboundedReceiver<RetryNotice>) {
+        self.retry_notices = Some(notices);
+    }
+
+    pub fn provider(&self) -> &dyn LLMProvider {
+        self.provider.as_ref()
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Estimated prompt size of the current history and the configured budget, in tokens
+    pub fn context_usage(&self) -> (usize, usize) {
+        let estimate = self
+            .compactor
+            .estimate(&self.build_messages(), &self.tool_definitions);
+        (estimate, self.compactor.budget_tokens())
+    }
+
+    /// Handle one user message: loop over completions and tool calls until the
+    /// assistant replies without tool calls or calls `finish`. Cancelling `cancel`
+    /// stops the request or running tools, records the interruption in the session
+    /// and ends the turn.
+    pub async fn run_turn(
+        &mut self,
+        input: String,
+        events: &mut dyn AgentEvents,
+        cancel: &CancellationToken,
+    ) -> Result<TurnOutcome> {
+        if let Some(store) = self.context.checkpoints() {
+            store.begin_turn(&input);
+        }
+        self.session.add_user_message(input);
+        self.messages = self.build_messages();
+
+        // Compaction only starts once there is something to summarize, and is not
+        // retried in this turn after it failed
+        let mut compaction_failed = false;
+        loop {
+            if !compaction_failed
+                && self
+                    .compactor
+                    .should_compact(&self.messages, &self.tool_definitions)
+                && self.compactor.split_to_fit(&self.session).is_some()
+            {
+                compaction_failed = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => return Ok(self.interrupt(String::new(), events)),
+                    result = self.run_compaction(events, true) => result.is_none(),
+                };
+            }
+
+            events.on_request(self.messages.len());
+            let request = reporting_retries(
+                &mut self.retry_notices,
+                events,
+                self.provider
+                    .stream_completion(self.messages.clone(), Some(self.tool_definitions.clone())),
+            );
+            let mut rx = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Ok(self.interrupt(String::new(), events)),
+                rx = request => rx?,
+            };
+
+            let mut full_content = String::new();
+            let mut tool_calls = Vec::new();
+            let mut usage = None;
+
+            loop {
+                let chunk = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => {
+                        events.on_response_end();
+                        return Ok(self.interrupt(full_content, events));
+                    }
+                    chunk = rx.recv() => chunk,
+                };
+                let chunk = match chunk {
+                    Some(Ok(chunk)) => chunk,
+                    Some(Err(e)) => {
+                        events.on_response_end();
+                        self.record_failed_response(full_content);
+                        return Err(e);
+                    }
+                    None => break,
+                };
+                events.on_chunk(&chunk);
+
+                if let Some(content) = &chunk.content {
+                    events.on_text(content);
+                    full_content.push_str(content);
+                }
+
+                tool_calls.extend(chunk.tool_calls);
+
+                if chunk.usage.is_some() {
+                    usage = chunk.usage;
+                }
+
+                if chunk.finished {
+                    break;
+                }
+            }
+            events.on_response_end();
+
+            let content = if full_content.is_empty() {
+                None
+            } else {
+                Some(full_content)
+            };
+            self.session
+                .add_assistant_message(content.clone(), tool_calls.clone(), usage);
+
+            // Keep the assistant turn in the request history so the model sees its own reply
+            self.messages.push(Message {
+                role: "assistant".to_string(),
+                content: content.unwrap_or_default(),
+                tool_call_id: None,
+                tool_calls: tool_calls.clone(),
+            });
+
+            if tool_calls.is_empty() {
+                break;
+            }
+
+            events.on_tools_start(&tool_calls);
+            if !self.execute_tools(&tool_calls, events, cancel).await {
+                events.on_interrupted();
+                return Ok(TurnOutcome::Interrupted);
+            }
+
+            if tool_calls.iter().any(|tc| tc.name == "finish") {
+                break;
+            }
+        }
+
+        Ok(TurnOutcome::Completed)
+    }
+
+    /// Record a response cut off by the user, keeping whatever text had streamed.
+    /// Tool calls of an unfinished response are dropped since they never ran.
+    fn interrupt(&mut self, partial: String, events: &mut dyn AgentEvents) -> TurnOutcome {
+        let note = if partial.is_empty() {
+            INTERRUPTED.to_string()
+        } else {
+            format!("{}\n\n[{}]", partial, INTERRUPTED)
+        };
+        self.session
+            .add_assistant_message(Some(note), Vec::new(), None);
+        events.on_interrupted();
+        TurnOutcome::Interrupted
+    }
+
+    /// Keep the text of a response that failed part-way, marked as incomplete, so
+    /// the next request shows the model where it was cut off
+    fn record_failed_response(&mut self, partial: String) {
+        if partial.is_empty() {
+            return;
+        }
+        self.session.add_assistant_message(
+            Some(format!("{}\n\n[{}]", partial, RESPONSE_FAILED)),
+            Vec::new(),
+            None,
+        );
+    }
+
+    /// Summarize older turns into the session and rebuild the request history from it.
+    /// Failures are reported to `events` but not fatal.
+    pub async fn compact(&mut self, events: &mut dyn AgentEvents) {
+        self.run_compaction(events, false).await;
+    }
+
+    /// Compact the session, with `to_fit` as far as needed to get under budget.
+    /// Returns the number of messages summarized, or None if compaction failed.
+    async fn run_compaction(
+        &mut self,
+        events: &mut dyn AgentEvents,
+        to_fit: bool,
+    ) -> Option<usize> {
+        events.on_compaction_start();
+        let provider = self.provider.as_ref();
+        let compaction = async {
+            if to_fit {
+                self.compactor
+                    .compact_to_fit(provider, &mut self.session)
+                    .await
+            } else {
+                self.compactor.compact(provider, &mut self.session).await
+            }
+        };
+        let result = reporting_retries(&mut self.retry_notices, events, compaction).await;
+        if matches!(result, Ok(count) if count > 0) {
+            self.messages = self.build_messages();
+        }
+        events.on_compaction_end(&result);
+        result.ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.session.save()
+    }
+
+    /// Run the calls of one response. Consecutive read-only calls run concurrently;
+    /// anything else runs on its own, so side effects keep the order the model chose.
+    /// Returns false if `cancel` fired; calls that did not finish are recorded as
+    /// interrupted.
+    async fn execute_tools(
+        &mut self,
+        tool_calls: &[ToolCall],
+        events: &mut dyn AgentEvents,
+        cancel: &CancellationToken,
+    ) -> bool {
+        let mut start = 0;
+        while start < tool_calls.len() {
+            let read_only = |tc: &ToolCall| self.tools.is_read_only(&tc.name, &tc.arguments);
+            let end = if read_only(&tool_calls[start]) {
+                start
+                    + tool_calls[start..]
+                        .iter()
+                        .take_while(|tc| read_only(tc))
+                        .count()
+            } else {
+                start + 1
+            };
+            let batch = &tool_calls[start..end];
+
+            let mut refusals = Vec::new();
+            for tool_call in batch {
+                events.on_tool_call(tool_call);
+                refusals.push(self.check_permission(tool_call, events));
+            }
+            let calls = batch
+                .iter()
+                .zip(&refusals)
+                .filter(|(_, refusal)| refusal.is_none())
+                .map(|(tc, _)| (tc.name.clone(), tc.arguments.clone()))
+                .collect();
+            // Dropping the tool futures stops them (bash kills its child on drop)
+            let results = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => None,
+                results = self.tools.execute_concurrently(calls, &self.context) => Some(results),
+            };
+
+            let Some(results) = results else {
+                for tool_call in &tool_calls[start..] {
+                    self.record_tool_result(
+                        tool_call,
+                        Ok(ToolOutput {
+                            output: serde_json::json!({ "interrupted": true }),
+                            observation: INTERRUPTED.to_string(),
+                            display: None,
+                            status: "cancelled".to_string(),
+                        }),
+                        events,
+                    );
+                }
+                return false;
+            };
+            let mut results = results.into_iter();
+            for (tool_call, refusal) in batch.iter().zip(refusals) {
+                let result = match refusal {
+                    Some(refusal) => Ok(refusal),
+                    None => results.next().expect("one result per approved call"),
+                };
+                self.record_tool_result(tool_call, result, events);
+            }
+
+            start = end;
+        }
+        true
+    }
+
+    /// Apply the permission policy, asking the user if it says so. Returns the
+    /// result to record instead of running the call, if it may not run.
+    fn check_permission(
+        &mut self,
+        tool_call: &ToolCall,
+        events: &mut dyn AgentEvents,
+    ) -> Option<ToolOutput> {
+        // Arguments that are not valid JSON fail without running; nothing to ask about
+        if tool_call.arguments.is_string() {
+            return None;
+        }
+        let verdict = self
+            .permissions
+            .check(&tool_call.name, &tool_call.arguments, &self.context);
+        match verdict.decision {
+            Decision::Allow => None,
+            Decision::Ask => {
+                // A call that cannot be previewed is asked about without one; if it
+                // is approved it fails with the same error when it runs
+                let preview = self
+                    .tools
+                    .preview(&tool_call.name, &tool_call.arguments, &self.context)
+                    .ok()
+                    .flatten();
+                match events.approve_tool_call(tool_call, &verdict.reason, preview.as_deref()) {
+                    Approval::Approve => None,
+                    Approval::AlwaysAllowFile => {
+                        let path = tool_call
+                            .arguments
+                            .get("file_path")
+                            .and_then(|v| v.as_str())
+                            .and_then(|path| self.context.resolve(path).ok());
+                        if let Some(path) = path {
+                            self.permissions.allow_file(&path);
+                        }
+                        None
+                    }
+                    Approval::Reject => Some(ToolOutput {
+                        output: serde_json::json!({ "approved": false }),
+                        observation: "Tool call declined by user".to_string(),
+                        display: None,
+                        status: "cancelled".to_string(),
+                    }),
+                }
+            }
+            Decision::Deny => Some(ToolOutput {
+                output: serde_json::json!({ "denied": true, "reason": verdict.reason }),
+                observation: format!(
+                    "Blocked by the user's permission policy ({}). Do not retry it in another form",
+                    verdict.reason
+                ),
+                display: None,
+                status: "denied".to_string(),
+            }),
+        }
+    }
+
+    /// Store a tool result in the session and the request history
+    fn record_tool_result(
+        &mut self,
+        tool_call: &ToolCall,
+        result: Result<ToolOutput>,
+        events: &mut dyn AgentEvents,
+    ) {
+        let content = match result {
+            Ok(result) => {
+                events.on_tool_result(tool_call, &result);
+
+                // Combine observation with structured output for LLM context
+                let output_json = serde_json::to_string_pretty(&result.output)
+                    .unwrap_or_else(|_| format!("{:?}", result.output));
+                let content = format!("{}\n\n```\n{}\n```", result.observation, output_json);
+
+                self.session.add_tool_result(
+                    tool_call.id.clone(),
+                    result.output,
+                    result.observation,
+                    result.status,
+                );
+                content
+            }
+            Err(e) => {
+                events.on_tool_error(tool_call, &e);
+
+                let error_msg = format!("Tool execution failed: {}", e);
+                self.session.add_tool_result(
+                    tool_call.id.clone(),
+                    serde_json::json!({"error": error_msg}),
+                    error_msg.clone(),
+                    "error".to_string(),
+                );
+                error_msg
+            }
+        };
+
+        self.messages.push(Message {
+            role: "user".to_string(),
+            content,
+            tool_call_id: Some(tool_call.id.clone()),
+            tool_calls: Vec::new(),
+        });
+    }
+
+    /// System prompt followed by the session's conversation history
+    fn build_messages(&self) -> Vec<Message> {
+        let mut messages = vec![Message {
+            role: "system".to_string(),
+            content: self.system_prompt.clone(),
+            tool_call_id: None,
+            tool_calls: Vec::new(),
+        }];
+        messages.extend(self.session.get_conversation_history());
+        messages
+    }
+}
+
+/// Await `request`, passing the provider's retry notices to `events` meanwhile
+async fn reporting_retries<T>(
+    notices: &mut Option<UnboundedReceiver<RetryNotice>>,
+    events: &mut dyn AgentEvents,
+    request: impl Future<Output = T>,
+) -> T {
+    let Some(notices) = notices else {
+        return request.await;
+    };
+    tokio::pin!(request);
+    loop {
+        tokio::select! {
+            biased;
+            output = &mut request => return output,
+            Some(notice) = notices.recv() => events.on_retry(&notice),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compaction::ContextConfig;
+    use crate::testing::{done, text, tool_call, NullEvents, ScriptedProvider, TempWorkspace};
+    use crate::tools::{Tool, ToolContext};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    /// Echoes its `text` argument back
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Echo the input"
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            json!({"type": "object", "properties": {"text": {"type": "string"}}})
+        }
+
+        async fn execute(
+            &self,
+            input: serde_json::Value,
+            _ctx: &ToolContext,
+        ) -> Result<ToolOutput> {
+            let text = input["text"].as_str().unwrap_or_default().to_string();
+            Ok(ToolOutput {
+                output: json!({ "text": text }),
+                observation: format!("echoed {}", text),
+                display: None,
+                status: "success".to_string(),
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingEvents {
+        events: Vec<String>,
+    }
+
+    impl AgentEvents for RecordingEvents {
+        fn on_text(&mut self, text: &str) {
+            self.events.push(format!("text:{}", text));
+        }
+
+        fn on_tool_call(&mut self, call: &ToolCall) {
+            self.events.push(format!("call:{}", call.name));
+        }
+
+        fn on_tool_result(&mut self, call: &ToolCall, result: &ToolOutput) {
+            self.events
+                .push(format!("result:{}:{}", call.name, result.observation));
+        }
+
+        fn on_tool_error(&mut self, call: &ToolCall, _error: &anyhow::Error) {
+            self.events.push(format!("error:{}", call.name));
+        }
+
+        fn on_compaction_end(&mut self, result: &Result<usize>) {
+            match result {
+                Ok(count) => self.events.push(format!("compacted:{}", count)),
+                Err(e) => self.events.push(format!("compaction failed:{}", e)),
+            }
+        }
+    }
+
+    type Requests = Arc<Mutex<Vec<Vec<Message>>>>;
+
+    fn runner_in(
+        workspace: &TempWorkspace,
+        responses: Vec<Vec<StreamChunk>>,
+    ) -> (AgentRunner, Requests) {
+        runner_with(workspace, ScriptedProvider::new(responses))
+    }
+
+    fn runner_with(
+        workspace: &TempWorkspace,
+        provider: ScriptedProvider,
+    ) -> (AgentRunner, Requests) {
+        let requests = provider.requests();
+        let mut tools = ToolRegistry::new();
+        tools.register(Box::new(EchoTool));
+
+        let runner = AgentRunner::new(
+            Box::new(provider),
+            tools,
+            workspace.session(),
+            "You are a test.".to_string(),
+            Compactor::new(&ContextConfig::default(), "scripted"),
+        );
+        (runner, requests)
+    }
+
+    #[tokio::test]
+    async fn text_reply_ends_turn() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, requests) =
+            runner_in(&workspace, vec![vec![text("Hel"), text("lo"), done()]]);
+        let mut events = RecordingEvents::default();
+
+        runner
+            .run_turn("hi".to_string(), &mut events, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert_eq!(events.events, vec!["text:Hel", "text:lo"]);
+        let messages = &runner.session().messages;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content.as_deref(), Some("Hello"));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0][0].role, "system");
+        assert_eq!(requests[0][1].content, "hi");
+    }
+
+    #[tokio::test]
+    async fn stream_failure_is_reported_not_stored_as_a_reply() {
+        let workspace = TempWorkspace::new();
+        let provider = ScriptedProvider::new(vec![vec![text("Half an ans")]])
+            .fail_stream(0, "connection reset by peer");
+        let (mut runner, _) = runner_with(&workspace, provider);
+
+        let err = runner
+            .run_turn("hi".to_string(), &mut NullEvents, &CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("connection reset by peer"));
+
+        let messages = &runner.session().messages;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[1].content.as_deref(),
+            Some("Half an ans\n\n[Response cut off by a provider error]")
+        );
+    }
+
+    #[tokio::test]
+    async fn a_single_long_turn_is_compacted_down_to_its_latest_step() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, requests) = runner_in(
+            &workspace,
+            vec![
+                vec![tool_call("call_1", "echo", json!({"text": "a"})), done()],
+                vec![tool_call("call_2", "echo", json!({"text": "b"})), done()],
+                vec![text("The user asked for echoes; a was echoed."), done()],
+                vec![text("Done."), done()],
+            ],
+        );
+        // Every request is over budget
+        let budget = ContextConfig {
+            budget_tokens: Some(1),
+            ..Default::default()
+        };
+        runner.compactor = Compactor::new(&budget, "scripted");
+        let mut events = RecordingEvents::default();
+
+        runner
+            .run_turn(
+                "echo twice".to_string(),
+                &mut events,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        // Nothing to summarize before the first request and with only one step; once
+        // there are two, the first is summarized
+        let compactions: Vec<&String> = events
+            .events
+            .iter()
+            .filter(|e| e.starts_with("compact"))
+            .collect();
+        assert_eq!(compactions, vec!["compacted:3"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[2][1].content.contains("User: echo twice"));
+        assert!(requests[2][1].content.contains("Tool result: echoed a"));
+        let last = &requests[3];
+        assert_eq!(last.len(), 4);
+        assert!(last[1]
+            .content
+            .starts_with("Summary of the earlier conversation"));
+        assert_eq!(last[2].tool_calls[0].id, "call_2");
+        assert!(last[3].content.starts_with("echoed b"));
+    }
+
+    #[tokio::test]
+    async fn compaction_failures_are_not_retried_within_a_turn() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, requests) = runner_in(
+            &workspace,
+            vec![
+                vec![tool_call("call_1", "echo", json!({"text": "a"})), done()],
+                vec![tool_call("call_2", "echo", json!({"text": "b"})), done()],
+                // The summary comes back empty
+                vec![text("  "), done()],
+                vec![tool_call("call_3", "echo", json!({"text": "c"})), done()],
+                vec![text("Done."), done()],
+            ],
+        );
+        let budget = ContextConfig {
+            budget_tokens: Some(1),
+            ..Default::default()
+        };
+        runner.compactor = Compactor::new(&budget, "scripted");
+        let mut events = RecordingEvents::default();
+
+        runner
+            .run_turn("echo".to_string(), &mut events, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        let compactions: Vec<&String> = events
+            .events
+            .iter()
+            .filter(|e| e.starts_with("compact"))
+            .collect();
+        assert_eq!(
+            compactions,
+            vec!["compaction failed:Compaction returned an empty summary"]
+        );
+        assert_eq!(requests.lock().unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn runs_tools_and_feeds_results_back() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, requests) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    text("Let me check."),
+                    tool_call("call_1", "echo", json!({"text": "ping"})),
+                    tool_call("call_2", "missing", json!({})),
+                    done(),
+                ],
+                vec![text("Done."), done()],
+            ],
+        );
+        let mut events = RecordingEvents::default();
+
+        runner
+            .run_turn("go".to_string(), &mut events, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            events.events,
+            vec![
+                "text:Let me check.",
+                "call:echo",
+                "result:echo:echoed ping",
+                "call:missing",
+                "error:missing",
+                "text:Done.",
+            ]
+        );
+
+        // The second request carries the assistant turn and both tool results
+        let requests = requests.lock().unwrap();
+        let second = &requests[1];
+        assert_eq!(second.len(), 5);
+        assert_eq!(second[2].role, "assistant");
+        assert_eq!(second[2].tool_calls.len(), 2);
+        assert_eq!(second[3].tool_call_id.as_deref(), Some("call_1"));
+        assert!(second[3].content.starts_with("echoed ping"));
+        assert_eq!(second[4].tool_call_id.as_deref(), Some("call_2"));
+        assert!(second[4].content.contains("Tool not found: missing"));
+
+        let results = &runner.session().messages[1].tool_results;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].status, "error");
+    }
+
+    #[tokio::test]
+    async fn finish_tool_ends_turn() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, requests) = runner_in(
+            &workspace,
+            vec![vec![
+                tool_call("call_1", "finish", json!({"answer": "All done"})),
+                done(),
+            ]],
+        );
+
+        runner
+            .run_turn("go".to_string(), &mut NullEvents, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(
+            runner.session().messages[1].tool_results[0].observation,
+            "All done"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_failure_is_returned() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, _) = runner_in(&workspace, Vec::new());
+        let result = runner
+            .run_turn("hi".to_string(), &mut NullEvents, &CancellationToken::new())
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn edits_a_file_end_to_end() {
+        let workspace = TempWorkspace::new();
+        workspace.write("src/main.rs", "fn main() {\n    println!(\"hi\");\n}\n");
+        let file = workspace.path_str("src/main.rs");
+
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    tool_call(
+                        "call_read",
+                        "edit_file",
+                        json!({"operation": "read_file", "file_path": file, "max_length": 0}),
+                    ),
+                    done(),
+                ],
+                vec![
+                    tool_call(
+                        "call_edit",
+                        "edit_file",
+                        json!({
+                            "operation": "replace_by_string",
+                            "file_path": file,
+                            "old_string": "println!(\"hi\");",
+                            "content": "println!(\"Hello, world!\");"
+                        }),
+                    ),
+                    tool_call(
+                        "call_new",
+                        "edit_file",
+                        json!({
+                            "operation": "create_file",
+                            "file_path": workspace.path_str("README.md"),
+                            "content": "# Demo\n"
+                        }),
+                    ),
+                    done(),
+                ],
+                vec![text("Updated the greeting."), done()],
+            ],
+        );
+        runner.set_permissions(PermissionPolicy::default().auto_approve());
+
+        runner
+            .run_turn(
+                "make it greet the world".to_string(),
+                &mut NullEvents,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        runner.save().unwrap();
+
+        assert_eq!(
+            workspace.read("src/main.rs"),
+            "fn main() {\n    println!(\"Hello, world!\");\n}\n"
+        );
+        assert_eq!(workspace.read("README.md"), "# Demo\n");
+
+        let saved = workspace.saved_session(runner.session());
+        let messages = saved[1].as_array().unwrap();
+        let roles: Vec<&str> = messages
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, vec!["user", "assistant", "assistant", "assistant"]);
+
+        let read = &messages[1]["tool_results"][0];
+        assert_eq!(read["tool_call_id"], "call_read");
+        assert_eq!(read["output"]["total_lines"], 3);
+        assert!(read["output"]["content"]
+            .as_str()
+            .unwrap()
+            .contains("2|    println!(\"hi\");"));
+
+        let edits = messages[2]["tool_results"].as_array().unwrap();
+        assert_eq!(edits.len(), 2);
+        assert!(edits.iter().all(|r| r["status"] == "success"));
+        assert_eq!(edits[0]["output"]["modified"], true);
+        assert_eq!(edits[1]["output"]["lines"], 1);
+
+        assert_eq!(messages[3]["content"], "Updated the greeting.");
+    }
+
+    #[tokio::test]
+    async fn searches_and_runs_bash_end_to_end() {
+        let workspace = TempWorkspace::new();
+        workspace.write("src/lib.rs", "// TODO: implement\npub fn add() {}\n");
+        workspace.write("src/util.rs", "pub fn util() {}\n");
+        workspace.write("notes.txt", "nothing here\n");
+
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    tool_call(
+                        "call_glob",
+                        "file_search",
+                        json!({
+                            "operation": "glob",
+                            "pattern": "src/*.rs",
+                            "path": workspace.path_str("")
+                        }),
+                    ),
+                    tool_call(
+                        "call_grep",
+                        "file_search",
+                        json!({
+                            "operation": "grep",
+                            "pattern": "TODO",
+                            "path": workspace.path_str("src")
+                        }),
+                    ),
+                    done(),
+                ],
+                vec![
+                    tool_call(
+                        "call_bash",
+                        "bash",
+                        json!({"command": "wc -l < src/lib.rs", "cwd": workspace.path_str("")}),
+                    ),
+                    done(),
+                ],
+                vec![text("One TODO in a two-line file."), done()],
+            ],
+        );
+        runner.set_permissions(PermissionPolicy::default().auto_approve());
+
+        runner
+            .run_turn(
+                "find the TODOs".to_string(),
+                &mut NullEvents,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        runner.save().unwrap();
+
+        let saved = workspace.saved_session(runner.session());
+        assert_eq!(saved[0]["title"], "test");
+        let messages = saved[1].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+
+        let search = messages[1]["tool_results"].as_array().unwrap();
+        let mut files: Vec<&str> = search[0]["output"]["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f.as_str().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                workspace.path_str("src/lib.rs"),
+                workspace.path_str("src/util.rs")
+            ]
+        );
+        let matches = search[1]["output"]["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0]["line"], 1);
+
+        let bash = &messages[2]["tool_results"][0];
+        assert_eq!(bash["status"], "success");
+        assert_eq!(bash["output"]["exit_code"], 0);
+        assert_eq!(bash["output"]["stdout"].as_str().unwrap().trim(), "2");
+    }
+
+    #[tokio::test]
+    async fn permission_policy_blocks_calls_the_model_cannot_override() {
+        let workspace = TempWorkspace::new();
+        workspace.write("keep.txt", "precious\n");
+        let cwd = workspace.path_str("");
+
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    tool_call(
+                        "call_rm",
+                        "bash",
+                        json!({"command": "rm -rf keep.txt", "cwd": cwd, "skip_approval": true}),
+                    ),
+                    tool_call(
+                        "call_touch",
+                        "bash",
+                        json!({"command": "touch made.txt", "cwd": cwd}),
+                    ),
+                    tool_call("call_echo", "echo", json!({"text": "still runs"})),
+                    done(),
+                ],
+                vec![text("Done."), done()],
+            ],
+        );
+        let config = serde_json::from_value(json!({
+            "deny": [{ "tool": "bash", "prefix": "rm" }]
+        }))
+        .unwrap();
+        runner.set_permissions(PermissionPolicy::new(config).unwrap());
+
+        // NullEvents declines every confirmation
+        runner
+            .run_turn(
+                "clean up".to_string(),
+                &mut NullEvents,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(workspace.read("keep.txt"), "precious\n");
+        assert!(!workspace.join("made.txt").exists());
+
+        let results = &runner.session().messages[1].tool_results;
+        let statuses: Vec<&str> = results.iter().map(|r| r.status.as_str()).collect();
+        assert_eq!(statuses, vec!["denied", "cancelled", "success"]);
+        assert_eq!(results[0].output["reason"], "rule bash prefix 'rm'");
+    }
+
+    #[tokio::test]
+    async fn tools_resolve_paths_against_the_session_directory() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    tool_call(
+                        "call_create",
+                        "edit_file",
+                        json!({
+                            "operation": "create_file",
+                            "file_path": "notes/todo.md",
+                            "content": "- write tests\n"
+                        }),
+                    ),
+                    tool_call(
+                        "call_escape",
+                        "edit_file",
+                        json!({
+                            "operation": "create_file",
+                            "file_path": "../escape.md",
+                            "content": "out\n"
+                        }),
+                    ),
+                    tool_call(
+                        "call_glob",
+                        "file_search",
+                        json!({"operation": "glob", "pattern": "notes/*.md"}),
+                    ),
+                    tool_call("call_bash", "bash", json!({"command": "cat notes/todo.md"})),
+                    done(),
+                ],
+                vec![text("Done."), done()],
+            ],
+        );
+        runner.set_permissions(PermissionPolicy::default().auto_approve());
+        runner.set_strict_workspace(true);
+
+        runner
+            .run_turn(
+                "take notes".to_string(),
+                &mut NullEvents,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(workspace.read("notes/todo.md"), "- write tests\n");
+        assert!(!workspace
+            .path()
+            .parent()
+            .unwrap()
+            .join("escape.md")
+            .exists());
+
+        let results = &runner.session().messages[1].tool_results;
+        assert_eq!(results[0].status, "success");
+        assert_eq!(results[1].status, "error");
+        assert!(results[1].observation.contains("outside the workspace"));
+        assert_eq!(results[2].output["files"], json!(["notes/todo.md"]));
+        assert_eq!(results[3].output["stdout"], "- write tests\n");
+    }
+
+    #[tokio::test]
+    async fn edits_are_checkpointed_per_turn() {
+        let workspace = TempWorkspace::new();
+        workspace.write("lib.rs", "fn old() {}\n");
+        let edit = |id: &str, from: &str, to: &str| {
+            tool_call(
+                id,
+                "edit_file",
+                json!({
+                    "operation": "replace_by_string",
+                    "file_path": "lib.rs",
+                    "old_string": from,
+                    "content": to
+                }),
+            )
+        };
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![edit("call_1", "old", "renamed"), done()],
+                vec![text("Renamed."), done()],
+                vec![
+                    edit("call_2", "renamed", "again"),
+                    tool_call(
+                        "call_3",
+                        "edit_file",
+                        json!({"operation": "create_file", "file_path": "new.rs", "content": ""}),
+                    ),
+                    done(),
+                ],
+                vec![text("Done."), done()],
+            ],
+        );
+        runner.set_permissions(PermissionPolicy::default().auto_approve());
+        runner.set_checkpoints(CheckpointStore::new(workspace.join(".checkpoints")));
+
+        for prompt in ["rename it", "rename it again"] {
+            runner
+                .run_turn(
+                    prompt.to_string(),
+                    &mut NullEvents,
+                    &CancellationToken::new(),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(workspace.read("lib.rs"), "fn again() {}\n");
+
+        let store = runner.checkpoints().unwrap();
+        let prompts: Vec<String> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.prompt)
+            .collect();
+        assert_eq!(prompts, vec!["rename it", "rename it again"]);
+
+        store.undo().unwrap();
+        assert_eq!(workspace.read("lib.rs"), "fn renamed() {}\n");
+        assert!(!workspace.join("new.rs").exists());
+        store.undo().unwrap();
+        assert_eq!(workspace.read("lib.rs"), "fn old() {}\n");
+    }
+
+    #[tokio::test]
+    async fn read_only_calls_run_between_writes_in_order() {
+        let workspace = TempWorkspace::new();
+        workspace.write("a.txt", "alpha\n");
+        workspace.write("b.txt", "beta\n");
+        let read = |id: &str, file: &str| {
+            tool_call(
+                id,
+                "edit_file",
+                json!({"operation": "read_file", "file_path": workspace.path_str(file)}),
+            )
+        };
+
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    read("read_a", "a.txt"),
+                    read("read_b", "b.txt"),
+                    tool_call(
+                        "create_c",
+                        "edit_file",
+                        json!({
+                            "operation": "create_file",
+                            "file_path": workspace.path_str("c.txt"),
+                            "content": "gamma\n"
+                        }),
+                    ),
+                    read("read_c", "c.txt"),
+                    done(),
+                ],
+                vec![text("Read them all."), done()],
+            ],
+        );
+        runner.set_permissions(PermissionPolicy::default().auto_approve());
+
+        runner
+            .run_turn(
+                "read".to_string(),
+                &mut NullEvents,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        let results = &runner.session().messages[1].tool_results;
+        let ids: Vec<&str> = results.iter().map(|r| r.tool_call_id.as_str()).collect();
+        assert_eq!(ids, vec!["read_a", "read_b", "create_c", "read_c"]);
+        assert!(results.iter().all(|r| r.status == "success"));
+        assert_eq!(results[1].output["content"], "1|beta");
+        assert_eq!(results[3].output["content"], "1|gamma");
+    }
+
+    /// Answers every approval prompt with `answer` and records the previews shown
+    struct ApprovingEvents {
+        answer: Approval,
+        prompts: Vec<(String, Option<String>)>,
+    }
+
+    impl AgentEvents for ApprovingEvents {
+        fn approve_tool_call(
+            &mut self,
+            call: &ToolCall,
+            _reason: &str,
+            preview: Option<&str>,
+        ) -> Approval {
+            self.prompts
+                .push((call.id.clone(), preview.map(str::to_string)));
+            self.answer
+        }
+    }
+
+    #[tokio::test]
+    async fn edits_are_previewed_as_diffs_before_approval() {
+        let workspace = TempWorkspace::new();
+        workspace.write("lib.rs", "fn old() {}\n");
+        let edit = |id: &str, from: &str, to: &str| {
+            tool_call(
+                id,
+                "edit_file",
+                json!({
+                    "operation": "replace_by_string",
+                    "file_path": "lib.rs",
+                    "old_string": from,
+                    "content": to
+                }),
+            )
+        };
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![
+                vec![edit("call_1", "old", "new"), done()],
+                vec![edit("call_2", "new", "newer"), done()],
+                vec![text("Renamed twice."), done()],
+                vec![
+                    tool_call(
+                        "call_3",
+                        "edit_file",
+                        json!({"operation": "create_file", "file_path": "b.rs", "content": "b\n"}),
+                    ),
+                    done(),
+                ],
+                vec![text("Gave up."), done()],
+            ],
+        );
+
+        let mut events = ApprovingEvents {
+            answer: Approval::AlwaysAllowFile,
+            prompts: Vec::new(),
+        };
+        runner
+            .run_turn("rename".to_string(), &mut events, &CancellationToken::new())
+            .await
+            .unwrap();
+        events.answer = Approval::Reject;
+        runner
+            .run_turn("add b".to_string(), &mut events, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        // The second edit of lib.rs was not asked about
+        let diff = "--- a/lib.rs\n+++ b/lib.rs\n@@ -1,1 +1,1 @@\n-fn old() {}\n+fn new() {}\n";
+        assert_eq!(
+            events.prompts,
+            vec![
+                ("call_1".to_string(), Some(diff.to_string())),
+                (
+                    "call_3".to_string(),
+                    Some("--- /dev/null\n+++ b/b.rs\n@@ -0,0 +1,1 @@\n+b\n".to_string())
+                ),
+            ]
+        );
+        assert_eq!(workspace.read("lib.rs"), "fn newer() {}\n");
+        assert!(!workspace.join("b.rs").exists());
+
+        let messages = &runner.session().messages;
+        assert_eq!(messages[1].tool_results[0].output["diff"], diff);
+        assert_eq!(messages[5].tool_results[0].status, "cancelled");
+    }
+
+    /// Sleeps far longer than any test should take
+    struct StuckTool;
+
+    #[async_trait]
+    impl Tool for StuckTool {
+        fn name(&self) -> &str {
+            "stuck"
+        }
+
+        fn description(&self) -> &str {
+            "Never finishes"
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            json!({"type": "object"})
+        }
+
+        async fn execute(
+            &self,
+            _input: serde_json::Value,
+            _ctx: &ToolContext,
+        ) -> Result<ToolOutput> {
+            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            unreachable!()
+        }
+    }
+
+    fn cancel_after(ms: u64) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+            token.cancel();
+        });
+        cancel
+    }
+
+    #[tokio::test]
+    async fn interrupting_a_stream_keeps_partial_text() {
+        let workspace = TempWorkspace::new();
+        // No finished chunk: the stream stalls after the first fragment
+        let (mut runner, _) = runner_in(&workspace, vec![vec![text("Partial ans")]]);
+
+        let outcome = runner
+            .run_turn("hi".to_string(), &mut NullEvents, &cancel_after(50))
+            .await
+            .unwrap();
+        runner.save().unwrap();
+
+        assert_eq!(outcome, TurnOutcome::Interrupted);
+        let saved = workspace.saved_session(runner.session());
+        let messages = saved[1].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[1]["content"],
+            "Partial ans\n\n[Interrupted by user]"
+        );
+    }
+
+    #[tokio::test]
+    async fn interrupting_tools_records_cancelled_results() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, requests) = runner_in(
+            &workspace,
+            vec![
+                vec![
+                    tool_call("call_1", "echo", json!({"text": "first"})),
+                    tool_call("call_2", "stuck", json!({})),
+                    tool_call("call_3", "echo", json!({"text": "never"})),
+                    done(),
+                ],
+                vec![text("Okay."), done()],
+            ],
+        );
+        runner.tools.register(Box::new(StuckTool));
+
+        let started = std::time::Instant::now();
+        let outcome = runner
+            .run_turn("go".to_string(), &mut NullEvents, &cancel_after(50))
+            .await
+            .unwrap();
+        assert_eq!(outcome, TurnOutcome::Interrupted);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+        let statuses: Vec<&str> = runner.session().messages[1]
+            .tool_results
+            .iter()
+            .map(|r| r.status.as_str())
+            .collect();
+        assert_eq!(statuses, vec!["success", "cancelled", "cancelled"]);
+
+        // The next turn sends a complete history: every call has a result
+        runner
+            .run_turn(
+                "continue".to_string(),
+                &mut NullEvents,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        let tool_results: Vec<&str> = requests[1]
+            .iter()
+            .filter_map(|m| m.tool_call_id.as_deref())
+            .collect();
+        assert_eq!(tool_results, vec!["call_1", "call_2", "call_3"]);
+        assert!(requests[1][4].content.starts_with("Interrupted by user"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn interrupting_bash_kills_the_child() {
+        let workspace = TempWorkspace::new();
+        let (mut runner, _) = runner_in(
+            &workspace,
+            vec![vec![
+                tool_call(
+                    "call_1",
+                    "bash",
+                    json!({"command": "echo $$ > pid; exec sleep 60", "cwd": workspace.path_str("")}),
+                ),
+                done(),
+            ]],
+        );
+        runner.set_permissions(PermissionPolicy::default().auto_approve());
+
+        let outcome = runner
+            .run_turn("sleep".to_string(), &mut NullEvents, &cancel_after(300))
+            .await
+            .unwrap();
+        assert_eq!(outcome, TurnOutcome::Interrupted);
+
+        let pid = workspace.read("pid").trim().to_string();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        // Gone, or a zombie waiting to be reaped
+        let state = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        assert!(
+            state.is_empty() || state.contains(") Z"),
+            "child still running: {}",
+            state
+        );
+    }
+}
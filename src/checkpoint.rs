@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MANIFEST: &str = "manifest.json";
+
+/// A file as it was before the first edit in a turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// Name of the saved copy in the checkpoint directory; None if the file did not exist
+    pub blob: Option<String>,
+}
+
+/// Everything one user turn changed through `edit_file`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub id: usize,
+    pub created_at: DateTime<Utc>,
+    /// The user message that started the turn
+    pub prompt: String,
+    pub files: Vec<FileSnapshot>,
+}
+
+#[derive(Debug, Default)]
+struct TurnState {
+    prompt: String,
+    /// Created on the first edit of the turn, so turns without edits leave no checkpoint
+    checkpoint: Option<Checkpoint>,
+}
+
+/// Snapshots files before the agent edits them, one numbered checkpoint per user turn,
+/// stored under `~/.codeagent/checkpoints/<session id>/<n>/`. Restoring a checkpoint
+/// rolls the files back to how they were before that turn.
+#[derive(Debug)]
+pub struct CheckpointStore {
+    dir: PathBuf,
+    turn: Mutex<TurnState>,
+}
+
+impl CheckpointStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            turn: Mutex::new(TurnState::default()),
+        }
+    }
+
+    /// Start collecting snapshots for a new user turn
+    pub fn begin_turn(&self, prompt: &str) {
+        *self.turn.lock().unwrap() = TurnState {
+            prompt: prompt.lines().next().unwrap_or_default().to_string(),
+            checkpoint: None,
+        };
+    }
+
+    /// Save `path` unless it was already saved this turn. Call before writing to it.
+    pub fn snapshot(&self, path: &Path) -> Result<()> {
+        let mut turn = self.turn.lock().unwrap();
+        if turn
+            .checkpoint
+            .as_ref()
+            .is_some_and(|c| c.files.iter().any(|f| f.path == path))
+        {
+            return Ok(());
+        }
+
+        if turn.checkpoint.is_none() {
+            turn.checkpoint = Some(Checkpoint {
+                id: self.list()?.last().map_or(1, |c| c.id + 1),
+                created_at: Utc::now(),
+                prompt: turn.prompt.clone(),
+                files: Vec::new(),
+            });
+        }
+        let checkpoint = turn.checkpoint.as_mut().expect("created above");
+        let dir = self.dir.join(checkpoint.id.to_string());
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create checkpoint '{}'", dir.display()))?;
+
+        let blob = if path.is_file() {
+            let name = checkpoint.files.len().to_string();
+            fs::copy(path, dir.join(&name))
+                .with_context(|| format!("Failed to snapshot '{}'", path.display()))?;
+            Some(name)
+        } else {
+            None
+        };
+        checkpoint.files.push(FileSnapshot {
+            path: path.to_path_buf(),
+            blob,
+        });
+        fs::write(
+            dir.join(MANIFEST),
+            serde_json::to_string_pretty(checkpoint)?,
+        )?;
+        Ok(())
+    }
+
+    /// All checkpoints, oldest first
+    pub fn list(&self) -> Result<Vec<Checkpoint>> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(Vec::new());
+        };
+        let mut checkpoints = Vec::new();
+        for entry in entries {
+            let manifest = entry?.path().join(MANIFEST);
+            if let Ok(data) = fs::read_to_string(&manifest) {
+                let checkpoint: Checkpoint = serde_json::from_str(&data)
+                    .with_context(|| format!("Invalid checkpoint '{}'", manifest.display()))?;
+                checkpoints.push(checkpoint);
+            }
+        }
+        checkpoints.sort_by_key(|c| c.id);
+        Ok(checkpoints)
+    }
+
+    /// Roll files back to before checkpoint `id`, undoing it and every later one.
+    /// The undone checkpoints are removed. Returns the files that were restored.
+    pub fn restore(&self, id: usize) -> Result<Vec<PathBuf>> {
+        let later: Vec<Checkpoint> = self.list()?.into_iter().filter(|c| c.id >= id).collect();
+        if !later.iter().any(|c| c.id == id) {
+            anyhow::bail!("No checkpoint {}", id);
+        }
+        // The current turn's checkpoint may be among them
+        self.turn.lock().unwrap().checkpoint = None;
+
+        let mut restored = Vec::new();
+        for checkpoint in later.iter().rev() {
+            let dir = self.dir.join(checkpoint.id.to_string());
+            for file in &checkpoint.files {
+                match &file.blob {
+                    Some(blob) => {
+                        if let Some(parent) = file.path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::copy(dir.join(blob), &file.path).with_context(|| {
+                            format!("Failed to restore '{}'", file.path.display())
+                        })?;
+                    }
+                    None if file.path.exists() => fs::remove_file(&file.path)
+                        .with_context(|| format!("Failed to remove '{}'", file.path.display()))?,
+                    None => {}
+                }
+                if !restored.contains(&file.path) {
+                    restored.push(file.path.clone());
+                }
+            }
+            fs::remove_dir_all(&dir)?;
+        }
+        Ok(restored)
+    }
+
+    /// Restore the most recent checkpoint. Returns its id and the restored files,
+    /// or None if there is nothing to undo.
+    pub fn undo(&self) -> Result<Option<(usize, Vec<PathBuf>)>> {
+        match self.list()?.last() {
+            Some(last) => Ok(Some((last.id, self.restore(last.id)?))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    #[test]
+    fn restores_files_turn_by_turn() {
+        let workspace = TempWorkspace::new();
+        let store = CheckpointStore::new(workspace.join(".checkpoints"));
+        let (a, b) = (workspace.join("a.txt"), workspace.join("new/b.txt"));
+        workspace.write("a.txt", "original\n");
+
+        store.begin_turn("first\nmore detail");
+        store.snapshot(&a).unwrap();
+        workspace.write("a.txt", "first edit\n");
+        store.snapshot(&a).unwrap();
+        workspace.write("a.txt", "second edit in the same turn\n");
+
+        store.begin_turn("no edits");
+
+        store.begin_turn("third");
+        store.snapshot(&a).unwrap();
+        store.snapshot(&b).unwrap();
+        workspace.write("a.txt", "third edit\n");
+        workspace.write("new/b.txt", "created\n");
+
+        let checkpoints = store.list().unwrap();
+        let summary: Vec<(usize, &str, usize)> = checkpoints
+            .iter()
+            .map(|c| (c.id, c.prompt.as_str(), c.files.len()))
+            .collect();
+        assert_eq!(summary, vec![(1, "first", 1), (2, "third", 2)]);
+
+        let (id, files) = store.undo().unwrap().unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(files, vec![a.clone(), b.clone()]);
+        assert_eq!(workspace.read("a.txt"), "second edit in the same turn\n");
+        assert!(!b.exists());
+
+        store.begin_turn("fourth");
+        store.snapshot(&a).unwrap();
+        workspace.write("a.txt", "fourth edit\n");
+        assert_eq!(store.list().unwrap().last().unwrap().id, 2);
+
+        // Restoring an earlier checkpoint undoes everything after it too
+        store.restore(1).unwrap();
+        assert_eq!(workspace.read("a.txt"), "original\n");
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.undo().unwrap().is_none());
+        assert!(store.restore(7).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::provider::{LLMProvider, Message};
+use crate::session::Session;
+
+/// Context-window settings (the `context` section of the config file)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    /// Compact automatically when the estimated prompt size crosses the budget
+    pub auto_compact: bool,
+    /// Token budget for the prompt (default: 75% of the model's context window)
+    pub budget_tokens: Option<usize>,
+    /// Number of most recent user turns kept verbatim when compacting
+    pub keep_recent_turns: usize,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            auto_compact: true,
+            budget_tokens: None,
+            keep_recent_turns: 2,
+        }
+    }
+}
+
+/// Rough token estimate from character counts. Good enough for budgeting without
+/// shipping a tokenizer per provider.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenEstimator {
+    chars_per_token: f64,
+}
+
+impl TokenEstimator {
+    pub fn for_model(model: &str) -> Self {
+        // OpenAI's o200k tokenizer produces noticeably fewer tokens for code than
+        // Claude's and most local models' tokenizers
+        let chars_per_token = if model.starts_with("gpt-4o") || model.starts_with("gpt-4.1") {
+            4.0
+        } else {
+            3.5
+        };
+        Self { chars_per_token }
+    }
+
+    pub fn estimate_text(&self, text: &str) -> usize {
+        (text.chars().count() as f64 / self.chars_per_token).ceil() as usize
+    }
+
+    pub fn estimate_messages(&self, messages: &[Message]) -> usize {
+        messages
+            .iter()
+            .map(|m| {
+                // Per-message overhead for role and framing
+                4 + self.estimate_text(&m.content)
+                    + m.tool_calls
+                        .iter()
+                        .map(|tc| {
+                            self.estimate_text(&tc.name)
+                                + self.estimate_text(&tc.arguments.to_string())
+                        })
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+/// Context window size for known model families
+pub fn context_window(model: &str) -> usize {
+    if model.starts_with("claude") {
+        200_000
+    } else if model.starts_with("gpt-4.1") {
+        1_000_000
+    } else if model.starts_with("gpt-4o") || model.starts_with("o3") || model.starts_with("o4") {
+        128_000
+    } else {
+        // Unknown and local models: stay conservative
+        32_000
+    }
+}
+
+const SUMMARY_PROMPT: &str = "You are compacting the history of a coding session so it fits in the context window. Summarize the conversation below for the assistant that will continue it. Keep: the user's goals and requirements, decisions made, files created or modified (with paths), important code facts, commands run and their outcomes, errors still unresolved, and what was being worked on last. Omit pleasantries and raw tool output that is no longer needed. Reply with the summary only.";
+
+/// Maximum characters of a single message kept in the transcript sent for summarization
+const MAX_TRANSCRIPT_MESSAGE_CHARS: usize = 4000;
+
+pub struct Compactor {
+    estimator: TokenEstimator,
+    budget_tokens: usize,
+    keep_recent_turns: usize,
+    auto_compact: bool,
+}
+
+impl Compactor {
+    pub fn new(config: &ContextConfig, model: &str) -> Self {
+        Self {
+            estimator: TokenEstimator::for_model(model),
+            budget_tokens: config
+                .budget_tokens
+                .unwrap_or_else(|| context_window(model) * 3 / 4),
+            keep_recent_turns: config.keep_recent_turns,
+            auto_compact: config.auto_compact,
+        }
+    }
+
+    pub fn budget_tokens(&self) -> usize {
+        self.budget_tokens
+    }
+
+    /// Estimated prompt size: messages plus the tool definitions sent with every request
+    pub fn estimate(&self, messages: &[Message], tools: &[serde_json::Value]) -> usize {
+        self.estimator.estimate_messages(messages)
+            + tools
+                .iter()
+                .map(|t| self.estimator.estimate_text(&t.to_string()))
+                .sum::<usize>()
+    }
+
+    pub fn should_compact(&self, messages: &[Message], tools: &[serde_json::Value]) -> bool {
+        self.auto_compact && self.estimate(messages, tools) > self.budget_tokens
+    }
+
+    /// Summarize everything but the most recent turns and record the summary in the
+    /// session. Returns the number of messages summarized (0 if there was nothing to do).
+    pub async fn compact(
+        &self,
+        provider: &dyn LLMProvider,
+        session: &mut Session,
+    ) -> Result<usize> {
+        let split = session.compaction_split(self.keep_recent_turns);
+        self.summarize(provider, session, split).await
+    }
+
+    /// Where `compact_to_fit` would split the session, or None if there is nothing
+    /// to summarize
+    pub fn split_to_fit(&self, session: &Session) -> Option<usize> {
+        session
+            .compaction_split(self.keep_recent_turns)
+            .or_else(|| session.compaction_split(1))
+            .or_else(|| session.latest_step_split())
+    }
+
+    /// Like `compact`, for a prompt over budget: when there are too few turns to
+    /// summarize whole ones, keep only the latest turn, and if that is all there is,
+    /// only the latest step of it
+    pub async fn compact_to_fit(
+        &self,
+        provider: &dyn LLMProvider,
+        session: &mut Session,
+    ) -> Result<usize> {
+        let split = self.split_to_fit(session);
+        self.summarize(provider, session, split).await
+    }
+
+    async fn summarize(
+        &self,
+        provider: &dyn LLMProvider,
+        session: &mut Session,
+        split: Option<usize>,
+    ) -> Result<usize> {
+        let Some(split) = split else {
+            return Ok(0);
+        };
+
+        let older = session.history_before(split);
+        let request = vec![
+            Message {
+                role: "system".to_string(),
+                content: SUMMARY_PROMPT.to_string(),
+                tool_call_id: None,
+                tool_calls: Vec::new(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: render_transcript(&older),
+                tool_call_id: None,
+                tool_calls: Vec::new(),
+            },
+        ];
+
+        let response = provider.chat_completion(request, None).await?;
+        let usage = response.usage;
+        let summary = response
+            .content
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Compaction returned an empty summary"))?;
+
+        session.add_summary(split, summary, usage);
+        Ok(older.len())
+    }
+}
+
+/// Flatten messages into plain text so the summarization request carries no tool-call
+/// structure that the provider would need to pair up
+fn render_transcript(messages: &[Message]) -> String {
+    let mut transcript = String::new();
+
+    for msg in messages {
+        let label = match (msg.role.as_str(), &msg.tool_call_id) {
+            (_, Some(_)) => "Tool result",
+            ("assistant", None) => "Assistant",
+            _ => "User",
+        };
+
+        if !msg.content.is_empty() {
+            transcript.push_str(&format!(
+                "{}: {}\n\n",
+                label,
+                truncate(&msg.content, MAX_TRANSCRIPT_MESSAGE_CHARS)
+            ));
+        }
+        for tc in &msg.tool_calls {
+            transcript.push_str(&format!(
+                "Assistant called {} with {}\n\n",
+                tc.name,
+                truncate(&tc.arguments.to_string(), MAX_TRANSCRIPT_MESSAGE_CHARS)
+            ));
+        }
+    }
+
+    transcript
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(max_chars).collect();
+    format!("{}... [truncated]", head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ToolCall;
+    use crate::provider::{StreamChunk, Usage};
+    use crate::testing::{done, text, ScriptedProvider};
+    use serde_json::json;
+
+    const SUMMARY_USAGE: Usage = Usage {
+        input_tokens: 900,
+        output_tokens: 60,
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: 0,
+    };
+
+    fn summary_provider(count: usize) -> ScriptedProvider {
+        let usage = StreamChunk {
+            usage: Some(SUMMARY_USAGE),
+            ..done()
+        };
+        ScriptedProvider::new(vec![
+            vec![
+                text("User wants a CLI; main.rs was created."),
+                usage
+            ];
+            count
+        ])
+    }
+
+    fn session_with_turns(turns: usize) -> Session {
+        let mut session = Session::new(
+            "test".to_string(),
+            ".".to_string(),
+            std::env::temp_dir(),
+            None,
+            None,
+        );
+        for i in 0..turns {
+            session.add_user_message(format!("request {}", i));
+            session.add_assistant_message(
+                None,
+                vec![ToolCall {
+                    id: format!("call_{}", i),
+                    name: "bash".to_string(),
+                    arguments: json!({"command": "ls"}),
+                }],
+                None,
+            );
+            session.add_tool_result(
+                format!("call_{}", i),
+                json!({"stdout": "main.rs"}),
+                "Command executed successfully".to_string(),
+                "success".to_string(),
+            );
+            session.add_assistant_message(Some(format!("answer {}", i)), Vec::new(), None);
+        }
+        session
+    }
+
+    #[tokio::test]
+    async fn replaces_older_turns_with_summary() {
+        let provider = summary_provider(2);
+        let requests = provider.requests();
+        let mut session = session_with_turns(4);
+        let compactor = Compactor::new(&ContextConfig::default(), "test-model");
+
+        let summarized = compactor.compact(&provider, &mut session).await.unwrap();
+        assert!(summarized > 0);
+
+        let history = session.get_conversation_history();
+        assert!(history[0]
+            .content
+            .starts_with("Summary of the earlier conversation"));
+        assert_eq!(history[1].content, "request 2");
+        assert!(history.iter().all(|m| m.content != "request 1"));
+
+        // The summarization request is part of the session's cost
+        assert_eq!(session.total_usage(), SUMMARY_USAGE);
+
+        // Nothing left to summarize beyond the kept turns
+        assert_eq!(compactor.compact(&provider, &mut session).await.unwrap(), 0);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0][1].content.contains("User: request 0"));
+        assert!(requests[0][1].content.contains("Assistant called bash"));
+        assert!(!requests[0][1].content.contains("request 2"));
+    }
+
+    #[tokio::test]
+    async fn later_compaction_includes_previous_summary() {
+        let provider = summary_provider(2);
+        let requests = provider.requests();
+        let mut session = session_with_turns(3);
+        let compactor = Compactor::new(&ContextConfig::default(), "test-model");
+        compactor.compact(&provider, &mut session).await.unwrap();
+
+        session.add_user_message("request 3".to_string());
+        session.add_user_message("request 4".to_string());
+        compactor.compact(&provider, &mut session).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[1][1]
+            .content
+            .contains("Summary of the earlier conversation"));
+        assert_eq!(session.get_conversation_history().len(), 3);
+    }
+
+    #[test]
+    fn budget_defaults_to_fraction_of_context_window() {
+        let compactor = Compactor::new(&ContextConfig::default(), "claude-sonnet-4-5");
+        assert_eq!(compactor.budget_tokens(), 150_000);
+
+        let small = ContextConfig {
+            budget_tokens: Some(10),
+            ..Default::default()
+        };
+        let compactor = Compactor::new(&small, "claude-sonnet-4-5");
+        let messages = session_with_turns(1).get_conversation_history();
+        assert!(compactor.should_compact(&messages, &[]));
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::compaction::ContextConfig;
+use crate::permissions::PermissionConfig;
+use crate::pricing::ModelPrice;
+use crate::provider::retry::RetryConfig;
+use crate::repo_map::RepoMapConfig;
+use crate::tools::bash::BashConfig;
+use crate::tools::context::WorkspaceConfig;
+use crate::tools::edit_file::EditConfig;
+
+/// User configuration, read from `~/.codeagent/config.json`. Every section is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    /// Per-model prices (USD per million tokens), keyed by model name or prefix
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+
+    /// Retry/backoff settings for LLM requests
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Context-window budget and automatic compaction
+    #[serde(default)]
+    pub context: ContextConfig,
+
+    /// Timeout and output limits for the bash tool
+    #[serde(default)]
+    pub bash: BashConfig,
+
+    /// Safety checks for the edit tool
+    #[serde(default)]
+    pub edit: EditConfig,
+
+    /// Which tool calls run, need confirmation or are refused
+    #[serde(default)]
+    pub permissions: PermissionConfig,
+
+    /// How tool paths are confined to the session directory
+    #[serde(default)]
+    pub workspace: WorkspaceConfig,
+
+    /// Ranked outline of the workspace appended to the system prompt
+    #[serde(default)]
+    pub repo_map: RepoMapConfig,
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults if it does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file '{}'", path.display()))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse config file '{}'", path.display()))
+    }
+}
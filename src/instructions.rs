@@ -0,0 +1,159 @@
+use anyhow::Context;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Instruction files looked for in each directory, in the order they are merged
+const FILE_NAMES: [&str; 3] = ["AGENTS.md", "CODEAGENT.md", ".codeagent/instructions.md"];
+
+/// The user-global instruction file, relative to `~/.codeagent`
+const GLOBAL_FILE: &str = "instructions.md";
+
+/// A loaded instruction file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionFile {
+    pub path: PathBuf,
+    /// How the file is shown in the banner and the prompt
+    pub name: String,
+    pub content: String,
+}
+
+/// Instruction files for `workspace`, least specific first: the global file in
+/// `codeagent_dir`, then the files of each directory from the git root (or the
+/// workspace itself outside a repository) down to the workspace. Empty files are
+/// skipped; files that can't be read are skipped and returned as errors.
+pub fn discover(
+    workspace: &Path,
+    codeagent_dir: &Path,
+) -> (Vec<InstructionFile>, Vec<anyhow::Error>) {
+    let workspace = workspace
+        .canonicalize()
+        .unwrap_or_else(|_| workspace.to_path_buf());
+    let mut dirs: Vec<&Path> = workspace.ancestors().collect();
+    match dirs.iter().position(|dir| dir.join(".git").exists()) {
+        Some(root) => dirs.truncate(root + 1),
+        None => dirs.truncate(1),
+    }
+    let root = dirs.last().copied().unwrap_or(&workspace);
+
+    let global = codeagent_dir.join(GLOBAL_FILE);
+    let mut candidates = vec![(
+        global.canonicalize().unwrap_or(global),
+        format!("~/.codeagent/{}", GLOBAL_FILE),
+    )];
+    for dir in dirs.iter().rev() {
+        for name in FILE_NAMES {
+            let path = dir.join(name);
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            candidates.push((path.clone(), relative.display().to_string()));
+        }
+    }
+
+    let mut files: Vec<InstructionFile> = Vec::new();
+    let mut errors = Vec::new();
+    for (path, name) in candidates {
+        // The global file is also a project file when the workspace is the home directory
+        if !path.is_file() || files.iter().any(|f| f.path == path) {
+            continue;
+        }
+        let content = match fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read instruction file '{}'", path.display()))
+        {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if content.trim().is_empty() {
+            continue;
+        }
+        files.push(InstructionFile {
+            path,
+            name,
+            content,
+        });
+    }
+    (files, errors)
+}
+
+/// The system prompt section for `files`, or None if there are none
+pub fn prompt_section(files: &[InstructionFile]) -> Option<String> {
+    if files.is_empty() {
+        return None;
+    }
+    let mut section = String::from(
+        "# Instructions from the user's files\n\nFollow these instructions. They are listed \
+         from least to most specific; when they conflict, later files take precedence.",
+    );
+    for file in files {
+        section.push_str(&format!("\n\n## {}\n\n{}", file.name, file.content.trim()));
+    }
+    Some(section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    fn names(files: &[InstructionFile]) -> Vec<&str> {
+        files.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn merges_global_then_git_root_down_to_the_workspace() {
+        let workspace = TempWorkspace::new();
+        workspace.write("home/instructions.md", "global\n");
+        workspace.write("AGENTS.md", "above the repository\n");
+        workspace.write("repo/.git/HEAD", "ref: refs/heads/main\n");
+        workspace.write("repo/AGENTS.md", "root agents\n");
+        workspace.write("repo/.codeagent/instructions.md", "root codeagent\n");
+        workspace.write("repo/crates/app/CODEAGENT.md", "app\n");
+        workspace.write("repo/crates/app/AGENTS.md", "   \n");
+        workspace.write("repo/crates/app/src/main.rs", "");
+
+        let (files, errors) = discover(&workspace.join("repo/crates/app"), &workspace.join("home"));
+        assert!(errors.is_empty());
+        assert_eq!(
+            names(&files),
+            vec![
+                "~/.codeagent/instructions.md",
+                "AGENTS.md",
+                ".codeagent/instructions.md",
+                "crates/app/CODEAGENT.md",
+            ]
+        );
+
+        let section = prompt_section(&files).unwrap();
+        let order: Vec<usize> = ["global", "root agents", "root codeagent", "app"]
+            .iter()
+            .map(|text| section.find(text).unwrap())
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn outside_a_repository_only_the_workspace_is_searched() {
+        let workspace = TempWorkspace::new();
+        workspace.write("AGENTS.md", "parent\n");
+        workspace.write("project/AGENTS.md", "project\n");
+
+        let (files, _) = discover(&workspace.join("project"), &workspace.join("home"));
+        assert_eq!(names(&files), vec!["AGENTS.md"]);
+        assert_eq!(files[0].content, "project\n");
+        assert_eq!(prompt_section(&[]), None);
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped_and_reported() {
+        let workspace = TempWorkspace::new();
+        workspace.write("CODEAGENT.md", "codeagent\n");
+        // Not UTF-8
+        std::fs::write(workspace.join("AGENTS.md"), b"caf\xe9\n").unwrap();
+
+        let (files, errors) = discover(workspace.path(), &workspace.join("home"));
+        assert_eq!(names(&files), vec!["CODEAGENT.md"]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("AGENTS.md"), "{}", errors[0]);
+    }
+}
@@ -1,33 +1,114 @@
+mod agent;
+mod checkpoint;
+mod compaction;
+mod config;
+mod instructions;
+mod permissions;
 mod persona;
+mod pricing;
 mod provider;
+mod repo_map;
 mod session;
+#[cfg(test)]
+mod testing;
 mod tools;
+mod ui;
 
+use agent::{AgentRunner, TurnOutcome};
 use anyhow::Result;
+use checkpoint::CheckpointStore;
 use clap::Parser;
 use colored::Colorize;
+use compaction::{Compactor, TokenEstimator};
+use config::Config;
 use dialoguer::{theme::ColorfulTheme, Input};
+use permissions::PermissionPolicy;
 
 use persona::{all_personas, get_persona};
-use provider::{LLMProvider, Message};
+use pricing::{format_usage, PriceTable};
+use provider::retry::RetryingProvider;
+use provider::LLMProvider;
 use session::Session;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
+use tools::background::BackgroundProcesses;
 use tools::ToolRegistry;
+use ui::ConsoleUi;
+
+/// Cancel the running turn on the first Ctrl-C and exit on the second. The returned
+/// task must be aborted once the turn is over.
+fn spawn_interrupt_handler(
+    cancel: CancellationToken,
+    background: BackgroundProcesses,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        cancel.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\n{}", "Interrupted twice, exiting.".red());
+            background.kill_all();
+            std::process::exit(130);
+        }
+    })
+}
 
-/// Write a debug log entry to the specified file with timestamp
-fn write_debug_log(log_path: &str, entry: &str) {
-    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(log_path) {
-        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let _ = writeln!(file, "[{}] {}", timestamp, entry);
+fn print_restored(id: usize, files: &[std::path::PathBuf]) {
+    println!(
+        "{}",
+        format!(
+            "Restored {} file(s) to before turn {}. The conversation is unchanged.",
+            files.len(),
+            id
+        )
+        .green()
+    );
+    for file in files {
+        println!("  {}", file.display().to_string().dimmed());
+    }
+}
+
+/// `checkpoints` lists the checkpoints; `checkpoints restore <n>` rolls back to before turn n
+fn handle_checkpoints_command(store: &CheckpointStore, input: &str) {
+    let args: Vec<&str> = input.split_whitespace().skip(1).collect();
+    match args.as_slice() {
+        [] => match store.list() {
+            Ok(checkpoints) if checkpoints.is_empty() => {
+                println!("{}", "No checkpoints yet.".dimmed())
+            }
+            Ok(checkpoints) => {
+                for checkpoint in checkpoints {
+                    println!(
+                        "  {:>3}  {}  {} file(s)  {}",
+                        checkpoint.id,
+                        checkpoint
+                            .created_at
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M"),
+                        checkpoint.files.len(),
+                        checkpoint.prompt.dimmed()
+                    );
+                }
+            }
+            Err(e) => println!("{}", format!("Failed to list checkpoints: {:#}", e).red()),
+        },
+        ["restore", id] => match id.parse() {
+            Ok(id) => match store.restore(id) {
+                Ok(files) => print_restored(id, &files),
+                Err(e) => println!("{}", format!("Restore failed: {:#}", e).red()),
+            },
+            Err(_) => println!("{}", format!("Invalid checkpoint number: {}", id).red()),
+        },
+        _ => println!("{}", "Usage: checkpoints [restore <n>]".yellow()),
     }
 }
 
 #[derive(Parser, Debug)]
 #[command(name = "codeagent")]
-#[command(about = "Interactive coding agent with OpenAI and Anthropic support", long_about = None)]
+#[command(about = "Interactive coding agent with OpenAI, Anthropic and Ollama support", long_about = None)]
 struct Args {
-    #[arg(short, long, help = "Provider to use: openai or anthropic")]
+    #[arg(short, long, help = "Provider to use: openai, anthropic or ollama")]
     provider: String,
 
     #[arg(
@@ -52,6 +133,12 @@ struct Args {
     #[arg(long, help = "Anthropic base URL (optional, overrides default)")]
     anthropic_base_url: Option<String>,
 
+    #[arg(
+        long,
+        help = "Ollama base URL (optional, default: http://localhost:11434)"
+    )]
+    ollama_base_url: Option<String>,
+
     #[arg(long, help = "System prompt override (direct text)")]
     system_prompt: Option<String>,
 
@@ -88,10 +175,22 @@ struct Args {
 
     #[arg(
         long,
-        help = "Auto-approve all bash commands (non-interactive mode only, use with caution)"
+        help = "Run tool calls the permission policy would ask about without asking (deny rules still apply)"
     )]
     auto_approve: bool,
 
+    #[arg(
+        long,
+        help = "Reject tool paths outside the working directory, including via '..' and symlinks"
+    )]
+    strict_workspace: bool,
+
+    #[arg(
+        long,
+        help = "Append a ranked map of the working directory's source files to the system prompt"
+    )]
+    repo_map: bool,
+
     #[arg(
         long,
         help = "Path to write debug log (raw model output and tool call details)"
@@ -99,6 +198,20 @@ struct Args {
     debug_log: Option<String>,
 }
 
+/// Banner lines for the instruction files and repo map added to the system prompt
+fn print_loaded_context(
+    instruction_files: &[instructions::InstructionFile],
+    repo_map: Option<&str>,
+) {
+    if !instruction_files.is_empty() {
+        let names: Vec<&str> = instruction_files.iter().map(|f| f.name.as_str()).collect();
+        println!("{}", format!("Instructions: {}", names.join(", ")).dimmed());
+    }
+    if let Some(summary) = repo_map {
+        println!("{}", summary.dimmed());
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -114,43 +227,57 @@ async fn main() -> Result<()> {
     // Detect mode: interactive (default) vs non-interactive (--input provided)
     let is_interactive = args.input.is_none();
 
-    // Get API key from args or environment
-    let api_key = args
-        .api_key
-        .or_else(|| match args.provider.as_str() {
-            "openai" => std::env::var("OPENAI_API_KEY").ok(),
-            "anthropic" => std::env::var("ANTHROPIC_API_KEY").ok(),
-            _ => None,
-        })
-        .ok_or_else(|| {
+    // Get API key from args or environment (a local Ollama server needs none)
+    let api_key = args.api_key.or_else(|| match args.provider.as_str() {
+        "openai" => std::env::var("OPENAI_API_KEY").ok(),
+        "anthropic" => std::env::var("ANTHROPIC_API_KEY").ok(),
+        _ => None,
+    });
+    let require_api_key = || {
+        api_key.clone().ok_or_else(|| {
             anyhow::anyhow!("API key required. Set via --api-key or environment variable")
-        })?;
+        })
+    };
+
+    // Setup storage
+    let codeagent_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
+        .join(".codeagent");
+    let storage_path = codeagent_dir.join("sessions");
+
+    let config = Config::load(&codeagent_dir.join("config.json"))?;
+    let price_table = PriceTable::new(config.prices.clone());
 
     // Create provider
     let provider: Box<dyn LLMProvider> = match args.provider.as_str() {
         "openai" => Box::new(provider::openai::OpenAIProvider::new(
-            api_key,
+            require_api_key()?,
             args.model,
             args.openai_base_url,
         )),
         "anthropic" => Box::new(provider::anthropic::AnthropicProvider::new(
-            api_key,
+            require_api_key()?,
             args.model,
             args.anthropic_base_url,
         )),
+        "ollama" => Box::new(provider::ollama::OllamaProvider::new(
+            args.model,
+            args.ollama_base_url,
+        )),
         _ => {
             return Err(anyhow::anyhow!(
-                "Unknown provider: {}. Use 'openai' or 'anthropic'",
+                "Unknown provider: {}. Use 'openai', 'anthropic' or 'ollama'",
                 args.provider
             ))
         }
     };
-
-    // Setup storage
-    let storage_path = dirs::home_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?
-        .join(".codeagent")
-        .join("sessions");
+    let mut provider = RetryingProvider::new(provider, config.retry.clone());
+    let retry_notices = provider.notices();
+    let compactor = Compactor::new(&config.context, provider.model());
+    let mut permissions = PermissionPolicy::new(config.permissions.clone())?;
+    if args.auto_approve {
+        permissions = permissions.auto_approve();
+    }
 
     // Resolve system prompt (priority: CLI arg > file > persona > default)
     const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful coding assistant. You have access to tools for file operations, code search, and command execution. Use them to help the user with their coding tasks.";
@@ -189,7 +316,7 @@ async fn main() -> Result<()> {
     };
 
     // Load or create session
-    let mut session = if let Some(session_id) = args.session {
+    let session = if let Some(session_id) = args.session {
         println!("{}", format!("Resuming session: {}", session_id).cyan());
         let mut session = Session::load(&session_id, storage_path)?;
 
@@ -247,14 +374,51 @@ async fn main() -> Result<()> {
         base_system_prompt
     };
 
+    // Append instruction files (AGENTS.md etc.) from the workspace and ~/.codeagent
+    let (instruction_files, instruction_errors) =
+        instructions::discover(Path::new(&session.info.directory), &codeagent_dir);
+    for e in instruction_errors {
+        eprintln!("{}", format!("Skipped instruction file: {:#}", e).yellow());
+    }
+    let system_prompt = match instructions::prompt_section(&instruction_files) {
+        Some(section) => format!("{}\n\n{}", system_prompt, section),
+        None => system_prompt,
+    };
+
+    // Append the repository map (refreshed from the on-disk cache)
+    let mut repo_map_summary = None;
+    let system_prompt = if args.repo_map || config.repo_map.enabled {
+        match repo_map::build(
+            Path::new(&session.info.directory),
+            &codeagent_dir.join("repo_maps"),
+            config.repo_map.budget_tokens,
+            &TokenEstimator::for_model(provider.model()),
+        ) {
+            Ok(map) => {
+                repo_map_summary = Some(format!(
+                    "Repo map: {} of {} files (~{} tokens)",
+                    map.shown, map.total, map.tokens
+                ));
+                format!("{}\n\n{}", system_prompt, map.text)
+            }
+            Err(e) => {
+                eprintln!("{}", format!("Failed to build repo map: {:#}", e).yellow());
+                system_prompt
+            }
+        }
+    } else {
+        system_prompt
+    };
+
     // Initialize tools
     let web_search_api_key = std::env::var("SERPER_API_KEY").ok();
-    let tool_registry = ToolRegistry::new_with_api_keys(web_search_api_key);
-    let tool_definitions: Vec<serde_json::Value> = tool_registry
-        .list_definitions()
-        .iter()
-        .map(|def| serde_json::to_value(def).unwrap())
-        .collect();
+    let tool_registry = ToolRegistry::new_with_api_keys(
+        web_search_api_key,
+        config.bash.clone(),
+        config.edit.clone(),
+    );
+    // Background processes die with the runner; this handle covers `process::exit`
+    let background = tool_registry.background_processes().clone();
 
     // Print welcome message
     if is_interactive {
@@ -270,9 +434,10 @@ async fn main() -> Result<()> {
             )
             .dimmed()
         );
+        print_loaded_context(&instruction_files, repo_map_summary.as_deref());
         println!(
             "{}",
-            "Type 'exit' to quit, 'save' to save session, 'export [file]' to export as markdown\n"
+            "Type 'exit' to quit, 'save' to save session, 'export [file]' to export as markdown, 'compact' to summarize history, 'undo' to revert the last turn's edits, 'checkpoints' to list or restore earlier ones, '/cost' for token usage\n"
                 .dimmed()
         );
     } else {
@@ -284,24 +449,51 @@ async fn main() -> Result<()> {
             )
             .dimmed()
         );
+        print_loaded_context(&instruction_files, repo_map_summary.as_deref());
     }
 
+    let mut runner = AgentRunner::new(
+        Box::new(provider),
+        tool_registry,
+        session,
+        system_prompt,
+        compactor,
+    );
+    runner.set_permissions(permissions);
+    runner.set_retry_notices(retry_notices);
+    runner.set_strict_workspace(args.strict_workspace || config.workspace.strict);
+    let checkpoint_dir = codeagent_dir
+        .join("checkpoints")
+        .join(&runner.session().info.id);
+    runner.set_checkpoints(CheckpointStore::new(checkpoint_dir));
+    let mut ui = ConsoleUi::new(args.debug_log.clone());
+
     // Main execution: interactive REPL or non-interactive single run
     if is_interactive {
         // Interactive REPL mode
         loop {
-            let user_input: String = Input::with_theme(&ColorfulTheme::default())
+            let user_input: String = match Input::with_theme(&ColorfulTheme::default())
                 .with_prompt("You")
-                .interact_text()?;
+                .interact_text()
+            {
+                Ok(input) => input,
+                // Ctrl-C at the prompt exits
+                Err(dialoguer::Error::IO(e)) if e.kind() == std::io::ErrorKind::Interrupted => {
+                    runner.save()?;
+                    println!("\n{}", "Session saved. Goodbye!".green());
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
             match user_input.trim() {
                 "exit" => {
-                    session.save()?;
+                    runner.save()?;
                     println!("{}", "Session saved. Goodbye!".green());
                     break;
                 }
                 "save" => {
-                    session.save()?;
+                    runner.save()?;
                     println!("{}", "Session saved.".green());
                     continue;
                 }
@@ -313,7 +505,7 @@ async fn main() -> Result<()> {
                         .filter(|s| !s.is_empty())
                         .map(|s| s.to_string());
 
-                    match session.export_to_markdown(filename) {
+                    match runner.session().export_to_markdown(filename) {
                         Ok(path) => {
                             println!("{}", format!("Exported to: {}", path).green());
                         }
@@ -323,233 +515,52 @@ async fn main() -> Result<()> {
                     }
                     continue;
                 }
-                "" => continue,
-                _ => {}
-            }
-
-            // Add user message
-            session.add_user_message(user_input);
-
-            // Get conversation history with system prompt
-            let mut messages = vec![Message {
-                role: "system".to_string(),
-                content: system_prompt.clone(),
-                tool_call_id: None,
-                tool_calls: Vec::new(),
-            }];
-            messages.extend(session.get_conversation_history());
-            if let Some(ref log_path) = args.debug_log {
-                write_debug_log(log_path, &format!("SENDING: {} messages", messages.len()));
-            }
-
-            // Agent loop
-            loop {
-                // Stream completion
-                let mut rx = provider
-                    .stream_completion(messages.clone(), Some(tool_definitions.clone()))
-                    .await?;
-
-                let mut full_content = String::new();
-                let mut all_tool_calls = Vec::new();
-                let mut header_printed = false;
-
-                while let Some(chunk) = rx.recv().await {
-                    // Debug log raw chunk
-                    if let Some(ref log_path) = args.debug_log {
-                        let chunk_json = serde_json::to_string(&chunk)
-                            .unwrap_or_else(|_| format!("{:?}", chunk));
-                        write_debug_log(log_path, &format!("STREAM_CHUNK: {}", chunk_json));
+                "undo" => {
+                    match runner.checkpoints().map(|store| store.undo()).transpose() {
+                        Ok(Some(Some((id, files)))) => print_restored(id, &files),
+                        Ok(_) => println!("{}", "Nothing to undo.".dimmed()),
+                        Err(e) => println!("{}", format!("Undo failed: {:#}", e).red()),
                     }
-
-                    if let Some(content) = chunk.content {
-                        // Only print the "Assistant:" header once we know there's text to show
-                        if !header_printed {
-                            println!("{}", "\nAssistant: ".bold().blue());
-                            header_printed = true;
-                        }
-                        print!("{}", content);
-                        full_content.push_str(&content);
-                    }
-
-                    all_tool_calls.extend(chunk.tool_calls);
-
-                    if chunk.finished {
-                        break;
+                    continue;
+                }
+                input if input.starts_with("checkpoints") => {
+                    if let Some(store) = runner.checkpoints() {
+                        handle_checkpoints_command(store, input);
                     }
+                    continue;
                 }
-                if !full_content.is_empty() {
-                    println!(); // Newline after streaming
+                "compact" => {
+                    runner.compact(&mut ui).await;
+                    runner.save()?;
+                    continue;
                 }
-
-                // Add assistant message
-                let content = if full_content.is_empty() {
-                    None
-                } else {
-                    Some(full_content)
-                };
-                session.add_assistant_message(content.clone(), all_tool_calls.clone());
-
-                // Also push assistant message into messages so the LLM sees its own prior response
-                messages.push(Message {
-                    role: "assistant".to_string(),
-                    content: content.clone().unwrap_or_default(),
-                    tool_call_id: None,
-                    tool_calls: all_tool_calls.clone(),
-                });
-
-                // If no tool calls, we're done
-                if all_tool_calls.is_empty() {
-                    break;
+                "/cost" => {
+                    let usage = runner.session().total_usage();
+                    let cost = price_table.cost(runner.provider().model(), &usage);
+                    println!("{}", format_usage(&usage, cost).cyan());
+                    let (context, budget) = runner.context_usage();
+                    println!(
+                        "{}",
+                        format!("Context: ~{} of {} tokens", context, budget).dimmed()
+                    );
+                    continue;
                 }
+                "" => continue,
+                _ => {}
+            }
 
-                // Execute tool calls
-                println!("\n{}", "Executing tools...".yellow());
-                let mut finish_called = false;
-                for tool_call in &all_tool_calls {
-                    println!("  {} {}", "→".blue(), tool_call.name.bold());
-
-                    // Check if this is the finish tool
-                    if tool_call.name == "finish" {
-                        finish_called = true;
-                    }
-
-                    // Print command details for bash tool
-                    if tool_call.name == "bash" {
-                        if let Some(cmd) = tool_call.arguments.get("command") {
-                            if let Some(cmd_str) = cmd.as_str() {
-                                println!("    Command: {}", cmd_str.dimmed());
-                            }
-                        }
-                        if let Some(desc) = tool_call.arguments.get("description") {
-                            if let Some(desc_str) = desc.as_str() {
-                                println!("    Description: {}", desc_str.dimmed());
-                            }
-                        }
-                        if let Some(cwd) = tool_call.arguments.get("cwd") {
-                            if let Some(cwd_str) = cwd.as_str() {
-                                println!("    Working directory: {}", cwd_str.dimmed());
-                            }
-                        }
-                    }
-
-                    // Print command details for file_search tool
-                    if tool_call.name == "file_search" {
-                        if let Some(op) = tool_call.arguments.get("operation") {
-                            if let Some(op_str) = op.as_str() {
-                                println!("    Operation: {}", op_str.dimmed());
-                            }
-                        }
-                        if let Some(pattern) = tool_call.arguments.get("pattern") {
-                            if let Some(pattern_str) = pattern.as_str() {
-                                println!("    Pattern: {}", pattern_str.dimmed());
-                            }
-                        }
-                        if let Some(path) = tool_call.arguments.get("path") {
-                            if let Some(path_str) = path.as_str() {
-                                println!("    Path: {}", path_str.dimmed());
-                            }
-                        }
-                        if let Some(ft) = tool_call.arguments.get("file_type") {
-                            if let Some(ft_str) = ft.as_str() {
-                                println!("    File type: {}", ft_str.dimmed());
-                            }
-                        }
-                    }
-
-                    // Debug log tool call
-                    if let Some(ref log_path) = args.debug_log {
-                        let args_json = serde_json::to_string(&tool_call.arguments)
-                            .unwrap_or_else(|_| format!("{:?}", tool_call.arguments));
-                        write_debug_log(
-                            log_path,
-                            &format!(
-                                "TOOL_CALL: {} ({}) args={}",
-                                tool_call.name, tool_call.id, args_json
-                            ),
-                        );
-                    }
+            let cancel = CancellationToken::new();
+            let interrupt_handler = spawn_interrupt_handler(cancel.clone(), background.clone());
+            let result = runner.run_turn(user_input, &mut ui, &cancel).await;
+            interrupt_handler.abort();
 
-                    match tool_registry.execute(&tool_call.name, tool_call.arguments.clone()) {
-                        Ok(result) => {
-                            // Debug log tool result
-                            if let Some(ref log_path) = args.debug_log {
-                                let output_json = serde_json::to_string(&result.output)
-                                    .unwrap_or_else(|_| format!("{:?}", result.output));
-                                write_debug_log(
-                                    log_path,
-                                    &format!(
-                                        "TOOL_RESULT: {} status={} output={}",
-                                        tool_call.name, result.status, output_json
-                                    ),
-                                );
-                            }
-
-                            println!("    {}", result.observation.green());
-                            if let Some(display) = &result.display {
-                                if !display.is_empty() {
-                                    println!("\n{}\n", display.dimmed());
-                                }
-                            }
-
-                            let observation = result.observation.clone();
-                            let output_json = serde_json::to_string_pretty(&result.output)
-                                .unwrap_or_else(|_| format!("{:?}", result.output));
-
-                            session.add_tool_result(
-                                tool_call.id.clone(),
-                                result.output,
-                                observation.clone(),
-                                result.status,
-                            );
-
-                            // Add tool result to messages
-                            // Combine observation with structured output for LLM context
-                            let combined_content =
-                                format!("{}\n\n```\n{}\n```", observation, output_json);
-                            messages.push(Message {
-                                role: "user".to_string(),
-                                content: combined_content,
-                                tool_call_id: Some(tool_call.id.clone()),
-                                tool_calls: Vec::new(),
-                            });
-                        }
-                        Err(e) => {
-                            // Debug log tool error
-                            if let Some(ref log_path) = args.debug_log {
-                                write_debug_log(
-                                    log_path,
-                                    &format!("TOOL_ERROR: {} error={}", tool_call.name, e),
-                                );
-                            }
-
-                            let error_msg = format!("Tool execution failed: {}", e);
-                            println!("    {}", error_msg.red());
-
-                            session.add_tool_result(
-                                tool_call.id.clone(),
-                                serde_json::json!({"error": error_msg}),
-                                error_msg.clone(),
-                                "error".to_string(),
-                            );
-
-                            messages.push(Message {
-                                role: "user".to_string(),
-                                content: error_msg,
-                                tool_call_id: Some(tool_call.id.clone()),
-                                tool_calls: Vec::new(),
-                            });
-                        }
-                    }
-                }
-
-                // If finish tool was called, exit the agent loop
-                if finish_called {
-                    break;
-                }
+            // Retries are exhausted by the time an error gets here; keep the REPL alive
+            if let Err(e) = result {
+                println!("{}", format!("Request failed: {:#}", e).red());
             }
 
             // Save after each interaction
-            session.save()?;
+            runner.save()?;
         }
     } else {
         // Non-interactive mode
@@ -561,202 +572,26 @@ async fn main() -> Result<()> {
             ));
         }
 
-        // Add user message
-        session.add_user_message(user_input);
-
-        // Get conversation history with system prompt
-        let mut messages = vec![Message {
-            role: "system".to_string(),
-            content: system_prompt.clone(),
-            tool_call_id: None,
-            tool_calls: Vec::new(),
-        }];
-        messages.extend(session.get_conversation_history());
-        if let Some(ref log_path) = args.debug_log {
-            write_debug_log(log_path, &format!("SENDING: {} messages", messages.len()));
-        }
-
-        // Agent loop
-        loop {
-            println!("{}", "\nAssistant: ".bold().blue());
-
-            // Stream completion
-            let mut rx = provider
-                .stream_completion(messages.clone(), Some(tool_definitions.clone()))
-                .await?;
-
-            let mut full_content = String::new();
-            let mut all_tool_calls = Vec::new();
-
-            while let Some(chunk) = rx.recv().await {
-                // Debug log raw chunk
-                if let Some(ref log_path) = args.debug_log {
-                    let chunk_json =
-                        serde_json::to_string(&chunk).unwrap_or_else(|_| format!("{:?}", chunk));
-                    write_debug_log(log_path, &format!("STREAM_CHUNK: {}", chunk_json));
-                }
+        let cancel = CancellationToken::new();
+        let interrupt_handler = spawn_interrupt_handler(cancel.clone(), background.clone());
+        let outcome = runner.run_turn(user_input, &mut ui, &cancel).await;
+        interrupt_handler.abort();
 
-                if let Some(content) = chunk.content {
-                    print!("{}", content);
-                    full_content.push_str(&content);
-                }
-
-                all_tool_calls.extend(chunk.tool_calls);
-
-                if chunk.finished {
-                    break;
-                }
-            }
-            println!(); // Newline after streaming
-
-            // Add assistant message
-            let content = if full_content.is_empty() {
-                None
-            } else {
-                Some(full_content)
-            };
-            session.add_assistant_message(content.clone(), all_tool_calls.clone());
-
-            // If no tool calls, we're done
-            if all_tool_calls.is_empty() {
-                break;
-            }
-
-            // Execute tool calls
-            println!("\n{}", "Executing tools...".yellow());
-            let mut finish_called = false;
-            for tool_call in &all_tool_calls {
-                println!("  {} {}", "→".blue(), tool_call.name.bold());
-
-                // Check if this is the finish tool
-                if tool_call.name == "finish" {
-                    finish_called = true;
-                }
-
-                // Handle auto-approve for bash tool
-                let mut modified_args = tool_call.arguments.clone();
-                if tool_call.name == "bash" && args.auto_approve {
-                    if let Some(obj) = modified_args.as_object_mut() {
-                        obj.insert("skip_approval".to_string(), serde_json::json!(true));
-                    }
-                }
-
-                // Print command details for bash tool
-                if tool_call.name == "bash" {
-                    if let Some(cmd) = modified_args.get("command") {
-                        if let Some(cmd_str) = cmd.as_str() {
-                            println!("    Command: {}", cmd_str.dimmed());
-                        }
-                    }
-                    if let Some(desc) = modified_args.get("description") {
-                        if let Some(desc_str) = desc.as_str() {
-                            println!("    Description: {}", desc_str.dimmed());
-                        }
-                    }
-                    if let Some(cwd) = modified_args.get("cwd") {
-                        if let Some(cwd_str) = cwd.as_str() {
-                            println!("    Working directory: {}", cwd_str.dimmed());
-                        }
-                    }
-                }
-
-                // Debug log tool call
-                if let Some(ref log_path) = args.debug_log {
-                    let args_json = serde_json::to_string(&modified_args)
-                        .unwrap_or_else(|_| format!("{:?}", modified_args));
-                    write_debug_log(
-                        log_path,
-                        &format!(
-                            "TOOL_CALL: {} ({}) args={}",
-                            tool_call.name, tool_call.id, args_json
-                        ),
-                    );
-                }
-
-                match tool_registry.execute(&tool_call.name, modified_args) {
-                    Ok(result) => {
-                        // Debug log tool result
-                        if let Some(ref log_path) = args.debug_log {
-                            let output_json = serde_json::to_string(&result.output)
-                                .unwrap_or_else(|_| format!("{:?}", result.output));
-                            write_debug_log(
-                                log_path,
-                                &format!(
-                                    "TOOL_RESULT: {} status={} output={}",
-                                    tool_call.name, result.status, output_json
-                                ),
-                            );
-                        }
-
-                        println!("    {}", result.observation.green());
-                        if let Some(display) = &result.display {
-                            if !display.is_empty() {
-                                println!("\n{}\n", display.dimmed());
-                            }
-                        }
-
-                        let observation = result.observation.clone();
-                        let output_json = serde_json::to_string_pretty(&result.output)
-                            .unwrap_or_else(|_| format!("{:?}", result.output));
-
-                        session.add_tool_result(
-                            tool_call.id.clone(),
-                            result.output,
-                            observation.clone(),
-                            result.status,
-                        );
-
-                        // Add tool result to messages
-                        // Combine observation with structured output for LLM context
-                        let combined_content =
-                            format!("{}\n\n```\n{}\n```", observation, output_json);
-                        messages.push(Message {
-                            role: "user".to_string(),
-                            content: combined_content,
-                            tool_call_id: Some(tool_call.id.clone()),
-                            tool_calls: Vec::new(),
-                        });
-                    }
-                    Err(e) => {
-                        // Debug log tool error
-                        if let Some(ref log_path) = args.debug_log {
-                            write_debug_log(
-                                log_path,
-                                &format!("TOOL_ERROR: {} error={}", tool_call.name, e),
-                            );
-                        }
-
-                        let error_msg = format!("Tool execution failed: {}", e);
-                        println!("    {}", error_msg.red());
-
-                        session.add_tool_result(
-                            tool_call.id.clone(),
-                            serde_json::json!({"error": error_msg}),
-                            error_msg.clone(),
-                            "error".to_string(),
-                        );
-
-                        messages.push(Message {
-                            role: "user".to_string(),
-                            content: error_msg,
-                            tool_call_id: Some(tool_call.id.clone()),
-                            tool_calls: Vec::new(),
-                        });
-                    }
-                }
-            }
-
-            // If finish tool was called, exit the agent loop
-            if finish_called {
-                break;
-            }
-        }
+        // Also when the turn failed: the tokens were spent and the session shows how far it got
+        let usage = runner.session().total_usage();
+        let cost = price_table.cost(runner.provider().model(), &usage);
+        println!("\n{}", format_usage(&usage, cost).dimmed());
 
         // Save session if --save flag set
         if args.save {
-            session.save()?;
+            runner.save()?;
             println!("{}", "\nSession saved.".green());
         }
+
+        if outcome? == TurnOutcome::Interrupted {
+            background.kill_all();
+            std::process::exit(130);
+        }
     }
 
     Ok(())
@@ -0,0 +1,563 @@
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+use crate::tools::apply_patch::patch_paths;
+use crate::tools::ToolContext;
+
+/// What happens to a tool call, from least to most restrictive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// Permission settings (the `permissions` section of the config file). Deny rules
+/// win over ask rules, which win over allow rules.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PermissionConfig {
+    pub allow: Vec<Rule>,
+    pub ask: Vec<Rule>,
+    pub deny: Vec<Rule>,
+    /// Decision for bash commands no rule matches
+    pub bash_default: Decision,
+    /// Decision for `edit_file` writes no rule matches
+    pub edit_default: Decision,
+}
+
+impl Default for PermissionConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            ask: Vec::new(),
+            deny: Vec::new(),
+            bash_default: Decision::Ask,
+            edit_default: Decision::Ask,
+        }
+    }
+}
+
+/// Matches tool calls. Every field that is set must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Tool name, or `*` for any tool
+    pub tool: String,
+    /// bash: the command starts with these words
+    pub prefix: Option<String>,
+    /// bash: glob over the whole command
+    pub pattern: Option<String>,
+    /// edit_file: glob over the file path. Without a `/` it matches the file name.
+    pub path: Option<String>,
+    /// edit_file: operations the rule applies to (all if empty)
+    #[serde(default)]
+    pub operations: Vec<String>,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tool)?;
+        if let Some(prefix) = &self.prefix {
+            write!(f, " prefix '{}'", prefix)?;
+        }
+        if let Some(pattern) = &self.pattern {
+            write!(f, " pattern '{}'", pattern)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " path '{}'", path)?;
+        }
+        if !self.operations.is_empty() {
+            write!(f, " operations [{}]", self.operations.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A rule with its globs compiled
+struct CompiledRule {
+    rule: Rule,
+    pattern: Option<Pattern>,
+    path: Option<Pattern>,
+}
+
+impl CompiledRule {
+    fn new(rule: Rule) -> Result<Self> {
+        let compile = |glob: &Option<String>| {
+            glob.as_deref()
+                .map(Pattern::new)
+                .transpose()
+                .with_context(|| format!("Invalid glob in permission rule '{}'", rule))
+        };
+        Ok(Self {
+            pattern: compile(&rule.pattern)?,
+            path: compile(&rule.path)?,
+            rule,
+        })
+    }
+
+    /// `edit_file` rules also cover the files an `apply_patch` call writes
+    fn applies_to(&self, tool: &str) -> bool {
+        self.rule.tool == "*"
+            || self.rule.tool == tool
+            || (tool == "apply_patch" && self.rule.tool == "edit_file")
+    }
+
+    /// Match a single bash command (one segment of a command line)
+    fn matches_command(&self, command: &str) -> bool {
+        if self.path.is_some() || !self.rule.operations.is_empty() {
+            return false;
+        }
+        let command = command.trim();
+        if let Some(prefix) = &self.rule.prefix {
+            let prefix = prefix.trim();
+            let Some(rest) = command.strip_prefix(prefix) else {
+                return false;
+            };
+            if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+                return false;
+            }
+        }
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.matches(command))
+    }
+
+    fn matches_edit(&self, operation: Option<&str>, path: Option<&Path>, root: &Path) -> bool {
+        if self.rule.prefix.is_some() || self.pattern.is_some() {
+            return false;
+        }
+        if !self.rule.operations.is_empty()
+            && !operation.is_some_and(|op| self.rule.operations.iter().any(|o| o == op))
+        {
+            return false;
+        }
+        match &self.path {
+            Some(pattern) => path.is_some_and(|path| path_matches(pattern, path, root)),
+            None => true,
+        }
+    }
+
+    /// Rules for other tools can only name the tool
+    fn matches_other(&self) -> bool {
+        self.rule.prefix.is_none()
+            && self.pattern.is_none()
+            && self.path.is_none()
+            && self.rule.operations.is_empty()
+    }
+}
+
+/// Match an absolute path against a rule glob. Patterns without a `/` match the file
+/// name; others match the absolute path or the path relative to the workspace root.
+fn path_matches(pattern: &Pattern, path: &Path, root: &Path) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::default()
+    };
+
+    if !pattern.as_str().contains('/') {
+        return path
+            .file_name()
+            .is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), options));
+    }
+    pattern.matches_path_with(path, options)
+        || path
+            .strip_prefix(root)
+            .is_ok_and(|relative| pattern.matches_path_with(relative, options))
+}
+
+/// Split a command line into the commands it runs, on `;`, `&`, `|` and newlines
+/// outside quotes
+fn split_commands(command: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in command.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ';' | '&' | '|' | '\n') => {
+                segments.push(&command[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    segments.push(&command[start..]);
+    segments.retain(|s| !s.trim().is_empty());
+    segments
+}
+
+/// Command and process substitution and output redirection can run or overwrite
+/// anything, so commands using them are never allowed by a prefix rule alone
+fn has_side_channels(command: &str) -> bool {
+    ["$(", "`", "<(", ">"]
+        .iter()
+        .any(|pattern| command.contains(pattern))
+}
+
+/// The decision for one tool call and why it was made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub decision: Decision,
+    pub reason: String,
+}
+
+/// Decides which tool calls run, which need confirmation and which are refused.
+/// Owned by the user: nothing in the tool arguments can change the outcome.
+pub struct PermissionPolicy {
+    allow: Vec<CompiledRule>,
+    ask: Vec<CompiledRule>,
+    deny: Vec<CompiledRule>,
+    bash_default: Decision,
+    edit_default: Decision,
+    auto_approve: bool,
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        Self::new(PermissionConfig::default()).expect("default policy has no globs")
+    }
+}
+
+impl PermissionPolicy {
+    pub fn new(config: PermissionConfig) -> Result<Self> {
+        let compile = |rules: Vec<Rule>| {
+            rules
+                .into_iter()
+                .map(CompiledRule::new)
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            allow: compile(config.allow)?,
+            ask: compile(config.ask)?,
+            deny: compile(config.deny)?,
+            bash_default: config.bash_default,
+            edit_default: config.edit_default,
+            auto_approve: false,
+        })
+    }
+
+    /// Allow whatever would otherwise be asked (`--auto-approve`). Deny rules still apply.
+    pub fn auto_approve(mut self) -> Self {
+        self.auto_approve = true;
+        self
+    }
+
+    /// Allow every future edit of `path` (absolute) without asking, for the rest of
+    /// the session. Deny rules still apply.
+    pub fn allow_file(&mut self, path: &Path) {
+        let glob = Pattern::escape(&path.to_string_lossy());
+        self.allow.insert(
+            0,
+            CompiledRule {
+                path: Pattern::new(&glob).ok(),
+                pattern: None,
+                rule: Rule {
+                    tool: "edit_file".to_string(),
+                    path: Some(glob),
+                    ..Rule::default()
+                },
+            },
+        );
+    }
+
+    /// Edit paths are resolved against the workspace root in `ctx`
+    pub fn check(&self, tool: &str, arguments: &serde_json::Value, ctx: &ToolContext) -> Verdict {
+        let verdict = match tool {
+            "bash" => self.check_bash(
+                arguments
+                    .get("command")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(""),
+            ),
+            "edit_file" => {
+                let operation = arguments.get("operation").and_then(|v| v.as_str());
+                let path = arguments
+                    .get("file_path")
+                    .and_then(|v| v.as_str())
+                    .and_then(|path| ctx.resolve(path).ok());
+                let default = if operation == Some("read_file") {
+                    Decision::Allow
+                } else {
+                    self.edit_default
+                };
+                self.check_rules(tool, default, |rule| {
+                    rule.matches_edit(operation, path.as_deref(), ctx.root())
+                })
+            }
+            // Each file in a patch is checked like an edit; the most restrictive decision wins
+            "apply_patch" => {
+                let patch = arguments
+                    .get("patch")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let verdicts = patch_paths(patch).into_iter().map(|path| {
+                    let path = ctx.resolve(&path).ok();
+                    self.check_rules(tool, self.edit_default, |rule| {
+                        rule.matches_edit(Some("apply_patch"), path.as_deref(), ctx.root())
+                    })
+                });
+                verdicts
+                    .max_by_key(|v| v.decision)
+                    .unwrap_or_else(|| Verdict {
+                        decision: self.edit_default,
+                        reason: "default for apply_patch".to_string(),
+                    })
+            }
+            _ => self.check_rules(tool, Decision::Allow, CompiledRule::matches_other),
+        };
+
+        if verdict.decision == Decision::Ask && self.auto_approve {
+            return Verdict {
+                decision: Decision::Allow,
+                reason: "--auto-approve".to_string(),
+            };
+        }
+        verdict
+    }
+
+    fn check_rules(
+        &self,
+        tool: &str,
+        default: Decision,
+        matches: impl Fn(&CompiledRule) -> bool,
+    ) -> Verdict {
+        for (decision, rules) in [
+            (Decision::Deny, &self.deny),
+            (Decision::Ask, &self.ask),
+            (Decision::Allow, &self.allow),
+        ] {
+            if let Some(rule) = rules.iter().find(|r| r.applies_to(tool) && matches(r)) {
+                return Verdict {
+                    decision,
+                    reason: format!("rule {}", rule.rule),
+                };
+            }
+        }
+        Verdict {
+            decision: default,
+            reason: format!("default for {}", tool),
+        }
+    }
+
+    /// Deny and ask rules match the whole command line or any command in it. Allow
+    /// rules must match every command in it.
+    fn check_bash(&self, command: &str) -> Verdict {
+        let segments = split_commands(command);
+        let matches = |r: &CompiledRule| {
+            r.applies_to("bash")
+                && (r.matches_command(command) || segments.iter().any(|s| r.matches_command(s)))
+        };
+
+        for (decision, rules) in [(Decision::Deny, &self.deny), (Decision::Ask, &self.ask)] {
+            if let Some(rule) = rules.iter().find(|r| matches(r)) {
+                return Verdict {
+                    decision,
+                    reason: format!("rule {}", rule.rule),
+                };
+            }
+        }
+
+        let allowed = !segments.is_empty()
+            && !has_side_channels(command)
+            && segments.iter().all(|s| {
+                self.allow
+                    .iter()
+                    .any(|r| r.applies_to("bash") && r.matches_command(s))
+            });
+        if allowed {
+            return Verdict {
+                decision: Decision::Allow,
+                reason: "allow rules".to_string(),
+            };
+        }
+
+        Verdict {
+            decision: self.bash_default,
+            reason: "default for bash".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> ToolContext {
+        ToolContext::new("/work", false)
+    }
+
+    fn policy(config: serde_json::Value) -> PermissionPolicy {
+        PermissionPolicy::new(serde_json::from_value(config).unwrap()).unwrap()
+    }
+
+    fn bash(policy: &PermissionPolicy, command: &str) -> Decision {
+        policy
+            .check("bash", &json!({ "command": command }), &context())
+            .decision
+    }
+
+    #[test]
+    fn bash_prefix_rules_cover_every_command_in_a_line() {
+        let policy = policy(json!({
+            "allow": [
+                { "tool": "bash", "prefix": "git status" },
+                { "tool": "bash", "prefix": "ls" }
+            ]
+        }));
+
+        assert_eq!(bash(&policy, "git status"), Decision::Allow);
+        assert_eq!(
+            bash(&policy, "git status --short && ls -la"),
+            Decision::Allow
+        );
+        assert_eq!(bash(&policy, "git statusx"), Decision::Ask);
+        assert_eq!(bash(&policy, "git status; rm -rf /"), Decision::Ask);
+        assert_eq!(bash(&policy, "ls $(rm -rf /)"), Decision::Ask);
+        assert_eq!(bash(&policy, "ls > listing.txt"), Decision::Ask);
+        assert_eq!(bash(&policy, "ls <(rm -rf ~)"), Decision::Ask);
+        assert_eq!(bash(&policy, "ls >(rm -rf ~)"), Decision::Ask);
+        // Separators inside quotes don't split the command
+        assert_eq!(bash(&policy, "ls 'a;b' \"c|d\""), Decision::Allow);
+    }
+
+    #[test]
+    fn deny_wins_over_allow_and_auto_approve() {
+        let policy = policy(json!({
+            "allow": [{ "tool": "bash", "prefix": "rm" }],
+            "deny": [{ "tool": "bash", "pattern": "rm *-*r*" }]
+        }))
+        .auto_approve();
+
+        assert_eq!(bash(&policy, "rm file.txt"), Decision::Allow);
+        assert_eq!(bash(&policy, "echo hi && rm -rf build"), Decision::Deny);
+        // Ask becomes allow under auto-approve
+        assert_eq!(bash(&policy, "make"), Decision::Allow);
+    }
+
+    #[test]
+    fn edit_rules_match_paths_and_operations() {
+        let policy = policy(json!({
+            "edit_default": "ask",
+            "allow": [{ "tool": "edit_file", "path": "src/**" }],
+            "deny": [
+                { "tool": "edit_file", "path": ".env" },
+                { "tool": "edit_file", "path": "Cargo.lock", "operations": ["create_file"] }
+            ]
+        }));
+        let edit = |operation: &str, path: &str| {
+            policy
+                .check(
+                    "edit_file",
+                    &json!({ "operation": operation, "file_path": path }),
+                    &context(),
+                )
+                .decision
+        };
+
+        assert_eq!(
+            edit("replace_by_string", "src/tools/mod.rs"),
+            Decision::Allow
+        );
+        assert_eq!(edit("replace_by_string", "README.md"), Decision::Ask);
+        // Paths are resolved against the workspace root before matching
+        assert_eq!(
+            edit("replace_by_string", "/work/src/lib.rs"),
+            Decision::Allow
+        );
+        assert_eq!(
+            edit("replace_by_string", "/other/src/lib.rs"),
+            Decision::Ask
+        );
+        assert_eq!(edit("read_file", "README.md"), Decision::Allow);
+        assert_eq!(edit("read_file", "config/.env"), Decision::Deny);
+        assert_eq!(edit("create_file", "Cargo.lock"), Decision::Deny);
+        assert_eq!(edit("replace_by_lines", "Cargo.lock"), Decision::Ask);
+
+        let patch = |files: &[&str]| {
+            let patch: String = files
+                .iter()
+                .map(|f| format!("--- a/{f}\n+++ b/{f}\n@@ -1 +1 @@\n-a\n+b\n"))
+                .collect();
+            policy
+                .check("apply_patch", &json!({ "patch": patch }), &context())
+                .decision
+        };
+        assert_eq!(patch(&["src/a.rs", "src/b.rs"]), Decision::Allow);
+        assert_eq!(patch(&["src/a.rs", "README.md"]), Decision::Ask);
+        assert_eq!(patch(&["src/a.rs", "config/.env"]), Decision::Deny);
+    }
+
+    #[test]
+    fn always_allowing_a_file_skips_the_prompt_for_it_only() {
+        let mut policy = policy(json!({
+            "deny": [{ "tool": "edit_file", "path": "secrets/**" }]
+        }));
+        let edit = |policy: &PermissionPolicy, path: &str| {
+            policy
+                .check(
+                    "edit_file",
+                    &json!({ "operation": "replace_by_string", "file_path": path }),
+                    &context(),
+                )
+                .decision
+        };
+        assert_eq!(edit(&policy, "notes[1].md"), Decision::Ask);
+
+        policy.allow_file(Path::new("/work/notes[1].md"));
+        policy.allow_file(Path::new("/work/secrets/key.txt"));
+        assert_eq!(edit(&policy, "notes[1].md"), Decision::Allow);
+        assert_eq!(edit(&policy, "./notes[1].md"), Decision::Allow);
+        assert_eq!(edit(&policy, "notes1.md"), Decision::Ask);
+        assert_eq!(edit(&policy, "secrets/key.txt"), Decision::Deny);
+    }
+
+    #[test]
+    fn defaults_and_tool_rules() {
+        let default = PermissionPolicy::default();
+        assert_eq!(bash(&default, "ls"), Decision::Ask);
+        assert_eq!(
+            default
+                .check(
+                    "edit_file",
+                    &json!({ "operation": "create_file", "file_path": "a.txt" }),
+                    &context(),
+                )
+                .decision,
+            Decision::Ask
+        );
+        assert_eq!(
+            default
+                .check("url_fetch", &json!({ "url": "x" }), &context())
+                .decision,
+            Decision::Allow
+        );
+
+        let policy = policy(json!({ "deny": [{ "tool": "url_fetch" }] }));
+        let verdict = policy.check("url_fetch", &json!({ "url": "x" }), &context());
+        assert_eq!(verdict.decision, Decision::Deny);
+        assert_eq!(verdict.reason, "rule url_fetch");
+
+        let invalid = serde_json::from_value(json!({
+            "deny": [{ "tool": "bash", "pattern": "[" }]
+        }))
+        .unwrap();
+        assert!(PermissionPolicy::new(invalid).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::provider::Usage;
+
+/// Prices in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price for writing to the prompt cache (defaults to the input price)
+    #[serde(default)]
+    pub cache_write: Option<f64>,
+    /// Price for reading from the prompt cache (defaults to the input price)
+    #[serde(default)]
+    pub cache_read: Option<f64>,
+}
+
+impl ModelPrice {
+    const fn new(input: f64, output: f64, cache_write: f64, cache_read: f64) -> Self {
+        Self {
+            input,
+            output,
+            cache_write: Some(cache_write),
+            cache_read: Some(cache_read),
+        }
+    }
+
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let per_token = |tokens: u64, price: f64| tokens as f64 * price / 1_000_000.0;
+
+        per_token(usage.input_tokens, self.input)
+            + per_token(usage.output_tokens, self.output)
+            + per_token(
+                usage.cache_creation_input_tokens,
+                self.cache_write.unwrap_or(self.input),
+            )
+            + per_token(
+                usage.cache_read_input_tokens,
+                self.cache_read.unwrap_or(self.input),
+            )
+    }
+}
+
+/// Built-in prices, keyed by model name prefix
+const DEFAULT_PRICES: [(&str, ModelPrice); 12] = [
+    ("claude-opus-4-5", ModelPrice::new(5.0, 25.0, 6.25, 0.5)),
+    ("claude-opus-4", ModelPrice::new(15.0, 75.0, 18.75, 1.5)),
+    ("claude-sonnet-4", ModelPrice::new(3.0, 15.0, 3.75, 0.3)),
+    ("claude-haiku-4", ModelPrice::new(1.0, 5.0, 1.25, 0.1)),
+    ("claude-3-7-sonnet", ModelPrice::new(3.0, 15.0, 3.75, 0.3)),
+    ("claude-3-5-sonnet", ModelPrice::new(3.0, 15.0, 3.75, 0.3)),
+    ("claude-3-5-haiku", ModelPrice::new(0.8, 4.0, 1.0, 0.08)),
+    ("gpt-4o", ModelPrice::new(2.5, 10.0, 2.5, 1.25)),
+    ("gpt-4o-mini", ModelPrice::new(0.15, 0.6, 0.15, 0.075)),
+    ("gpt-4.1", ModelPrice::new(2.0, 8.0, 2.0, 0.5)),
+    ("gpt-4.1-mini", ModelPrice::new(0.4, 1.6, 0.4, 0.1)),
+    ("gpt-4.1-nano", ModelPrice::new(0.1, 0.4, 0.1, 0.025)),
+];
+
+/// Per-model price lookup. User-configured prices take precedence over the built-in table.
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new(overrides: HashMap<String, ModelPrice>) -> Self {
+        let mut prices: HashMap<String, ModelPrice> = DEFAULT_PRICES
+            .iter()
+            .map(|(name, price)| (name.to_string(), *price))
+            .collect();
+        prices.extend(overrides);
+
+        Self { prices }
+    }
+
+    /// Find the price for a model: exact name first, then the longest matching prefix
+    /// (so "claude-sonnet-4-5-20250929" matches "claude-sonnet-4")
+    pub fn lookup(&self, model: &str) -> Option<ModelPrice> {
+        if let Some(price) = self.prices.get(model) {
+            return Some(*price);
+        }
+
+        self.prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<f64> {
+        self.lookup(model).map(|price| price.cost(usage))
+    }
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
+
+/// One-line summary of token usage and cost, e.g. for `/cost`
+pub fn format_usage(usage: &Usage, cost: Option<f64>) -> String {
+    let mut summary = format!(
+        "Tokens: {} input, {} output",
+        usage.input_tokens, usage.output_tokens
+    );
+
+    if usage.cache_creation_input_tokens > 0 || usage.cache_read_input_tokens > 0 {
+        summary.push_str(&format!(
+            " (cache: {} written, {} read)",
+            usage.cache_creation_input_tokens, usage.cache_read_input_tokens
+        ));
+    }
+
+    match cost {
+        Some(cost) => summary.push_str(&format!(" | Cost: ${:.4}", cost)),
+        None => summary.push_str(" | Cost: unknown (no price configured for this model)"),
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input: u64, output: u64) -> Usage {
+        Usage {
+            input_tokens: input,
+            output_tokens: output,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_longest_prefix() {
+        let table = PriceTable::default();
+
+        assert_eq!(table.lookup("gpt-4o-mini-2024-07-18").unwrap().input, 0.15);
+        assert_eq!(table.lookup("gpt-4o-2024-08-06").unwrap().input, 2.5);
+        assert_eq!(
+            table.lookup("claude-sonnet-4-5-20250929").unwrap().output,
+            15.0
+        );
+        assert_eq!(table.lookup("claude-opus-4-5-20251101").unwrap().input, 5.0);
+        assert_eq!(
+            table.lookup("claude-opus-4-1-20250805").unwrap().input,
+            15.0
+        );
+        assert!(table.lookup("GLM-4.7").is_none());
+    }
+
+    #[test]
+    fn user_prices_override_defaults() {
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "gpt-4o".to_string(),
+            ModelPrice {
+                input: 1.0,
+                output: 2.0,
+                cache_write: None,
+                cache_read: None,
+            },
+        );
+        let table = PriceTable::new(overrides);
+
+        let cost = table.cost("gpt-4o", &usage(1_000_000, 500_000)).unwrap();
+        assert!((cost - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn prices_cache_tokens_separately() {
+        let price = ModelPrice::new(3.0, 15.0, 3.75, 0.3);
+        let usage = Usage {
+            input_tokens: 1_000,
+            output_tokens: 1_000,
+            cache_creation_input_tokens: 10_000,
+            cache_read_input_tokens: 100_000,
+        };
+
+        let expected = (3_000.0 + 15_000.0 + 37_500.0 + 30_000.0) / 1_000_000.0;
+        assert!((price.cost(&usage) - expected).abs() < 1e-9);
+    }
+}
@@ -1,4 +1,7 @@
-use super::{LLMProvider, Message, StreamChunk, ToolCall};
+use super::{
+    arguments_object, parse_tool_arguments, LLMProvider, LineBuffer, Message, ProviderError,
+    StreamChunk, ToolCall, Usage,
+};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use reqwest::Client;
@@ -26,7 +29,34 @@ struct AnthropicRequest {
 #[derive(Debug, Deserialize)]
 struct AnthropicResponse {
     content: Vec<ContentBlock>,
-    stop_reason: Option<String>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    cache_creation_input_tokens: Option<u64>,
+    cache_read_input_tokens: Option<u64>,
+}
+
+impl AnthropicUsage {
+    /// Merge reported counts into `usage`. Counts in message_delta are cumulative,
+    /// so values present here replace earlier ones rather than adding to them.
+    fn apply_to(&self, usage: &mut Usage) {
+        if let Some(n) = self.input_tokens {
+            usage.input_tokens = n;
+        }
+        if let Some(n) = self.output_tokens {
+            usage.output_tokens = n;
+        }
+        if let Some(n) = self.cache_creation_input_tokens {
+            usage.cache_creation_input_tokens = n;
+        }
+        if let Some(n) = self.cache_read_input_tokens {
+            usage.cache_read_input_tokens = n;
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,11 +78,21 @@ struct StreamEvent {
     event_type: String,
     delta: Option<Delta>,
     content_block: Option<ContentBlock>,
+    /// Present on message_start
+    message: Option<StreamMessage>,
+    /// Present on message_delta
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    usage: Option<AnthropicUsage>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Delta {
-    #[serde(rename = "type")]
+    // message_delta deltas carry stop_reason but no type
+    #[serde(rename = "type", default)]
     delta_type: String,
     text: Option<String>,
     partial_json: Option<String>,
@@ -99,7 +139,7 @@ impl AnthropicProvider {
                             "type": "tool_use",
                             "id": tc.id,
                             "name": tc.name,
-                            "input": tc.arguments
+                            "input": arguments_object(&tc.arguments)
                         }));
                     }
 
@@ -187,6 +227,10 @@ impl AnthropicProvider {
 
 #[async_trait]
 impl LLMProvider for AnthropicProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
     async fn chat_completion(
         &self,
         messages: Vec<Message>,
@@ -212,7 +256,11 @@ impl LLMProvider for AnthropicProvider {
             .json(&request)
             .send()
             .await
+            .map_err(ProviderError::from)
             .context("Failed to send Anthropic request")?;
+        let response = ProviderError::check(response)
+            .await
+            .context("Anthropic request failed")?;
 
         let anthropic_response: AnthropicResponse = response
             .json()
@@ -235,10 +283,17 @@ impl LLMProvider for AnthropicProvider {
             }
         }
 
+        let usage = anthropic_response.usage.map(|u| {
+            let mut usage = Usage::default();
+            u.apply_to(&mut usage);
+            usage
+        });
+
         Ok(StreamChunk {
             content,
             tool_calls,
             finished: true,
+            usage,
         })
     }
 
@@ -246,7 +301,7 @@ impl LLMProvider for AnthropicProvider {
         &self,
         messages: Vec<Message>,
         tools: Option<Vec<serde_json::Value>>,
-    ) -> Result<tokio::sync::mpsc::Receiver<StreamChunk>> {
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
         let (system, converted_messages) = self.convert_messages(messages);
 
         let request = AnthropicRequest {
@@ -267,7 +322,11 @@ impl LLMProvider for AnthropicProvider {
             .json(&request)
             .send()
             .await
+            .map_err(ProviderError::from)
             .context("Failed to send Anthropic stream request")?;
+        let response = ProviderError::check(response)
+            .await
+            .context("Anthropic stream request failed")?;
 
         let (tx, rx) = tokio::sync::mpsc::channel(100);
 
@@ -278,97 +337,103 @@ impl LLMProvider for AnthropicProvider {
             // State for accumulating tool call parameters
             let mut current_tool_call: Option<(String, String, String)> = None; // (id, name, accumulated_json)
                                                                                 // Buffer for incomplete SSE lines split across HTTP chunks
-            let mut line_buf = String::new();
+            let mut line_buf = LineBuffer::default();
+            // Token usage from message_start / message_delta
+            let mut usage: Option<Usage> = None;
 
             while let Some(chunk_result) = stream.next().await {
-                if let Ok(chunk) = chunk_result {
-                    let text = String::from_utf8_lossy(&chunk);
-                    line_buf.push_str(&text);
-
-                    // Process all complete lines; keep the last partial line in the buffer
-                    let mut lines: Vec<String> =
-                        line_buf.split('\n').map(|s| s.to_string()).collect();
-                    let remainder = if line_buf.ends_with('\n') {
-                        String::new()
-                    } else {
-                        lines.pop().unwrap_or_default()
-                    };
-                    line_buf = remainder;
-
-                    for line in lines {
-                        let line = line.trim_end_matches('\r');
-                        if let Some(data) = line.strip_prefix("data: ") {
-                            if let Ok(event) = serde_json::from_str::<StreamEvent>(data) {
-                                match event.event_type.as_str() {
-                                    "content_block_delta" => {
-                                        if let Some(delta) = event.delta {
-                                            if let Some(text) = delta.text {
-                                                let chunk = StreamChunk {
-                                                    content: Some(text),
-                                                    tool_calls: Vec::new(),
-                                                    finished: false,
-                                                };
-                                                if tx.send(chunk).await.is_err() {
-                                                    return;
-                                                }
-                                            }
-
-                                            // Accumulate tool input JSON deltas
-                                            if delta.delta_type == "input_json_delta" {
-                                                if let Some(partial_json) = delta.partial_json {
-                                                    if let Some((_, _, ref mut json)) =
-                                                        current_tool_call
-                                                    {
-                                                        json.push_str(&partial_json);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    "content_block_start" => {
-                                        if let Some(ContentBlock::ToolUse { id, name, input: _ }) =
-                                            event.content_block
-                                        {
-                                            // Start accumulating - initial input is always empty {}
-                                            current_tool_call = Some((id, name, String::new()));
-                                        }
-                                    }
-                                    "content_block_stop" => {
-                                        // Finalize accumulated tool call
-                                        if let Some((id, name, json_str)) = current_tool_call.take()
-                                        {
-                                            let arguments = if json_str.is_empty() {
-                                                serde_json::json!({})
-                                            } else {
-                                                serde_json::from_str(&json_str)
-                                                    .unwrap_or_else(|_| serde_json::json!({}))
-                                            };
-
+                let chunk = match chunk_result {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(Err(ProviderError::from(e).into())).await;
+                        return;
+                    }
+                };
+                for line in line_buf.push(&chunk) {
+                    let line = line.trim_end_matches('\r');
+                    if let Some(data) = line.strip_prefix("data: ") {
+                        if let Ok(event) = serde_json::from_str::<StreamEvent>(data) {
+                            match event.event_type.as_str() {
+                                "content_block_delta" => {
+                                    if let Some(delta) = event.delta {
+                                        if let Some(text) = delta.text {
                                             let chunk = StreamChunk {
-                                                content: None,
-                                                tool_calls: vec![ToolCall {
-                                                    id,
-                                                    name,
-                                                    arguments,
-                                                }],
+                                                content: Some(text),
+                                                tool_calls: Vec::new(),
                                                 finished: false,
+                                                usage: None,
                                             };
-                                            if tx.send(chunk).await.is_err() {
+                                            if tx.send(Ok(chunk)).await.is_err() {
                                                 return;
                                             }
                                         }
+
+                                        // Accumulate tool input JSON deltas
+                                        if delta.delta_type == "input_json_delta" {
+                                            if let Some(partial_json) = delta.partial_json {
+                                                if let Some((_, _, ref mut json)) =
+                                                    current_tool_call
+                                                {
+                                                    json.push_str(&partial_json);
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                                "content_block_start" => {
+                                    if let Some(ContentBlock::ToolUse { id, name, input: _ }) =
+                                        event.content_block
+                                    {
+                                        // Start accumulating - initial input is always empty {}
+                                        current_tool_call = Some((id, name, String::new()));
                                     }
-                                    "message_stop" => {
+                                }
+                                "content_block_stop" => {
+                                    // Finalize accumulated tool call
+                                    if let Some((id, name, json_str)) = current_tool_call.take() {
+                                        let arguments = parse_tool_arguments(&json_str);
                                         let chunk = StreamChunk {
                                             content: None,
-                                            tool_calls: Vec::new(),
-                                            finished: true,
+                                            tool_calls: vec![ToolCall {
+                                                id,
+                                                name,
+                                                arguments,
+                                            }],
+                                            finished: false,
+                                            usage: None,
                                         };
-                                        let _ = tx.send(chunk).await;
-                                        return;
+                                        if tx.send(Ok(chunk)).await.is_err() {
+                                            return;
+                                        }
+                                    }
+                                }
+                                "message_start" => {
+                                    if let Some(u) = event.message.and_then(|m| m.usage) {
+                                        u.apply_to(usage.get_or_insert_with(Usage::default));
                                     }
-                                    _ => {}
                                 }
+                                "message_delta" => {
+                                    if let Some(u) = event.usage {
+                                        u.apply_to(usage.get_or_insert_with(Usage::default));
+                                    }
+                                }
+                                // e.g. overloaded_error after the response started
+                                "error" => {
+                                    let err = ProviderError::from_stream_event(data);
+                                    let _ = tx.send(Err(err.into())).await;
+                                    return;
+                                }
+                                "message_stop" => {
+                                    let chunk = StreamChunk {
+                                        content: None,
+                                        tool_calls: Vec::new(),
+                                        finished: true,
+                                        usage,
+                                    };
+                                    let _ = tx.send(Ok(chunk)).await;
+                                    return;
+                                }
+                                _ => {}
                             }
                         }
                     }
@@ -377,14 +442,70 @@ impl LLMProvider for AnthropicProvider {
 
             // Send final finished chunk if stream ended without message_stop
             let _ = tx
-                .send(StreamChunk {
+                .send(Ok(StreamChunk {
                     content: None,
                     tool_calls: Vec::new(),
                     finished: true,
-                })
+                    usage,
+                }))
                 .await;
         });
 
         Ok(rx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_unparseable_tool_arguments_as_an_object() {
+        let provider = AnthropicProvider::new("key".to_string(), None, None);
+        let call = |id: &str, arguments: serde_json::Value| ToolCall {
+            id: id.to_string(),
+            name: "bash".to_string(),
+            arguments,
+        };
+        let assistant = Message {
+            role: "assistant".to_string(),
+            content: String::new(),
+            tool_call_id: None,
+            tool_calls: vec![
+                call("call_1", serde_json::json!({"command": "ls"})),
+                call("call_2", serde_json::json!("{\"command\": \"l")),
+            ],
+        };
+
+        let (_, converted) = provider.convert_messages(vec![assistant]);
+        let blocks = converted[0].content.as_array().unwrap();
+        assert_eq!(blocks[0]["input"], serde_json::json!({"command": "ls"}));
+        assert_eq!(blocks[1]["input"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn accumulates_usage_from_stream_events() {
+        let start: StreamEvent = serde_json::from_str(
+            r#"{"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-5-20250929","stop_reason":null,"usage":{"input_tokens":2095,"cache_creation_input_tokens":512,"cache_read_input_tokens":4096,"output_tokens":1}}}"#,
+        )
+        .unwrap();
+        let delta: StreamEvent = serde_json::from_str(
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":503}}"#,
+        )
+        .unwrap();
+
+        let mut usage = Usage::default();
+        start.message.unwrap().usage.unwrap().apply_to(&mut usage);
+        delta.usage.unwrap().apply_to(&mut usage);
+
+        assert_eq!(
+            usage,
+            Usage {
+                input_tokens: 2095,
+                output_tokens: 503,
+                cache_creation_input_tokens: 512,
+                cache_read_input_tokens: 4096,
+            }
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Typed failure from an LLM API, built from the HTTP status and error body
+#[derive(Debug, Error)]
+pub enum ProviderError {
+    #[error("Rate limited (HTTP 429): {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("Provider overloaded (HTTP {status}): {message}")]
+    Overloaded {
+        status: u16,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("Server error (HTTP {status}): {message}")]
+    Server {
+        status: u16,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("Authentication failed (HTTP {status}): {message}")]
+    Authentication { status: u16, message: String },
+
+    #[error("Invalid request (HTTP {status}): {message}")]
+    InvalidRequest { status: u16, message: String },
+
+    #[error("Connection error: {0}")]
+    Connection(String),
+
+    /// Reported in place of a response after a successful status (e.g. Ollama's
+    /// unknown model or out of memory); not transient, so not retried
+    #[error("Provider error: {0}")]
+    Response(String),
+}
+
+impl ProviderError {
+    /// Build an error from a non-success response
+    pub fn from_response(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let message = error_message(body);
+        let retry_after = parse_retry_after(headers);
+        let code = status.as_u16();
+
+        match code {
+            429 => ProviderError::RateLimited {
+                message,
+                retry_after,
+            },
+            // 529 is Anthropic's "overloaded"; 503 is the conventional equivalent
+            503 | 529 => ProviderError::Overloaded {
+                status: code,
+                message,
+                retry_after,
+            },
+            401 | 403 => ProviderError::Authentication {
+                status: code,
+                message,
+            },
+            408 | 500..=599 => ProviderError::Server {
+                status: code,
+                message,
+                retry_after,
+            },
+            _ => ProviderError::InvalidRequest {
+                status: code,
+                message,
+            },
+        }
+    }
+
+    /// Build an error from an error event sent after a successful status, classified
+    /// by its `error.type` (e.g. Anthropic's `overloaded_error`)
+    pub fn from_stream_event(body: &str) -> Self {
+        let error_type = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| v["error"]["type"].as_str().map(String::from))
+            .unwrap_or_default();
+        let status = match error_type.as_str() {
+            t if t.contains("overloaded") => 529,
+            t if t.contains("rate_limit") => 429,
+            t if t.contains("authentication") => 401,
+            t if t.contains("permission") => 403,
+            t if t.contains("invalid_request") || t.contains("not_found") => 400,
+            _ => 500,
+        };
+        Self::from_response(
+            StatusCode::from_u16(status).expect("valid status code"),
+            &HeaderMap::new(),
+            body,
+        )
+    }
+
+    /// Check a response's status, turning failures into a typed error
+    pub async fn check(response: reqwest::Response) -> Result<reqwest::Response, ProviderError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        Err(ProviderError::from_response(status, &headers, &body))
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ProviderError::RateLimited { .. }
+                | ProviderError::Overloaded { .. }
+                | ProviderError::Server { .. }
+                | ProviderError::Connection(_)
+        )
+    }
+
+    /// Delay requested by the server via retry-after headers, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderError::RateLimited { retry_after, .. }
+            | ProviderError::Overloaded { retry_after, .. }
+            | ProviderError::Server { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(err: reqwest::Error) -> Self {
+        ProviderError::Connection(err.to_string())
+    }
+}
+
+/// Pull `error.message` (OpenAI, Anthropic) or a plain `error` string (Ollama) out of
+/// an error body, falling back to the raw body
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| {
+            let error = v.get("error")?;
+            error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .or_else(|| error.as_str())
+                .map(String::from)
+        })
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// Parse `retry-after-ms` (OpenAI) or `retry-after` in seconds
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v >= 0.0)
+    };
+
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn classifies_anthropic_overloaded() {
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let err = ProviderError::from_response(
+            StatusCode::from_u16(529).unwrap(),
+            &HeaderMap::new(),
+            body,
+        );
+
+        assert!(matches!(err, ProviderError::Overloaded { status: 529, .. }));
+        assert!(err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "Provider overloaded (HTTP 529): Overloaded"
+        );
+    }
+
+    #[test]
+    fn reads_retry_after_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        let err = ProviderError::from_response(StatusCode::TOO_MANY_REQUESTS, &headers, "");
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+
+        headers.insert("retry-after-ms", HeaderValue::from_static("1500"));
+        let err = ProviderError::from_response(StatusCode::TOO_MANY_REQUESTS, &headers, "");
+        assert_eq!(err.retry_after(), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn classifies_stream_error_events() {
+        let err = ProviderError::from_stream_event(
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        );
+        assert!(matches!(err, ProviderError::Overloaded { status: 529, .. }));
+
+        let err = ProviderError::from_stream_event(
+            r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long"}}"#,
+        );
+        assert!(!err.is_retryable());
+        assert!(err.to_string().contains("prompt is too long"));
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let body = r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error","code":"invalid_api_key"}}"#;
+        let err = ProviderError::from_response(StatusCode::UNAUTHORIZED, &HeaderMap::new(), body);
+
+        assert!(matches!(err, ProviderError::Authentication { .. }));
+        assert!(!err.is_retryable());
+        assert!(err.to_string().contains("Incorrect API key provided"));
+    }
+}
@@ -1,5 +1,10 @@
 pub mod anthropic;
+pub mod error;
+pub mod ollama;
 pub mod openai;
+pub mod retry;
+
+pub use error::ProviderError;
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -24,24 +29,112 @@ pub struct ToolCall {
     pub arguments: serde_json::Value,
 }
 
+/// Parse streamed tool-call arguments. Empty means no arguments; invalid JSON (e.g.
+/// from a truncated stream) is kept as the raw string, which tools reject with an
+/// error the model can act on.
+pub fn parse_tool_arguments(raw: &str) -> serde_json::Value {
+    if raw.trim().is_empty() {
+        return serde_json::json!({});
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| serde_json::Value::String(raw.to_string()))
+}
+
+/// Tool-call arguments for APIs that require an object when a call is sent back in the
+/// history. Arguments kept as raw text are sent as `{}`; the tool result already tells
+/// the model they did not parse.
+pub fn arguments_object(arguments: &serde_json::Value) -> serde_json::Value {
+    if arguments.is_object() {
+        arguments.clone()
+    } else {
+        serde_json::json!({})
+    }
+}
+
+/// Splits a streamed response body into lines. Bytes are decoded only once their
+/// line is complete, so a multi-byte character split across HTTP chunks stays intact.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Add `chunk` and return the lines it completed, without their `\n`
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') else {
+            return Vec::new();
+        };
+        let complete: Vec<u8> = self.pending.drain(..=end).collect();
+        complete[..end]
+            .split(|&b| b == b'\n')
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect()
+    }
+}
+
+/// Token counts reported by the provider for a single completion request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamChunk {
     pub content: Option<String>,
     pub tool_calls: Vec<ToolCall>,
     pub finished: bool,
+    /// Set on the final chunk when the provider reported token usage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[async_trait]
 pub trait LLMProvider: Send + Sync {
+    /// Model name requests are sent to (used for pricing)
+    fn model(&self) -> &str;
+
     async fn chat_completion(
         &self,
         messages: Vec<Message>,
         tools: Option<Vec<serde_json::Value>>,
     ) -> Result<StreamChunk>;
 
+    /// Stream a completion. A failure part-way through the response (a dropped
+    /// connection, an error event) is sent as an `Err` item and ends the stream.
     async fn stream_completion(
         &self,
         messages: Vec<Message>,
         tools: Option<Vec<serde_json::Value>>,
-    ) -> Result<tokio::sync::mpsc::Receiver<StreamChunk>>;
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_buffer_keeps_characters_split_across_chunks() {
+        let text = "data: héllo\ndata: wörld\n".as_bytes();
+        let split = text.iter().position(|&b| b == 0xc3).unwrap() + 1;
+        let mut buffer = LineBuffer::default();
+
+        assert_eq!(buffer.push(&text[..split]), Vec::<String>::new());
+        assert_eq!(buffer.push(&text[split..18]), vec!["data: héllo"]);
+        assert_eq!(buffer.push(&text[18..]), vec!["data: wörld"]);
+        assert_eq!(buffer.push(b"\r\n"), vec!["\r"]);
+    }
 }
@@ -0,0 +1,475 @@
+use super::{
+    arguments_object, LLMProvider, LineBuffer, Message, ProviderError, StreamChunk, ToolCall, Usage,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+    /// For tool results: the name of the tool that produced them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaFunction {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+    stream: bool,
+}
+
+/// One NDJSON line of a /api/chat response (or the whole body when not streaming)
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: Option<OllamaResponseMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponseMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+/// Native Ollama provider speaking /api/chat. Unlike the OpenAI-compatible
+/// endpoint, this keeps tool calls intact for local models.
+pub struct OllamaProvider {
+    client: Client,
+    model: String,
+    base_url: String,
+}
+
+impl OllamaProvider {
+    pub fn new(model: Option<String>, base_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            model: model.unwrap_or_else(|| "llama3.1".to_string()),
+            base_url: base_url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "http://localhost:11434".to_string()),
+        }
+    }
+
+    fn convert_messages(&self, messages: Vec<Message>) -> Vec<OllamaMessage> {
+        // Ollama has no tool call ids; results are matched to calls by tool name
+        let mut tool_names: HashMap<String, String> = HashMap::new();
+
+        messages
+            .into_iter()
+            .map(|msg| {
+                for tc in &msg.tool_calls {
+                    tool_names.insert(tc.id.clone(), tc.name.clone());
+                }
+
+                match (msg.role.as_str(), &msg.tool_call_id) {
+                    ("user" | "tool", Some(tool_call_id)) => OllamaMessage {
+                        role: "tool".to_string(),
+                        content: msg.content,
+                        tool_calls: Vec::new(),
+                        tool_name: tool_names.get(tool_call_id).cloned(),
+                    },
+                    _ => OllamaMessage {
+                        role: msg.role,
+                        content: msg.content,
+                        tool_calls: msg
+                            .tool_calls
+                            .into_iter()
+                            .map(|tc| OllamaToolCall {
+                                function: OllamaFunction {
+                                    name: tc.name,
+                                    arguments: arguments_object(&tc.arguments),
+                                },
+                            })
+                            .collect(),
+                        tool_name: None,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn convert_tools(&self, tools: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
+        tools
+            .into_iter()
+            .filter_map(|tool| {
+                let name = tool.get("name")?.as_str()?;
+                let description = tool.get("description")?.as_str()?;
+                let parameters = tool.get("input_schema")?;
+
+                Some(json!({
+                    "type": "function",
+                    "function": {
+                        "name": name,
+                        "description": description,
+                        "parameters": parameters
+                    }
+                }))
+            })
+            .collect()
+    }
+
+    async fn send(&self, request: &OllamaRequest) -> Result<reqwest::Response> {
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(request)
+            .send()
+            .await
+            .map_err(ProviderError::from)
+            .context("Failed to send Ollama request")?;
+
+        ProviderError::check(response)
+            .await
+            .context("Ollama request failed")
+    }
+}
+
+/// Convert one response object into a chunk. Tool calls arrive whole, so they can be
+/// forwarded immediately; ids are generated since Ollama does not provide them.
+fn convert_response(response: OllamaChatResponse) -> StreamChunk {
+    let (content, tool_calls) = match response.message {
+        Some(message) => (
+            Some(message.content).filter(|c| !c.is_empty()),
+            message
+                .tool_calls
+                .into_iter()
+                .map(|tc| ToolCall {
+                    id: format!("call_{}", Uuid::new_v4().simple()),
+                    name: tc.function.name,
+                    arguments: if tc.function.arguments.is_null() {
+                        json!({})
+                    } else {
+                        tc.function.arguments
+                    },
+                })
+                .collect(),
+        ),
+        None => (None, Vec::new()),
+    };
+
+    let usage = if response.done {
+        Some(Usage {
+            input_tokens: response.prompt_eval_count.unwrap_or(0),
+            output_tokens: response.eval_count.unwrap_or(0),
+            ..Default::default()
+        })
+    } else {
+        None
+    };
+
+    StreamChunk {
+        content,
+        tool_calls,
+        finished: response.done,
+        usage,
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat_completion(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<StreamChunk> {
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            messages: self.convert_messages(messages),
+            tools: tools.map(|t| self.convert_tools(t)),
+            stream: false,
+        };
+
+        let response: OllamaChatResponse = self
+            .send(&request)
+            .await?
+            .json()
+            .await
+            .context("Failed to parse Ollama response")?;
+
+        if let Some(error) = response.error {
+            return Err(ProviderError::Response(error).into());
+        }
+
+        let mut chunk = convert_response(response);
+        chunk.finished = true;
+        Ok(chunk)
+    }
+
+    async fn stream_completion(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            messages: self.convert_messages(messages),
+            tools: tools.map(|t| self.convert_tools(t)),
+            stream: true,
+        };
+
+        let response = self.send(&request).await?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(100);
+
+        tokio::spawn(async move {
+            let mut stream = response.bytes_stream();
+            use futures::StreamExt;
+
+            // Buffer for incomplete NDJSON lines split across HTTP chunks
+            let mut line_buf = LineBuffer::default();
+
+            while let Some(chunk) = stream.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(Err(ProviderError::from(e).into())).await;
+                        return;
+                    }
+                };
+                for line in line_buf.push(&chunk) {
+                    let Ok(response) = serde_json::from_str::<OllamaChatResponse>(line.trim())
+                    else {
+                        continue;
+                    };
+                    if let Some(error) = response.error {
+                        let _ = tx.send(Err(ProviderError::Response(error).into())).await;
+                        return;
+                    }
+
+                    let chunk = convert_response(response);
+                    let finished = chunk.finished;
+                    if tx.send(Ok(chunk)).await.is_err() || finished {
+                        return;
+                    }
+                }
+            }
+
+            // Send final finished chunk if stream ended without done
+            let _ = tx
+                .send(Ok(StreamChunk {
+                    content: None,
+                    tool_calls: Vec::new(),
+                    finished: true,
+                    usage: None,
+                }))
+                .await;
+        });
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve one HTTP request with the given NDJSON body and return the request body received
+    async fn mock_server(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            // Read headers, then as many body bytes as Content-Length says
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            let header_end = loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+            };
+            let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+            let content_length: usize = headers
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length:"))
+                .map(|v| v.trim().parse().unwrap())
+                .unwrap_or(0);
+            while request.len() < header_end + content_length {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+
+            String::from_utf8_lossy(&request[header_end..]).to_string()
+        });
+
+        (url, handle)
+    }
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_call_id: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn error_lines_end_the_stream_with_their_message() {
+        let (url, _server) = mock_server(concat!(
+            r#"{"model":"qwen3:8b","message":{"role":"assistant","content":"Let"},"done":false}"#,
+            "\n",
+            r#"{"error":"model requires more system memory (9.2 GiB) than is available (4.1 GiB)"}"#,
+            "\n",
+        ))
+        .await;
+        let provider = OllamaProvider::new(Some("qwen3:8b".to_string()), Some(url));
+        let mut rx = provider
+            .stream_completion(vec![message("user", "hi")], None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rx.recv().await.unwrap().unwrap().content.as_deref(),
+            Some("Let")
+        );
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProviderError>(),
+            Some(ProviderError::Response(message)) if message.contains("more system memory")
+        ));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn streams_text_and_tool_calls_from_ndjson() {
+        let (url, server) = mock_server(include_str!(
+            "../../tests/fixtures/ollama_chat_tool_call.ndjson"
+        ))
+        .await;
+        let provider = OllamaProvider::new(Some("qwen3:8b".to_string()), Some(url));
+
+        let tools = vec![json!({
+            "name": "bash",
+            "description": "Run a command",
+            "input_schema": {"type": "object", "properties": {"command": {"type": "string"}}}
+        })];
+        let mut rx = provider
+            .stream_completion(vec![message("user", "list files")], Some(tools))
+            .await
+            .unwrap();
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            let chunk = chunk.unwrap();
+            let finished = chunk.finished;
+            chunks.push(chunk);
+            if finished {
+                break;
+            }
+        }
+
+        let text: String = chunks.iter().filter_map(|c| c.content.clone()).collect();
+        assert_eq!(text, "I'll list the files.");
+
+        let calls: Vec<&ToolCall> = chunks.iter().flat_map(|c| &c.tool_calls).collect();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "bash");
+        assert_eq!(calls[0].arguments, json!({"command": "ls -la"}));
+        assert!(!calls[0].id.is_empty());
+
+        let last = chunks.last().unwrap();
+        assert!(last.finished);
+        assert_eq!(
+            last.usage,
+            Some(Usage {
+                input_tokens: 312,
+                output_tokens: 41,
+                ..Default::default()
+            })
+        );
+
+        let request: serde_json::Value = serde_json::from_str(&server.await.unwrap()).unwrap();
+        assert_eq!(request["model"], "qwen3:8b");
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["tools"][0]["type"], "function");
+        assert_eq!(request["tools"][0]["function"]["name"], "bash");
+    }
+
+    #[test]
+    fn sends_tool_results_with_tool_name() {
+        let provider = OllamaProvider::new(None, None);
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls.push(ToolCall {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            arguments: json!({"command": "ls"}),
+        });
+        let mut result = message("user", "Cargo.toml\nsrc");
+        result.tool_call_id = Some("call_1".to_string());
+
+        let converted = provider.convert_messages(vec![assistant, result]);
+        let value = serde_json::to_value(&converted).unwrap();
+
+        assert_eq!(value[0]["tool_calls"][0]["function"]["name"], "bash");
+        assert_eq!(
+            value[0]["tool_calls"][0]["function"]["arguments"],
+            json!({"command": "ls"})
+        );
+        assert_eq!(value[1]["role"], "tool");
+        assert_eq!(value[1]["tool_name"], "bash");
+        assert_eq!(value[1]["content"], "Cargo.toml\nsrc");
+    }
+
+    #[test]
+    fn replays_unparseable_tool_arguments_as_an_object() {
+        let provider = OllamaProvider::new(None, None);
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls.push(ToolCall {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            arguments: json!("{\"command\": \"l"),
+        });
+
+        let value = serde_json::to_value(provider.convert_messages(vec![assistant])).unwrap();
+        assert_eq!(
+            value[0]["tool_calls"][0]["function"]["arguments"],
+            json!({})
+        );
+    }
+}
@@ -1,35 +1,91 @@
-use super::{LLMProvider, Message, StreamChunk, ToolCall};
+use super::{
+    parse_tool_arguments, LLMProvider, LineBuffer, Message, ProviderError, StreamChunk, ToolCall,
+    Usage,
+};
 use anyhow::{Context, Result};
-use async_openai::{
-    types::{
-        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-        ChatCompletionRequestUserMessageArgs, ChatCompletionTool, ChatCompletionToolType,
-        CreateChatCompletionRequestArgs, FunctionObjectArgs,
-    },
-    Client,
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionStreamOptions, ChatCompletionTool,
+    ChatCompletionToolType, CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+    CreateChatCompletionResponse, CreateChatCompletionStreamResponse, FunctionCall,
+    FunctionObjectArgs,
 };
 use async_trait::async_trait;
 use futures::StreamExt;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 
+// async-openai is only used for its request/response types. Requests are sent with
+// reqwest directly so that HTTP status and retry-after headers are visible.
 pub struct OpenAIProvider {
-    client: Client<async_openai::config::OpenAIConfig>,
+    client: Client,
+    api_key: String,
     model: String,
+    base_url: String,
 }
 
 impl OpenAIProvider {
     pub fn new(api_key: String, model: Option<String>, base_url: Option<String>) -> Self {
-        let mut config = async_openai::config::OpenAIConfig::new().with_api_key(api_key);
-
-        if let Some(url) = base_url {
-            config = config.with_api_base(url);
+        Self {
+            client: Client::new(),
+            api_key,
+            model: model.unwrap_or_else(|| "gpt-4o".to_string()),
+            base_url: base_url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
         }
+    }
 
-        let client = Client::with_config(config);
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<serde_json::Value>>,
+        stream: bool,
+    ) -> Result<CreateChatCompletionRequest> {
+        let converted_messages = self.convert_messages(messages);
 
-        Self {
-            client,
-            model: model.unwrap_or_else(|| "gpt-4o".to_string()),
+        let mut request = CreateChatCompletionRequestArgs::default()
+            .model(&self.model)
+            .messages(converted_messages)
+            .to_owned();
+
+        if let Some(tool_defs) = tools {
+            let converted_tools = self.convert_tools(tool_defs);
+            if !converted_tools.is_empty() {
+                request = request.tools(converted_tools).to_owned();
+            }
         }
+
+        if stream {
+            // Ask for a trailing usage chunk so token counts can be reported
+            request = request
+                .stream(true)
+                .stream_options(ChatCompletionStreamOptions {
+                    include_usage: true,
+                })
+                .to_owned();
+        }
+
+        Ok(request.build()?)
+    }
+
+    async fn send(&self, request: &CreateChatCompletionRequest) -> Result<reqwest::Response> {
+        let response = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(request)
+            .send()
+            .await
+            .map_err(ProviderError::from)
+            .context("Failed to send OpenAI request")?;
+
+        ProviderError::check(response)
+            .await
+            .context("OpenAI request failed")
     }
 
     fn convert_messages(&self, messages: Vec<Message>) -> Vec<ChatCompletionRequestMessage> {
@@ -42,12 +98,46 @@ impl OpenAIProvider {
                         .build()
                         .unwrap(),
                 ),
-                "user" => ChatCompletionRequestMessage::User(
-                    ChatCompletionRequestUserMessageArgs::default()
-                        .content(msg.content)
-                        .build()
-                        .unwrap(),
-                ),
+                "assistant" => {
+                    let mut args = ChatCompletionRequestAssistantMessageArgs::default();
+
+                    // OpenAI expects null content (not "") on pure tool-call turns
+                    if !msg.content.is_empty() || msg.tool_calls.is_empty() {
+                        args.content(msg.content);
+                    }
+
+                    if !msg.tool_calls.is_empty() {
+                        let tool_calls: Vec<ChatCompletionMessageToolCall> = msg
+                            .tool_calls
+                            .into_iter()
+                            .map(|tc| ChatCompletionMessageToolCall {
+                                id: tc.id,
+                                r#type: ChatCompletionToolType::Function,
+                                function: FunctionCall {
+                                    name: tc.name,
+                                    // Invalid arguments go back exactly as received
+                                    arguments: match tc.arguments {
+                                        serde_json::Value::String(raw) => raw,
+                                        arguments => arguments.to_string(),
+                                    },
+                                },
+                            })
+                            .collect();
+                        args.tool_calls(tool_calls);
+                    }
+
+                    ChatCompletionRequestMessage::Assistant(args.build().unwrap())
+                }
+                // Tool results are stored as user messages carrying the tool_call_id
+                "user" | "tool" if msg.tool_call_id.is_some() => {
+                    ChatCompletionRequestMessage::Tool(
+                        ChatCompletionRequestToolMessageArgs::default()
+                            .content(msg.content)
+                            .tool_call_id(msg.tool_call_id.unwrap_or_default())
+                            .build()
+                            .unwrap(),
+                    )
+                }
                 _ => ChatCompletionRequestMessage::User(
                     ChatCompletionRequestUserMessageArgs::default()
                         .content(msg.content)
@@ -80,35 +170,169 @@ impl OpenAIProvider {
     }
 }
 
+/// Assembles streamed tool-call fragments. OpenAI sends the id and name in the
+/// first delta for each call and then the arguments in pieces, all keyed by `index`.
+#[derive(Default)]
+struct ToolCallAccumulator {
+    calls: BTreeMap<i32, (String, String, String)>, // index -> (id, name, accumulated_json)
+}
+
+impl ToolCallAccumulator {
+    fn push(&mut self, fragments: &[ChatCompletionMessageToolCallChunk]) {
+        for fragment in fragments {
+            let entry = self.calls.entry(fragment.index).or_default();
+
+            if let Some(id) = &fragment.id {
+                entry.0.push_str(id);
+            }
+            if let Some(function) = &fragment.function {
+                if let Some(name) = &function.name {
+                    entry.1.push_str(name);
+                }
+                if let Some(arguments) = &function.arguments {
+                    entry.2.push_str(arguments);
+                }
+            }
+        }
+    }
+
+    /// Drain all accumulated calls in index order
+    fn finish(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.calls)
+            .into_values()
+            .map(|(id, name, json_str)| ToolCall {
+                id,
+                name,
+                arguments: parse_tool_arguments(&json_str),
+            })
+            .collect()
+    }
+}
+
+/// State carried across the responses of one stream
+#[derive(Default)]
+struct StreamState {
+    tool_calls: ToolCallAccumulator,
+    usage: Option<Usage>,
+}
+
+impl StreamState {
+    /// Final chunk sent when the stream ends, with any pending tool calls and the usage totals
+    fn finish(&mut self) -> StreamChunk {
+        StreamChunk {
+            content: None,
+            tool_calls: self.tool_calls.finish(),
+            finished: true,
+            usage: self.usage.take(),
+        }
+    }
+}
+
+/// Token usage as the API reports it. async-openai's `CompletionUsage` has no
+/// `prompt_tokens_details`, which carries the cached part of the prompt.
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: Option<u64>,
+}
+
+/// An async-openai response type with its usage parsed as `OpenAIUsage`
+#[derive(Debug, Deserialize)]
+struct WithUsage<T> {
+    #[serde(flatten)]
+    response: T,
+    usage: Option<OpenAIUsage>,
+}
+
+/// OpenAI counts cached tokens as part of the prompt; they are billed separately
+fn convert_usage(usage: &OpenAIUsage) -> Usage {
+    let cached = usage
+        .prompt_tokens_details
+        .as_ref()
+        .and_then(|d| d.cached_tokens)
+        .unwrap_or(0);
+    Usage {
+        input_tokens: usage.prompt_tokens.saturating_sub(cached),
+        output_tokens: usage.completion_tokens,
+        cache_read_input_tokens: cached,
+        ..Default::default()
+    }
+}
+
+/// Turn one streamed response into the chunk to forward, if any.
+/// Tool calls are only emitted once the choice reports a finish_reason. The
+/// usage-only response that follows it (with `include_usage`) has no choices.
+fn process_stream_response(
+    state: &mut StreamState,
+    response: WithUsage<CreateChatCompletionStreamResponse>,
+) -> Option<StreamChunk> {
+    if let Some(usage) = &response.usage {
+        state.usage = Some(convert_usage(usage));
+    }
+
+    let choice = response.response.choices.into_iter().next()?;
+
+    if let Some(fragments) = &choice.delta.tool_calls {
+        state.tool_calls.push(fragments);
+    }
+
+    let finish_reason = choice.finish_reason.is_some();
+    if choice.delta.content.is_none() && !finish_reason {
+        return None;
+    }
+
+    Some(StreamChunk {
+        content: choice.delta.content,
+        tool_calls: if finish_reason {
+            state.tool_calls.finish()
+        } else {
+            Vec::new()
+        },
+        finished: false,
+        usage: None,
+    })
+}
+
+/// Handle one `data:` payload. Errors reported inside the stream (after HTTP 200)
+/// become provider errors; other payloads that do not parse are skipped.
+fn parse_stream_data(
+    state: &mut StreamState,
+    data: &str,
+) -> Result<Option<StreamChunk>, ProviderError> {
+    match serde_json::from_str::<WithUsage<CreateChatCompletionStreamResponse>>(data) {
+        Ok(response) => Ok(process_stream_response(state, response)),
+        Err(_) if data.contains("\"error\"") => Err(ProviderError::from_stream_event(data)),
+        Err(_) => Ok(None),
+    }
+}
+
 #[async_trait]
 impl LLMProvider for OpenAIProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
     async fn chat_completion(
         &self,
         messages: Vec<Message>,
         tools: Option<Vec<serde_json::Value>>,
     ) -> Result<StreamChunk> {
-        let converted_messages = self.convert_messages(messages);
-
-        let mut request = CreateChatCompletionRequestArgs::default()
-            .model(&self.model)
-            .messages(converted_messages)
-            .to_owned();
-
-        if let Some(tool_defs) = tools {
-            let converted_tools = self.convert_tools(tool_defs);
-            if !converted_tools.is_empty() {
-                request = request.tools(converted_tools).to_owned();
-            }
-        }
+        let request = self.build_request(messages, tools, false)?;
 
-        let request = request.build()?;
-
-        let response = self
-            .client
-            .chat()
-            .create(request)
+        let WithUsage { response, usage }: WithUsage<CreateChatCompletionResponse> = self
+            .send(&request)
+            .await?
+            .json()
             .await
-            .context("Failed to get OpenAI completion")?;
+            .context("Failed to parse OpenAI response")?;
 
         let choice = response.choices.first().context("No choices in response")?;
 
@@ -123,8 +347,7 @@ impl LLMProvider for OpenAIProvider {
                     .map(|call| ToolCall {
                         id: call.id.clone(),
                         name: call.function.name.clone(),
-                        arguments: serde_json::from_str(&call.function.arguments)
-                            .unwrap_or_default(),
+                        arguments: parse_tool_arguments(&call.function.arguments),
                     })
                     .collect()
             })
@@ -134,6 +357,7 @@ impl LLMProvider for OpenAIProvider {
             content,
             tool_calls,
             finished: true,
+            usage: usage.as_ref().map(convert_usage),
         })
     }
 
@@ -141,90 +365,252 @@ impl LLMProvider for OpenAIProvider {
         &self,
         messages: Vec<Message>,
         tools: Option<Vec<serde_json::Value>>,
-    ) -> Result<tokio::sync::mpsc::Receiver<StreamChunk>> {
-        let converted_messages = self.convert_messages(messages);
-
-        let mut request = CreateChatCompletionRequestArgs::default()
-            .model(&self.model)
-            .messages(converted_messages)
-            .to_owned();
-
-        if let Some(tool_defs) = tools {
-            let converted_tools = self.convert_tools(tool_defs);
-            if !converted_tools.is_empty() {
-                request = request.tools(converted_tools).to_owned();
-            }
-        }
-
-        let request = request.build()?;
-
-        let mut stream = self
-            .client
-            .chat()
-            .create_stream(request)
-            .await
-            .context("Failed to create OpenAI stream")?;
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<StreamChunk>>> {
+        let request = self.build_request(messages, tools, true)?;
+        let response = self.send(&request).await?;
 
         let (tx, rx) = tokio::sync::mpsc::channel(100);
 
         tokio::spawn(async move {
-            while let Some(result) = stream.next().await {
-                match result {
-                    Ok(response) => {
-                        if let Some(choice) = response.choices.first() {
-                            let content = choice.delta.content.clone();
-                            let tool_calls = choice
-                                .delta
-                                .tool_calls
-                                .as_ref()
-                                .map(|calls| {
-                                    calls
-                                        .iter()
-                                        .filter_map(|call| {
-                                            Some(ToolCall {
-                                                id: call.id.clone()?,
-                                                name: call.function.as_ref()?.name.clone()?,
-                                                arguments: serde_json::from_str(
-                                                    &call.function.as_ref()?.arguments.clone()?,
-                                                )
-                                                .unwrap_or_default(),
-                                            })
-                                        })
-                                        .collect()
-                                })
-                                .unwrap_or_default();
-
-                            let finished = choice.finish_reason.is_some();
-
-                            let chunk = StreamChunk {
-                                content,
-                                tool_calls,
-                                finished,
-                            };
-
-                            if tx.send(chunk).await.is_err() {
-                                break;
-                            }
+            let mut stream = response.bytes_stream();
+            let mut state = StreamState::default();
+            // Buffer for incomplete SSE lines split across HTTP chunks
+            let mut line_buf = LineBuffer::default();
 
-                            if finished {
-                                break;
+            'stream: while let Some(chunk) = stream.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(Err(ProviderError::from(e).into())).await;
+                        return;
+                    }
+                };
+                for line in line_buf.push(&chunk) {
+                    let Some(data) = line.trim_end_matches('\r').strip_prefix("data: ") else {
+                        continue;
+                    };
+                    if data == "[DONE]" {
+                        break 'stream;
+                    }
+
+                    match parse_stream_data(&mut state, data) {
+                        Ok(None) => {}
+                        Ok(Some(chunk)) => {
+                            if tx.send(Ok(chunk)).await.is_err() {
+                                return;
                             }
                         }
+                        Err(e) => {
+                            let _ = tx.send(Err(e.into())).await;
+                            return;
+                        }
                     }
-                    Err(_) => break,
                 }
             }
 
-            // Send final finished chunk if stream ended without finish_reason
-            let _ = tx
-                .send(StreamChunk {
-                    content: None,
-                    tool_calls: Vec::new(),
-                    finished: true,
-                })
-                .await;
+            // The usage chunk arrives after finish_reason, so only finish once the stream ends
+            let _ = tx.send(Ok(state.finish())).await;
         });
 
         Ok(rx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider() -> OpenAIProvider {
+        OpenAIProvider::new("test-key".to_string(), None, None)
+    }
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_call_id: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn converts_tool_round_trip() {
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls.push(ToolCall {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            arguments: json!({"command": "ls"}),
+        });
+        let mut tool_result = message("user", "Command executed successfully");
+        tool_result.tool_call_id = Some("call_1".to_string());
+
+        let converted = provider().convert_messages(vec![
+            message("system", "be helpful"),
+            message("user", "list files"),
+            assistant,
+            tool_result,
+        ]);
+        let value = serde_json::to_value(&converted).unwrap();
+
+        assert_eq!(value[0]["role"], "system");
+        assert_eq!(value[1]["role"], "user");
+        assert_eq!(value[2]["role"], "assistant");
+        assert!(value[2]["content"].is_null());
+        assert_eq!(value[2]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(value[2]["tool_calls"][0]["type"], "function");
+        assert_eq!(value[2]["tool_calls"][0]["function"]["name"], "bash");
+        let arguments: serde_json::Value = serde_json::from_str(
+            value[2]["tool_calls"][0]["function"]["arguments"]
+                .as_str()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(arguments, json!({"command": "ls"}));
+        assert_eq!(value[3]["role"], "tool");
+        assert_eq!(value[3]["tool_call_id"], "call_1");
+        assert_eq!(value[3]["content"], "Command executed successfully");
+    }
+
+    /// Replay a recorded SSE stream through the same path the spawned stream task uses
+    fn replay(fixture: &str) -> Vec<StreamChunk> {
+        let mut state = StreamState::default();
+        let mut chunks: Vec<StreamChunk> = fixture
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| {
+                serde_json::from_str::<WithUsage<CreateChatCompletionStreamResponse>>(data).unwrap()
+            })
+            .filter_map(|response| process_stream_response(&mut state, response))
+            .collect();
+        chunks.push(state.finish());
+        chunks
+    }
+
+    fn tool_calls(chunks: &[StreamChunk]) -> Vec<ToolCall> {
+        chunks.iter().flat_map(|c| c.tool_calls.clone()).collect()
+    }
+
+    #[test]
+    fn assembles_fragmented_parallel_tool_calls() {
+        let chunks = replay(include_str!(
+            "../../tests/fixtures/openai_stream_tool_calls.sse"
+        ));
+
+        assert!(chunks.last().unwrap().finished);
+        assert_eq!(
+            chunks.iter().filter(|c| !c.tool_calls.is_empty()).count(),
+            1
+        );
+
+        let calls = tool_calls(&chunks);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_grep");
+        assert_eq!(calls[0].name, "file_search");
+        assert_eq!(
+            calls[0].arguments,
+            json!({"operation": "grep", "pattern": "TODO"})
+        );
+        assert_eq!(calls[1].id, "call_read");
+        assert_eq!(calls[1].name, "edit_file");
+        assert_eq!(
+            calls[1].arguments,
+            json!({"operation": "read_file", "file_path": "src/main.rs"})
+        );
+    }
+
+    #[test]
+    fn keeps_truncated_arguments_as_raw_text() {
+        // The stream skips from the first call's opening fragments to the finish reason
+        let fixture = include_str!("../../tests/fixtures/openai_stream_tool_calls.sse");
+        let lines: Vec<&str> = fixture.lines().collect();
+        let truncated = [&lines[..6], &lines[14..]].concat().join("\n");
+
+        let calls = tool_calls(&replay(&truncated));
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].arguments, json!("{\"operation\": \"grep\", "));
+
+        // Sent back exactly as received, not re-encoded as a JSON string
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls = calls;
+        let converted = provider().convert_messages(vec![assistant]);
+        let value = serde_json::to_value(&converted).unwrap();
+        assert_eq!(
+            value[0]["tool_calls"][0]["function"]["arguments"],
+            "{\"operation\": \"grep\", "
+        );
+    }
+
+    #[test]
+    fn error_payloads_in_the_stream_become_provider_errors() {
+        let mut state = StreamState::default();
+        let err = parse_stream_data(
+            &mut state,
+            r#"{"error":{"message":"The server had an error","type":"server_error"}}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ProviderError::Server { status: 500, .. }));
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn streams_text_before_tool_calls() {
+        let chunks = replay(include_str!("../../tests/fixtures/openai_stream_text.sse"));
+
+        let text: String = chunks.iter().filter_map(|c| c.content.clone()).collect();
+        assert_eq!(text, "Let me check.");
+
+        let calls = tool_calls(&chunks);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "bash");
+        assert_eq!(calls[0].arguments, json!({}));
+    }
+
+    #[test]
+    fn reports_usage_on_final_chunk() {
+        let chunks = replay(include_str!("../../tests/fixtures/openai_stream_text.sse"));
+
+        let last = chunks.last().unwrap();
+        assert!(last.finished);
+        assert_eq!(
+            last.usage,
+            Some(Usage {
+                input_tokens: 496,
+                output_tokens: 18,
+                cache_read_input_tokens: 1024,
+                ..Default::default()
+            })
+        );
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.usage.is_none()));
+    }
+
+    #[test]
+    fn flushes_pending_calls_without_finish_reason() {
+        let mut state = StreamState::default();
+        let fixture = include_str!("../../tests/fixtures/openai_stream_tool_calls.sse");
+        for data in fixture
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| !data.contains("finish_reason\":\"tool_calls") && *data != "[DONE]")
+        {
+            let response = serde_json::from_str(data).unwrap();
+            assert!(process_stream_response(&mut state, response).is_none());
+        }
+
+        let last = state.finish();
+        assert!(last.finished);
+        assert_eq!(last.tool_calls.len(), 2);
+        assert!(state.finish().tool_calls.is_empty());
+    }
+
+    #[test]
+    fn keeps_plain_assistant_text() {
+        let converted = provider().convert_messages(vec![message("assistant", "done")]);
+        let value = serde_json::to_value(&converted).unwrap();
+
+        assert_eq!(value[0]["role"], "assistant");
+        assert_eq!(value[0]["content"], "done");
+        assert!(value[0].get("tool_calls").is_none());
+    }
+}
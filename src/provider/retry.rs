@@ -0,0 +1,337 @@
+use super::{LLMProvider, Message, ProviderError, StreamChunk};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+/// Retry settings for provider requests (the `retry` section of the config file)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Total attempts per request, including the first one
+    pub max_attempts: u32,
+    /// Give up once this much time has passed since the first attempt
+    pub deadline_secs: u64,
+    /// Backoff before the first retry; doubled on every further attempt
+    pub initial_delay_ms: u64,
+    /// Upper bound for a single backoff delay
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            deadline_secs: 120,
+            initial_delay_ms: 1000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Exponential backoff with jitter: a random delay between half and all of
+    /// `initial * 2^(attempt - 1)`, capped at `max_delay_ms`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .initial_delay_ms
+            .saturating_mul(1u64 << (attempt - 1).min(20))
+            .min(self.max_delay_ms);
+        // uuid v4 is already a dependency and is a cheap source of randomness
+        let jitter = (Uuid::new_v4().as_u128() % 1000) as f64 / 1000.0;
+        Duration::from_millis((exp as f64 * (0.5 + jitter / 2.0)) as u64)
+    }
+}
+
+/// A failed request that is about to be retried
+#[derive(Debug, Clone)]
+pub struct RetryNotice {
+    /// Why the request failed
+    pub reason: String,
+    pub delay: Duration,
+    /// The attempt that follows the delay
+    pub attempt: u32,
+    pub max_attempts: u32,
+}
+
+/// Wraps any provider and retries rate-limited, overloaded and transient failures
+pub struct RetryingProvider {
+    inner: Box<dyn LLMProvider>,
+    config: RetryConfig,
+    notices: Option<UnboundedSender<RetryNotice>>,
+}
+
+impl RetryingProvider {
+    pub fn new(inner: Box<dyn LLMProvider>, config: RetryConfig) -> Self {
+        Self {
+            inner,
+            config,
+            notices: None,
+        }
+    }
+
+    /// Send a notice before every retry to the returned receiver
+    pub fn notices(&mut self) -> UnboundedReceiver<RetryNotice> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.notices = Some(tx);
+        rx
+    }
+
+    async fn retry<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let started = Instant::now();
+        let deadline = Duration::from_secs(self.config.deadline_secs);
+        let mut attempt = 1;
+
+        loop {
+            let err = match op().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            let Some(provider_err) = err.downcast_ref::<ProviderError>() else {
+                return Err(err);
+            };
+            if !provider_err.is_retryable() || attempt >= self.config.max_attempts {
+                return Err(err);
+            }
+
+            let delay = provider_err
+                .retry_after()
+                .unwrap_or_else(|| self.config.backoff(attempt));
+            if started.elapsed() + delay > deadline {
+                return Err(err);
+            }
+
+            if let Some(notices) = &self.notices {
+                let _ = notices.send(RetryNotice {
+                    reason: provider_err.to_string(),
+                    delay,
+                    attempt: attempt + 1,
+                    max_attempts: self.config.max_attempts,
+                });
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl LLMProvider for RetryingProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn chat_completion(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<StreamChunk> {
+        self.retry(|| self.inner.chat_completion(messages.clone(), tools.clone()))
+            .await
+    }
+
+    async fn stream_completion(
+        &self,
+        messages: Vec<Message>,
+        tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<Receiver<Result<StreamChunk>>> {
+        self.retry(|| async {
+            let mut rx = self
+                .inner
+                .stream_completion(messages.clone(), tools.clone())
+                .await?;
+            // An error before anything was streamed (e.g. overloaded right after the
+            // response started) is retried like a failed request
+            match rx.recv().await {
+                Some(Err(err)) => Err(err),
+                Some(first) => Ok(prepend(first, rx)),
+                None => Ok(rx),
+            }
+        })
+        .await
+    }
+}
+
+/// A stream that yields `first` and then everything from `rest`
+fn prepend(
+    first: Result<StreamChunk>,
+    mut rest: Receiver<Result<StreamChunk>>,
+) -> Receiver<Result<StreamChunk>> {
+    let (tx, rx) = mpsc::channel(100);
+    tokio::spawn(async move {
+        if tx.send(first).await.is_err() {
+            return;
+        }
+        loop {
+            let item = tokio::select! {
+                _ = tx.closed() => return,
+                item = rest.recv() => item,
+            };
+            let Some(item) = item else { return };
+            if tx.send(item).await.is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Fails with the given errors in order, then succeeds
+    struct FlakyProvider {
+        calls: Arc<AtomicU32>,
+        failures: Vec<fn() -> ProviderError>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for FlakyProvider {
+        fn model(&self) -> &str {
+            "flaky"
+        }
+
+        async fn chat_completion(
+            &self,
+            _messages: Vec<Message>,
+            _tools: Option<Vec<serde_json::Value>>,
+        ) -> Result<StreamChunk> {
+            self.next()
+        }
+
+        /// Failures arrive as the first item of a stream that opened fine
+        async fn stream_completion(
+            &self,
+            _messages: Vec<Message>,
+            _tools: Option<Vec<serde_json::Value>>,
+        ) -> Result<Receiver<Result<StreamChunk>>> {
+            let (tx, rx) = mpsc::channel(1);
+            tx.send(self.next()).await?;
+            Ok(rx)
+        }
+    }
+
+    impl FlakyProvider {
+        fn next(&self) -> Result<StreamChunk> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) as usize;
+            if let Some(failure) = self.failures.get(call) {
+                return Err(failure().into());
+            }
+            Ok(StreamChunk {
+                content: Some("ok".to_string()),
+                tool_calls: Vec::new(),
+                finished: true,
+                usage: None,
+            })
+        }
+    }
+
+    fn fast_config(max_attempts: u32) -> RetryConfig {
+        RetryConfig {
+            max_attempts,
+            deadline_secs: 10,
+            initial_delay_ms: 1,
+            max_delay_ms: 5,
+        }
+    }
+
+    fn overloaded() -> ProviderError {
+        ProviderError::Overloaded {
+            status: 529,
+            message: "Overloaded".to_string(),
+            retry_after: None,
+        }
+    }
+
+    fn unauthorized() -> ProviderError {
+        ProviderError::Authentication {
+            status: 401,
+            message: "bad key".to_string(),
+        }
+    }
+
+    fn provider(failures: Vec<fn() -> ProviderError>, config: RetryConfig) -> RetryingProvider {
+        RetryingProvider::new(
+            Box::new(FlakyProvider {
+                calls: Arc::new(AtomicU32::new(0)),
+                failures,
+            }),
+            config,
+        )
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let mut provider = provider(vec![overloaded, overloaded], fast_config(3));
+        let mut notices = provider.notices();
+        let chunk = provider.chat_completion(Vec::new(), None).await.unwrap();
+        assert_eq!(chunk.content.as_deref(), Some("ok"));
+
+        let attempts: Vec<(u32, u32)> = std::iter::from_fn(|| notices.try_recv().ok())
+            .map(|notice| (notice.attempt, notice.max_attempts))
+            .collect();
+        assert_eq!(attempts, vec![(2, 3), (3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn retries_errors_at_the_start_of_a_stream() {
+        let provider = provider(vec![overloaded], fast_config(3));
+        let mut rx = provider.stream_completion(Vec::new(), None).await.unwrap();
+        let chunk = rx.recv().await.unwrap().unwrap();
+        assert_eq!(chunk.content.as_deref(), Some("ok"));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let provider = provider(vec![overloaded, overloaded, overloaded], fast_config(3));
+        let err = provider
+            .chat_completion(Vec::new(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProviderError>(),
+            Some(ProviderError::Overloaded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = RetryingProvider::new(
+            Box::new(FlakyProvider {
+                calls: calls.clone(),
+                failures: vec![unauthorized],
+            }),
+            fast_config(5),
+        );
+
+        assert!(provider.chat_completion(Vec::new(), None).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let config = RetryConfig {
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+            ..Default::default()
+        };
+
+        let first = config.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let later = config.backoff(10);
+        assert!(later >= Duration::from_millis(500) && later <= Duration::from_millis(1000));
+    }
+}
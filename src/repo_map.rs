@@ -0,0 +1,359 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::compaction::TokenEstimator;
+use crate::tools::code_symbols::{file_identifiers, file_symbols, is_supported, MAX_FILE_BYTES};
+use crate::tools::file_search::walker;
+
+/// Bump when the cached data changes shape, so old caches are rebuilt
+const CACHE_VERSION: u32 = 1;
+
+/// Files indexed at most; later files (in path order) are left out
+const MAX_FILES: usize = 5000;
+
+/// Definitions listed per file at most
+const MAX_SYMBOLS_PER_FILE: usize = 12;
+
+/// Repository map settings (the `repo_map` section of the config file)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RepoMapConfig {
+    /// Append a map of the session directory to the system prompt
+    pub enabled: bool,
+    /// Token budget for the map; lower-ranked files are dropped to fit
+    pub budget_tokens: usize,
+}
+
+impl Default for RepoMapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            budget_tokens: 1024,
+        }
+    }
+}
+
+/// One file's entry in the cache, reused while its mtime and size are unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFile {
+    modified: SystemTime,
+    size: u64,
+    /// Top-level definitions as "kind name", in file order
+    symbols: Vec<String>,
+    /// Identifiers used in the file, for ranking
+    identifiers: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    version: u32,
+    /// Keyed by path relative to the root, with '/' separators
+    files: BTreeMap<String, CachedFile>,
+}
+
+/// A ranked summary of the source files under a directory and their top-level
+/// definitions, for the system prompt
+#[derive(Debug)]
+pub struct RepoMap {
+    pub text: String,
+    /// Files listed in `text`
+    pub shown: usize,
+    /// Files with definitions
+    pub total: usize,
+    pub tokens: usize,
+}
+
+/// Build the map for `root`, re-parsing only files changed since the cache in
+/// `cache_dir` was written
+pub fn build(
+    root: &Path,
+    cache_dir: &Path,
+    budget_tokens: usize,
+    estimator: &TokenEstimator,
+) -> Result<RepoMap> {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let cache_path = cache_dir.join(format!("{:016x}.json", fnv1a(&root)));
+    let cache = refresh(&root, load(&cache_path))?;
+    save(&cache_path, &cache)?;
+    Ok(render(&rank(&cache), budget_tokens, estimator))
+}
+
+fn load(path: &Path) -> Cache {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str::<Cache>(&data).ok())
+        .filter(|cache| cache.version == CACHE_VERSION)
+        .unwrap_or_default()
+}
+
+fn save(path: &Path, cache: &Cache) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+    }
+    fs::write(path, serde_json::to_string(cache)?)
+        .with_context(|| format!("Failed to write repo map cache '{}'", path.display()))
+}
+
+/// Stable across builds, unlike `DefaultHasher`, so cache file names do not change
+fn fnv1a(path: &Path) -> u64 {
+    path.to_string_lossy()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// The current files under `root`, taking unchanged entries from `cache`. Deleted
+/// files drop out.
+fn refresh(root: &Path, mut cache: Cache) -> Result<Cache> {
+    let mut files = BTreeMap::new();
+    for entry in walker(root, None)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !is_supported(path) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() || metadata.len() > MAX_FILE_BYTES {
+            continue;
+        }
+        if files.len() == MAX_FILES {
+            break;
+        }
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let key = relative.to_string_lossy().replace('\\', "/");
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let cached = cache
+            .files
+            .remove(&key)
+            .filter(|c| c.modified == modified && c.size == metadata.len());
+        let file = match cached {
+            Some(file) => file,
+            None => {
+                let Ok(source) = fs::read_to_string(path) else {
+                    continue;
+                };
+                index(path, &source, modified, metadata.len())
+            }
+        };
+        files.insert(key, file);
+    }
+    Ok(Cache {
+        version: CACHE_VERSION,
+        files,
+    })
+}
+
+fn index(path: &Path, source: &str, modified: SystemTime, size: u64) -> CachedFile {
+    let symbols = file_symbols(path, source)
+        .unwrap_or_default()
+        .into_iter()
+        // Module declarations and impl blocks name things defined elsewhere
+        .filter(|s| s.container.is_none() && !matches!(s.kind, "impl" | "module"))
+        .map(|s| format!("{} {}", s.kind, s.name))
+        .collect();
+    let identifiers = file_identifiers(path, source)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    CachedFile {
+        modified,
+        size,
+        symbols,
+        identifiers,
+    }
+}
+
+/// Files with definitions, most referenced first. A file's score is the number of
+/// other files using each of its top-level names, summed; ties go to shallower paths.
+fn rank(cache: &Cache) -> Vec<(&str, &CachedFile)> {
+    let mut users: HashMap<&str, usize> = HashMap::new();
+    for file in cache.files.values() {
+        for identifier in &file.identifiers {
+            *users.entry(identifier.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut ranked: Vec<(usize, &str, &CachedFile)> = cache
+        .files
+        .iter()
+        .filter(|(_, file)| !file.symbols.is_empty())
+        .map(|(path, file)| {
+            let score = file
+                .symbols
+                .iter()
+                .filter_map(|s| s.split_once(' ').map(|(_, name)| name))
+                .map(|name| {
+                    let used_here = file.identifiers.binary_search_by(|i| i.as_str().cmp(name));
+                    users
+                        .get(name)
+                        .map_or(0, |n| n - usize::from(used_here.is_ok()))
+                })
+                .sum();
+            (score, path.as_str(), file)
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then_with(|| a.1.matches('/').count().cmp(&b.1.matches('/').count()))
+            .then_with(|| a.1.cmp(b.1))
+    });
+    ranked
+        .into_iter()
+        .map(|(_, path, file)| (path, file))
+        .collect()
+}
+
+fn render(
+    ranked: &[(&str, &CachedFile)],
+    budget_tokens: usize,
+    estimator: &TokenEstimator,
+) -> RepoMap {
+    let mut text = String::from(
+        "# Repository map\n\nSource files in the working directory and their top-level definitions, \
+         most referenced first. It was built when the session started and may be incomplete.\n\n",
+    );
+    let mut tokens = estimator.estimate_text(&text);
+    // Room for the "more files" note, so the whole map stays within budget
+    let reserve = estimator.estimate_text(&format!("({} more files not shown)\n", ranked.len()));
+    let mut shown = 0;
+    for (i, (path, file)) in ranked.iter().enumerate() {
+        let mut line = format!("{}: ", path);
+        line.push_str(
+            &file
+                .symbols
+                .iter()
+                .take(MAX_SYMBOLS_PER_FILE)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        if file.symbols.len() > MAX_SYMBOLS_PER_FILE {
+            line.push_str(&format!(
+                ", ... ({} more)",
+                file.symbols.len() - MAX_SYMBOLS_PER_FILE
+            ));
+        }
+        line.push('\n');
+
+        let line_tokens = estimator.estimate_text(&line);
+        let needed = if i + 1 < ranked.len() { reserve } else { 0 };
+        if tokens + line_tokens + needed > budget_tokens {
+            break;
+        }
+        text.push_str(&line);
+        tokens += line_tokens;
+        shown += 1;
+    }
+    if shown < ranked.len() {
+        let more = format!("({} more files not shown)\n", ranked.len() - shown);
+        tokens += estimator.estimate_text(&more);
+        text.push_str(&more);
+    }
+    RepoMap {
+        text,
+        shown,
+        total: ranked.len(),
+        tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    fn estimator() -> TokenEstimator {
+        TokenEstimator::for_model("gpt-4o")
+    }
+
+    fn lines(map: &RepoMap) -> Vec<&str> {
+        map.text.lines().skip(4).collect()
+    }
+
+    #[test]
+    fn ranks_files_by_how_often_others_use_them() {
+        let workspace = TempWorkspace::new();
+        workspace.write("src/util.rs", "pub fn helper() {}\n");
+        workspace.write(
+            "src/session.rs",
+            "pub struct Session;\nimpl Session { fn save(&self) {} }\n",
+        );
+        workspace.write(
+            "src/main.rs",
+            "fn main() { let s = Session; helper(); }\nfn run(s: Session) {}\n",
+        );
+        workspace.write("src/agent.rs", "fn step(s: &Session) {}\n");
+        workspace.write("README.md", "# not source\n");
+
+        let map = build(
+            workspace.path(),
+            &workspace.join(".cache"),
+            1000,
+            &estimator(),
+        )
+        .unwrap();
+        assert_eq!(
+            lines(&map),
+            vec![
+                "src/session.rs: struct Session",
+                "src/util.rs: function helper",
+                "src/agent.rs: function step",
+                "src/main.rs: function main, function run",
+            ]
+        );
+        assert_eq!((map.shown, map.total), (4, 4));
+    }
+
+    #[test]
+    fn cache_is_refreshed_when_files_change() {
+        let workspace = TempWorkspace::new();
+        let cache_dir = workspace.join(".cache");
+        workspace.write("a.py", "def first():\n    pass\n");
+        workspace.write("b.py", "class Gone:\n    pass\n");
+        build(workspace.path(), &cache_dir, 1000, &estimator()).unwrap();
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+
+        workspace.write(
+            "a.py",
+            "def first():\n    pass\n\ndef second():\n    pass\n",
+        );
+        fs::remove_file(workspace.join("b.py")).unwrap();
+        let map = build(workspace.path(), &cache_dir, 1000, &estimator()).unwrap();
+        assert_eq!(lines(&map), vec!["a.py: function first, function second"]);
+    }
+
+    #[test]
+    fn lower_ranked_files_are_dropped_to_fit_the_budget() {
+        let workspace = TempWorkspace::new();
+        for i in 0..20 {
+            workspace.write(
+                &format!("mod_{:02}.go", i),
+                &format!("package m\n\nfunc Function{:02}() {{}}\n", i),
+            );
+        }
+
+        let map = build(
+            workspace.path(),
+            &workspace.join(".cache"),
+            100,
+            &estimator(),
+        )
+        .unwrap();
+        assert!(map.shown > 0 && map.shown < 20);
+        assert!(map.tokens <= 100);
+        assert_eq!(
+            map.text.lines().last(),
+            Some(format!("({} more files not shown)", 20 - map.shown).as_str())
+        );
+    }
+}
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use uuid::Uuid;
 
-use crate::provider::{Message, ToolCall};
+use crate::provider::{Message, ToolCall, Usage};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionInfo {
@@ -27,6 +27,9 @@ pub struct MessagePart {
     pub tool_calls: Vec<ToolCall>,
     pub tool_results: Vec<ToolResult>,
     pub timestamp: DateTime<Utc>,
+    /// Token usage of the completion that produced this (assistant) message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -100,6 +103,7 @@ impl Session {
             tool_calls: Vec::new(),
             tool_results: Vec::new(),
             timestamp: Utc::now(),
+            usage: None,
         });
 
         self.info.message_count += 1;
@@ -112,6 +116,7 @@ impl Session {
         &mut self,
         content: Option<String>,
         tool_calls: Vec<ToolCall>,
+        usage: Option<Usage>,
     ) -> String {
         let message_id = Uuid::new_v4().to_string();
 
@@ -122,6 +127,7 @@ impl Session {
             tool_calls,
             tool_results: Vec::new(),
             timestamp: Utc::now(),
+            usage,
         });
 
         self.info.message_count += 1;
@@ -149,51 +155,87 @@ impl Session {
     }
 
     pub fn get_conversation_history(&self) -> Vec<Message> {
-        let mut history = Vec::new();
-
-        for msg in &self.messages {
-            match msg.role.as_str() {
-                "user" => {
-                    if let Some(content) = &msg.content {
-                        history.push(Message {
-                            role: "user".to_string(),
-                            content: content.clone(),
-                            tool_call_id: None,
-                            tool_calls: Vec::new(),
-                        });
-                    }
-                }
-                "assistant" => {
-                    // Always emit assistant turn, even if content is empty, when there are tool calls
-                    if msg.content.is_some() || !msg.tool_calls.is_empty() {
-                        history.push(Message {
-                            role: "assistant".to_string(),
-                            content: msg.content.clone().unwrap_or_default(),
-                            tool_call_id: None,
-                            tool_calls: msg.tool_calls.clone(),
-                        });
-                    }
+        build_history(&self.messages[self.latest_summary_index(self.messages.len())..])
+    }
 
-                    // Emit one user message per tool result with proper tool_call_id
-                    // Combine observation with structured output for LLM context
-                    for result in &msg.tool_results {
-                        let output_json = serde_json::to_string_pretty(&result.output)
-                            .unwrap_or_else(|_| format!("{:?}", result.output));
-                        let combined_content =
-                            format!("{}\n\n```\n{}\n```", result.observation, output_json);
-                        history.push(Message {
-                            role: "user".to_string(),
-                            content: combined_content,
-                            tool_call_id: Some(result.tool_call_id.clone()),
-                            tool_calls: Vec::new(),
-                        });
-                    }
-                }
-                _ => {}
-            }
+    /// Index of the most recent compaction summary before `end` (or 0 if there is none).
+    /// History sent to the model starts there; older messages are only kept for export.
+    fn latest_summary_index(&self, end: usize) -> usize {
+        self.messages[..end]
+            .iter()
+            .rposition(|m| m.role == "summary")
+            .unwrap_or(0)
+    }
+
+    /// Where to split the history for compaction so that the last `keep_recent_turns`
+    /// user turns stay verbatim. Returns the index of the first kept message, or None
+    /// if there is nothing older to summarize.
+    pub fn compaction_split(&self, keep_recent_turns: usize) -> Option<usize> {
+        let start = self.latest_summary_index(self.messages.len());
+        let turn_starts: Vec<usize> = (start..self.messages.len())
+            .filter(|&i| self.messages[i].role == "user")
+            .collect();
+
+        if turn_starts.len() <= keep_recent_turns {
+            return None;
         }
 
-        history
+        let split = turn_starts
+            .get(turn_starts.len() - keep_recent_turns)
+            .copied()
+            .unwrap_or(self.messages.len());
+        (split > start
+            && self.messages[start..split]
+                .iter()
+                .any(|m| m.role != "summary"))
+        .then_some(split)
+    }
+
+    /// Where to split inside the current turn so that only its latest assistant step
+    /// (the last tool calls and their results) stays verbatim, for a turn that is too
+    /// long on its own. None if there is no earlier step to summarize.
+    pub fn latest_step_split(&self) -> Option<usize> {
+        let start = self.latest_summary_index(self.messages.len());
+        let split = (start..self.messages.len())
+            .rev()
+            .find(|&i| self.messages[i].role == "assistant")?;
+        self.messages[start..split]
+            .iter()
+            .any(|m| m.role == "assistant")
+            .then_some(split)
+    }
+
+    /// The history that a compaction at `split` would summarize, including any earlier summary
+    pub fn history_before(&self, split: usize) -> Vec<Message> {
+        build_history(&self.messages[self.latest_summary_index(split)..split])
+    }
+
+    /// Insert a compaction summary marker at `split`. Messages before it stay in the
+    /// session (and in exports) but are no longer sent to the model. `usage` is that
+    /// of the summarization request, so it counts towards the session total.
+    pub fn add_summary(&mut self, split: usize, summary: String, usage: Option<Usage>) {
+        self.messages.insert(
+            split,
+            MessagePart {
+                id: Uuid::new_v4().to_string(),
+                role: "summary".to_string(),
+                content: Some(summary),
+                tool_calls: Vec::new(),
+                tool_results: Vec::new(),
+                timestamp: Utc::now(),
+                usage,
+            },
+        );
+        self.info.updated_at = Utc::now();
+    }
+
+    /// Sum of token usage over all messages in the session
+    pub fn total_usage(&self) -> Usage {
+        let mut total = Usage::default();
+        for usage in self.messages.iter().filter_map(|m| m.usage.as_ref()) {
+            total.add(usage);
+        }
+        total
     }
 
     pub fn get_system_prompt(&self) -> Option<String> {
@@ -300,14 +342,35 @@ impl Session {
                                     markdown
                                         .push_str(&format!("  - Result: {}\n", result.observation));
                                     markdown.push_str(&format!("  - Status: {}\n", result.status));
+                                    if let Some(diff) = result
+                                        .output
+                                        .get("diff")
+                                        .and_then(|v| v.as_str())
+                                        .filter(|d| !d.is_empty())
+                                    {
+                                        markdown.push_str("  - Diff:\n");
+                                        markdown.push_str("    ```diff\n");
+                                        for line in diff.lines() {
+                                            markdown.push_str(&format!("    {}\n", line));
+                                        }
+                                        markdown.push_str("    ```\n");
+                                    }
                                 } else {
                                     markdown.push_str("  - Result: *No result recorded*\n");
                                 }
-                                markdown.push_str("\n");
+                                markdown.push('\n');
                             }
                         }
                         markdown.push_str("---\n\n");
                     }
+                    "summary" => {
+                        markdown.push_str("## Summary of earlier conversation\n\n");
+                        if let Some(content) = &msg.content {
+                            markdown.push_str(content);
+                            markdown.push_str("\n\n");
+                        }
+                        markdown.push_str("---\n\n");
+                    }
                     _ => {}
                 }
             }
@@ -322,6 +385,64 @@ impl Session {
 }
 
 // Helper functions
+fn build_history(messages: &[MessagePart]) -> Vec<Message> {
+    let mut history = Vec::new();
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "summary" => {
+                if let Some(content) = &msg.content {
+                    history.push(Message {
+                        role: "user".to_string(),
+                        content: format!("Summary of the earlier conversation:\n\n{}", content),
+                        tool_call_id: None,
+                        tool_calls: Vec::new(),
+                    });
+                }
+            }
+            "user" => {
+                if let Some(content) = &msg.content {
+                    history.push(Message {
+                        role: "user".to_string(),
+                        content: content.clone(),
+                        tool_call_id: None,
+                        tool_calls: Vec::new(),
+                    });
+                }
+            }
+            "assistant" => {
+                // Always emit assistant turn, even if content is empty, when there are tool calls
+                if msg.content.is_some() || !msg.tool_calls.is_empty() {
+                    history.push(Message {
+                        role: "assistant".to_string(),
+                        content: msg.content.clone().unwrap_or_default(),
+                        tool_call_id: None,
+                        tool_calls: msg.tool_calls.clone(),
+                    });
+                }
+
+                // Emit one user message per tool result with proper tool_call_id
+                // Combine observation with structured output for LLM context
+                for result in &msg.tool_results {
+                    let output_json = serde_json::to_string_pretty(&result.output)
+                        .unwrap_or_else(|_| format!("{:?}", result.output));
+                    let combined_content =
+                        format!("{}\n\n```\n{}\n```", result.observation, output_json);
+                    history.push(Message {
+                        role: "user".to_string(),
+                        content: combined_content,
+                        tool_call_id: Some(result.tool_call_id.clone()),
+                        tool_calls: Vec::new(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    history
+}
+
 fn sanitize_filename(s: &str) -> String {
     s.chars()
         .map(|c| {
@@ -0,0 +1,205 @@
+//! Test helpers: a provider that replays scripted responses and a temporary workspace
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::agent::AgentEvents;
+use crate::provider::{LLMProvider, Message, ProviderError, StreamChunk, ToolCall};
+use crate::session::Session;
+
+/// Answers each completion request with the next scripted list of chunks and records
+/// the messages it was sent. Errors once the script runs out. A response without a
+/// finished chunk leaves its stream open, like a stalled connection.
+pub struct ScriptedProvider {
+    responses: Mutex<VecDeque<Vec<StreamChunk>>>,
+    requests: Arc<Mutex<Vec<Vec<Message>>>>,
+    open_streams: Mutex<Vec<mpsc::Sender<Result<StreamChunk>>>>,
+    /// Streams that fail after their scripted chunks, by request number
+    stream_errors: Mutex<HashMap<usize, String>>,
+}
+
+impl ScriptedProvider {
+    pub fn new(responses: Vec<Vec<StreamChunk>>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            open_streams: Mutex::new(Vec::new()),
+            stream_errors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// End the stream of response `index` (counting from 0) with a connection error
+    /// after its scripted chunks
+    pub fn fail_stream(self, index: usize, message: &str) -> Self {
+        self.stream_errors
+            .lock()
+            .unwrap()
+            .insert(index, message.to_string());
+        self
+    }
+
+    /// Shared handle to the requests received so far (still usable after the
+    /// provider has been moved into a runner)
+    pub fn requests(&self) -> Arc<Mutex<Vec<Vec<Message>>>> {
+        self.requests.clone()
+    }
+
+    fn next_response(&self, messages: Vec<Message>) -> Result<Vec<StreamChunk>> {
+        self.requests.lock().unwrap().push(messages);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("ScriptedProvider: no scripted response left"))
+    }
+}
+
+#[async_trait]
+impl LLMProvider for ScriptedProvider {
+    fn model(&self) -> &str {
+        "scripted"
+    }
+
+    async fn chat_completion(
+        &self,
+        messages: Vec<Message>,
+        _tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<StreamChunk> {
+        // Collapse the scripted stream into a single response
+        let mut response = done();
+        for chunk in self.next_response(messages)? {
+            if let Some(content) = chunk.content {
+                response
+                    .content
+                    .get_or_insert_with(String::new)
+                    .push_str(&content);
+            }
+            response.tool_calls.extend(chunk.tool_calls);
+            response.usage = chunk.usage.or(response.usage);
+        }
+        Ok(response)
+    }
+
+    async fn stream_completion(
+        &self,
+        messages: Vec<Message>,
+        _tools: Option<Vec<serde_json::Value>>,
+    ) -> Result<mpsc::Receiver<Result<StreamChunk>>> {
+        let index = self.requests.lock().unwrap().len();
+        let chunks = self.next_response(messages)?;
+        let finished = chunks.iter().any(|c| c.finished);
+        let (tx, rx) = mpsc::channel(chunks.len() + 1);
+        for chunk in chunks {
+            tx.send(Ok(chunk)).await?;
+        }
+        let error = self.stream_errors.lock().unwrap().remove(&index);
+        if let Some(message) = error {
+            tx.send(Err(ProviderError::Connection(message).into()))
+                .await?;
+        } else if !finished {
+            self.open_streams.lock().unwrap().push(tx);
+        }
+        Ok(rx)
+    }
+}
+
+/// A streamed text fragment
+pub fn text(content: &str) -> StreamChunk {
+    StreamChunk {
+        content: Some(content.to_string()),
+        tool_calls: Vec::new(),
+        finished: false,
+        usage: None,
+    }
+}
+
+/// A complete tool call
+pub fn tool_call(id: &str, name: &str, arguments: serde_json::Value) -> StreamChunk {
+    StreamChunk {
+        content: None,
+        tool_calls: vec![ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments,
+        }],
+        finished: false,
+        usage: None,
+    }
+}
+
+/// The final chunk of a response
+pub fn done() -> StreamChunk {
+    StreamChunk {
+        content: None,
+        tool_calls: Vec::new(),
+        finished: true,
+        usage: None,
+    }
+}
+
+/// Ignores every agent event
+pub struct NullEvents;
+
+impl AgentEvents for NullEvents {}
+
+/// A temporary directory that is removed when dropped. Sessions from `session()` are
+/// rooted in it, so tools resolve relative paths against it.
+pub struct TempWorkspace {
+    dir: tempfile::TempDir,
+}
+
+impl TempWorkspace {
+    pub fn new() -> Self {
+        Self {
+            dir: tempfile::tempdir().expect("failed to create temp dir"),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn join(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    /// Absolute path of `relative` as a string, for tool arguments
+    pub fn path_str(&self, relative: &str) -> String {
+        self.join(relative).to_string_lossy().into_owned()
+    }
+
+    pub fn write(&self, relative: &str, content: &str) {
+        let path = self.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(path, content).unwrap();
+    }
+
+    pub fn read(&self, relative: &str) -> String {
+        std::fs::read_to_string(self.join(relative)).unwrap()
+    }
+
+    /// A new session rooted in the workspace, stored under `.sessions`
+    pub fn session(&self) -> Session {
+        Session::new(
+            "test".to_string(),
+            self.path().to_string_lossy().into_owned(),
+            self.join(".sessions"),
+            None,
+            None,
+        )
+    }
+
+    /// The saved JSON of a session, as `[info, messages]`
+    pub fn saved_session(&self, session: &Session) -> serde_json::Value {
+        let path = self
+            .join(".sessions")
+            .join(format!("{}.json", session.info.id));
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+}
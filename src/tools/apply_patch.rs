@@ -0,0 +1,891 @@
+use super::diff::unified_diff;
+use super::edit_file::EditConfig;
+use super::{Tool, ToolContext, ToolOutput};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+/// What a patch does to one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FileChange {
+    Add(String),
+    Delete,
+    Update(Vec<Hunk>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FilePatch {
+    path: String,
+    change: FileChange,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Hunk {
+    /// The `@@` line, for error messages
+    header: String,
+    /// 1-based line the hunk claims to start at (unified diffs only)
+    old_start: Option<usize>,
+    /// Text after a bare `@@` in the simple format: the hunk applies after the
+    /// first line containing it
+    anchor: Option<String>,
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// Parse a unified diff (as produced by `diff -u` or `git diff`) or the simple
+/// `*** Begin Patch` format into per-file changes
+fn parse_patch(patch: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let files = if lines.iter().any(|l| l.starts_with("*** Begin Patch")) {
+        parse_simple(&lines)?
+    } else {
+        parse_unified(&lines)?
+    };
+    if files.is_empty() {
+        anyhow::bail!("No file changes found in patch");
+    }
+    Ok(files)
+}
+
+fn parse_unified(lines: &[&str]) -> Result<Vec<FilePatch>> {
+    let mut files = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let is_header =
+            lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "));
+        if !is_header {
+            // `diff --git`, `index`, mode lines and anything else between files
+            i += 1;
+            continue;
+        }
+        let old_path = header_path(&lines[i][4..]);
+        let new_path = header_path(&lines[i + 1][4..]);
+        i += 2;
+
+        let mut hunks = Vec::new();
+        while i < lines.len() && lines[i].starts_with("@@") {
+            let header = lines[i];
+            i += 1;
+            let mut hunk = Hunk {
+                header: header.to_string(),
+                old_start: parse_old_start(header),
+                ..Hunk::default()
+            };
+            match parse_counts(header) {
+                // The counts say where the hunk ends, so removed `-- x` or added
+                // `++ y` lines aren't taken for file headers, nor prose after it for context
+                Some((mut old, mut new)) => {
+                    while (old > 0 || new > 0) && i < lines.len() && is_hunk_line(lines[i]) {
+                        match lines[i].chars().next() {
+                            Some('-') => old = old.saturating_sub(1),
+                            Some('+') => new = new.saturating_sub(1),
+                            Some('\\') => {}
+                            _ => {
+                                old = old.saturating_sub(1);
+                                new = new.saturating_sub(1);
+                            }
+                        }
+                        push_hunk_line(&mut hunk, lines[i]);
+                        i += 1;
+                    }
+                    // "\ No newline at end of file" after the last line
+                    while i < lines.len() && lines[i].starts_with('\\') {
+                        i += 1;
+                    }
+                    if i < lines.len()
+                        && !ends_hunk(lines, i)
+                        && lines[i].starts_with(['+', '-', ' '])
+                    {
+                        anyhow::bail!(
+                            "Hunk '{}' has more lines than its header counts (line {} of the patch)",
+                            header,
+                            i + 1
+                        );
+                    }
+                }
+                None => {
+                    while i < lines.len() && !ends_hunk(lines, i) {
+                        push_hunk_line(&mut hunk, lines[i]);
+                        i += 1;
+                    }
+                }
+            }
+            hunks.push(hunk);
+        }
+
+        let change = match (old_path, new_path) {
+            (None, Some(_)) => FileChange::Add(join_lines(hunks.iter().flat_map(|h| &h.new))),
+            (Some(_), None) => FileChange::Delete,
+            (Some(_), Some(_)) => FileChange::Update(hunks),
+            (None, None) => anyhow::bail!("Patch header without a file name at line {}", i),
+        };
+        let path = new_path.or(old_path).expect("matched above").to_string();
+        files.push(FilePatch { path, change });
+    }
+    Ok(files)
+}
+
+fn parse_simple(lines: &[&str]) -> Result<Vec<FilePatch>> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    for (n, line) in lines.iter().enumerate() {
+        if let Some(path) = line.strip_prefix("*** Add File: ") {
+            files.push(FilePatch {
+                path: path.trim().to_string(),
+                change: FileChange::Add(String::new()),
+            });
+        } else if let Some(path) = line.strip_prefix("*** Delete File: ") {
+            files.push(FilePatch {
+                path: path.trim().to_string(),
+                change: FileChange::Delete,
+            });
+        } else if let Some(path) = line.strip_prefix("*** Update File: ") {
+            files.push(FilePatch {
+                path: path.trim().to_string(),
+                change: FileChange::Update(Vec::new()),
+            });
+        } else if matches!(
+            line.trim_end(),
+            "*** Begin Patch" | "*** End Patch" | "*** End of File"
+        ) {
+        } else if line.starts_with("*** ") {
+            anyhow::bail!(
+                "Unsupported directive '{}' at line {} of the patch",
+                line.trim_end(),
+                n + 1
+            );
+        } else {
+            let Some(file) = files.last_mut() else {
+                continue;
+            };
+            match &mut file.change {
+                FileChange::Add(content) => {
+                    content.push_str(line.strip_prefix('+').unwrap_or(line));
+                    content.push('\n');
+                }
+                FileChange::Update(hunks) => {
+                    if let Some(rest) = line.strip_prefix("@@") {
+                        let anchor = rest.trim().trim_end_matches("@@").trim();
+                        hunks.push(Hunk {
+                            header: line.to_string(),
+                            anchor: (!anchor.is_empty()).then(|| anchor.to_string()),
+                            ..Hunk::default()
+                        });
+                    } else {
+                        if hunks.is_empty() {
+                            hunks.push(Hunk {
+                                header: format!("line {} of the patch", n + 1),
+                                ..Hunk::default()
+                            });
+                        }
+                        push_hunk_line(hunks.last_mut().expect("pushed above"), line);
+                    }
+                }
+                FileChange::Delete => anyhow::bail!(
+                    "Unexpected content after '*** Delete File: {}' at line {}",
+                    file.path,
+                    n + 1
+                ),
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// File name from a `---`/`+++` header: None for /dev/null, `a/` and `b/` prefixes
+/// and any timestamp removed
+fn header_path(header: &str) -> Option<&str> {
+    let path = header.split('\t').next().unwrap_or(header).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(
+        path.strip_prefix("a/")
+            .or_else(|| path.strip_prefix("b/"))
+            .unwrap_or(path),
+    )
+}
+
+/// `12` from `@@ -12,5 +12,6 @@`. `-12,0` means "after line 12", so the hunk starts at 13.
+fn parse_old_start(header: &str) -> Option<usize> {
+    let range = header.strip_prefix("@@ -")?.split_whitespace().next()?;
+    let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+    let start: usize = start.parse().ok()?;
+    Some(if count == "0" { start + 1 } else { start })
+}
+
+/// Old and new line counts from `@@ -12,5 +12,6 @@` (a missing count is 1). None for a
+/// bare `@@`.
+fn parse_counts(header: &str) -> Option<(usize, usize)> {
+    let mut ranges = header.strip_prefix("@@ ")?.split_whitespace();
+    let count = |range: &str, sign: char| -> Option<usize> {
+        let range = range.strip_prefix(sign)?;
+        match range.split_once(',') {
+            Some((_, count)) => count.parse().ok(),
+            None => range.parse::<usize>().ok().map(|_| 1),
+        }
+    };
+    Some((count(ranges.next()?, '-')?, count(ranges.next()?, '+')?))
+}
+
+/// Lines that can be part of a hunk body: context (blank when an editor stripped the
+/// space), removed, added, or "\ No newline at end of file"
+fn is_hunk_line(line: &str) -> bool {
+    line.is_empty() || line.starts_with([' ', '+', '-', '\\'])
+}
+
+/// A hunk ends at the next hunk or file header
+fn ends_hunk(lines: &[&str], i: usize) -> bool {
+    lines[i].starts_with("@@")
+        || lines[i].starts_with("diff --git ")
+        || (lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ ")))
+}
+
+fn push_hunk_line(hunk: &mut Hunk, line: &str) {
+    match line.chars().next() {
+        Some('+') => hunk.new.push(line[1..].to_string()),
+        Some('-') => hunk.old.push(line[1..].to_string()),
+        // "\ No newline at end of file"
+        Some('\\') => {}
+        // Context; editors often strip the space from blank context lines
+        _ => {
+            let text = line.strip_prefix(' ').unwrap_or(line).to_string();
+            hunk.old.push(text.clone());
+            hunk.new.push(text);
+        }
+    }
+}
+
+fn join_lines<'a>(lines: impl Iterator<Item = &'a String>) -> String {
+    lines.map(|l| format!("{}\n", l)).collect()
+}
+
+/// How loosely a hunk's lines had to be compared to find it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fuzz {
+    Exact,
+    TrailingWhitespace,
+    Whitespace,
+}
+
+impl Fuzz {
+    fn matches(self, a: &str, b: &str) -> bool {
+        match self {
+            Fuzz::Exact => a == b,
+            Fuzz::TrailingWhitespace => a.trim_end() == b.trim_end(),
+            Fuzz::Whitespace => a.trim() == b.trim(),
+        }
+    }
+}
+
+/// Apply `hunks` to `content`. Returns the new content and notes on hunks that only
+/// applied with an offset or fuzz, or one failure message per hunk that did not apply.
+fn apply_hunks(
+    path: &str,
+    content: &str,
+    hunks: &[Hunk],
+) -> std::result::Result<(String, Vec<String>), Vec<String>> {
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut notes = Vec::new();
+    let mut failures = Vec::new();
+    // Lines before `cursor` were produced by earlier hunks; `delta` is how far they
+    // moved everything after them
+    let mut cursor = 0;
+    let mut delta: isize = 0;
+
+    for (n, hunk) in hunks.iter().enumerate() {
+        let label = format!("{} hunk {} ({})", path, n + 1, hunk.header);
+        let mut from = cursor;
+        if let Some(anchor) = &hunk.anchor {
+            match lines[from..]
+                .iter()
+                .position(|l| l.contains(anchor.as_str()))
+            {
+                Some(i) => from += i + 1,
+                None => {
+                    failures.push(format!("{}: anchor '{}' not found", label, anchor));
+                    continue;
+                }
+            }
+        }
+        let hint = hunk
+            .old_start
+            .map(|start| (start as isize - 1 + delta).max(from as isize) as usize)
+            // Lines that are only added go right after their anchor
+            .or_else(|| (hunk.anchor.is_some() && hunk.old.is_empty()).then_some(from));
+
+        match locate(&lines, &hunk.old, from, hint) {
+            Some((at, fuzz)) => {
+                if let Some(hint) = hint.filter(|&h| h != at) {
+                    notes.push(format!(
+                        "{} applied at line {} (offset {:+})",
+                        label,
+                        at + 1,
+                        at as isize - hint as isize
+                    ));
+                }
+                if fuzz != Fuzz::Exact {
+                    notes.push(format!("{} matched ignoring {:?}", label, fuzz));
+                }
+                lines.splice(at..at + hunk.old.len(), hunk.new.iter().cloned());
+                cursor = at + hunk.new.len();
+                delta += hunk.new.len() as isize - hunk.old.len() as isize;
+            }
+            None => failures.push(format!("{}: {}", label, mismatch(&lines, &hunk.old, from))),
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(failures);
+    }
+    let mut new = lines.join("\n");
+    if !lines.is_empty() && (content.is_empty() || content.ends_with('\n')) {
+        new.push('\n');
+    }
+    Ok((new, notes))
+}
+
+/// Where `old` occurs at or after `from`, trying exact matches first. Among several
+/// matches the one closest to `hint` wins, or the first one without a hint.
+fn locate(
+    lines: &[String],
+    old: &[String],
+    from: usize,
+    hint: Option<usize>,
+) -> Option<(usize, Fuzz)> {
+    if old.is_empty() {
+        // Pure insertion: at the claimed line, or at the end of the file
+        return Some((hint.unwrap_or(lines.len()).min(lines.len()), Fuzz::Exact));
+    }
+    if old.len() > lines.len() {
+        return None;
+    }
+    for fuzz in [Fuzz::Exact, Fuzz::TrailingWhitespace, Fuzz::Whitespace] {
+        let found = (from..=lines.len() - old.len())
+            .filter(|&at| {
+                old.iter()
+                    .zip(&lines[at..])
+                    .all(|(a, b)| fuzz.matches(a, b))
+            })
+            .min_by_key(|&at| hint.map_or(at, |h| at.abs_diff(h)));
+        if let Some(at) = found {
+            return Some((at, fuzz));
+        }
+    }
+    None
+}
+
+/// Why `old` did not match: the closest candidate and its first differing line
+fn mismatch(lines: &[String], old: &[String], from: usize) -> String {
+    let best = (from..lines.len())
+        .map(|at| {
+            let same = old
+                .iter()
+                .zip(&lines[at..])
+                .filter(|(a, b)| a.trim() == b.trim())
+                .count();
+            (same, at)
+        })
+        .max_by_key(|&(same, at)| (same, std::cmp::Reverse(at)));
+
+    match best {
+        Some((same, at)) if same > 0 => {
+            let (i, expected) = old
+                .iter()
+                .enumerate()
+                .find(|(i, l)| lines.get(at + i).is_none_or(|f| f.trim() != l.trim()))
+                .expect("a full match would have been found");
+            format!(
+                "context not found; closest match at line {} differs at line {}: expected {:?}, found {:?}",
+                at + 1,
+                at + i + 1,
+                expected,
+                lines.get(at + i).map_or("end of file", String::as_str)
+            )
+        }
+        _ => format!(
+            "context not found; expected {:?}",
+            old.first().map_or("", String::as_str)
+        ),
+    }
+}
+
+/// The result of applying a patch in memory
+struct PlannedFile {
+    path: PathBuf,
+    display: String,
+    /// None if the file does not exist yet
+    before: Option<String>,
+    /// None if the patch deletes the file
+    after: Option<String>,
+}
+
+/// Paths a patch touches, as written in it. Used by the permission policy.
+pub fn patch_paths(patch: &str) -> Vec<String> {
+    parse_patch(patch)
+        .map(|files| files.into_iter().map(|f| f.path).collect())
+        .unwrap_or_default()
+}
+
+/// Applies multi-file patches in one call, all or nothing
+pub struct ApplyPatchTool {
+    config: EditConfig,
+}
+
+impl ApplyPatchTool {
+    pub fn new(config: EditConfig) -> Self {
+        Self { config }
+    }
+
+    /// Apply every file change in memory. Fails with the details of every hunk that
+    /// did not apply.
+    fn plan(&self, patch: &str, ctx: &ToolContext) -> Result<(Vec<PlannedFile>, Vec<String>)> {
+        let mut planned: Vec<PlannedFile> = Vec::new();
+        let mut notes = Vec::new();
+        let mut failures = Vec::new();
+
+        for file in parse_patch(patch)? {
+            let path = ctx.resolve(&file.path)?;
+            // A file may appear more than once; later changes apply on top
+            let index = match planned.iter().position(|p| p.path == path) {
+                Some(index) => index,
+                None => {
+                    let before = if path.is_file() {
+                        let content = fs::read_to_string(&path)
+                            .with_context(|| format!("Failed to read file: {}", file.path))?;
+                        if let Err(e) = ctx.check_seen(&path, &content, self.config.require_read) {
+                            failures.push(e.to_string());
+                        }
+                        Some(content)
+                    } else {
+                        None
+                    };
+                    planned.push(PlannedFile {
+                        display: ctx.display_path(&path),
+                        after: before.clone(),
+                        before,
+                        path,
+                    });
+                    planned.len() - 1
+                }
+            };
+            let entry = &mut planned[index];
+
+            match (file.change, &entry.after) {
+                (FileChange::Add(content), None) => entry.after = Some(content),
+                (FileChange::Add(_), Some(_)) => {
+                    failures.push(format!("{}: file already exists", file.path))
+                }
+                (FileChange::Delete, Some(_)) => entry.after = None,
+                (FileChange::Update(hunks), Some(current)) => {
+                    match apply_hunks(&file.path, current, &hunks) {
+                        Ok((content, hunk_notes)) => {
+                            entry.after = Some(content);
+                            notes.extend(hunk_notes);
+                        }
+                        Err(hunk_failures) => failures.extend(hunk_failures),
+                    }
+                }
+                (FileChange::Delete | FileChange::Update(_), None) => {
+                    failures.push(format!("{}: file not found", file.path))
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            anyhow::bail!(
+                "Patch not applied, no files were changed. {} problem(s):\n- {}",
+                failures.len(),
+                failures.join("\n- ")
+            );
+        }
+        Ok((planned, notes))
+    }
+
+    /// Write every planned file. If one fails, the ones already written are put back.
+    fn write(&self, planned: &[PlannedFile], ctx: &ToolContext) -> Result<()> {
+        for (i, file) in planned.iter().enumerate() {
+            if let Err(e) = ctx.snapshot(&file.path).and_then(|_| write_file(file)) {
+                for done in planned[..i].iter().rev() {
+                    let _ = restore_file(done);
+                }
+                return Err(e.context(format!(
+                    "Failed to write {}; rolled back {} file(s)",
+                    file.display, i
+                )));
+            }
+        }
+        for file in planned {
+            match &file.after {
+                Some(content) => ctx.record_seen(&file.path, content),
+                None => ctx.forget_seen(&file.path),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_file(file: &PlannedFile) -> Result<()> {
+    match &file.after {
+        Some(content) => {
+            if let Some(parent) = file.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&file.path, content)?;
+        }
+        None => fs::remove_file(&file.path)?,
+    }
+    Ok(())
+}
+
+fn restore_file(file: &PlannedFile) -> Result<()> {
+    match &file.before {
+        Some(content) => fs::write(&file.path, content)?,
+        None => fs::remove_file(&file.path)?,
+    }
+    Ok(())
+}
+
+fn combined_diff(planned: &[PlannedFile]) -> String {
+    planned
+        .iter()
+        .map(|file| match &file.after {
+            Some(after) => unified_diff(&file.display, file.before.as_deref(), after),
+            None => format!("--- a/{}\n+++ /dev/null\n(file deleted)\n", file.display),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Tool for ApplyPatchTool {
+    fn name(&self) -> &str {
+        "apply_patch"
+    }
+
+    fn description(&self) -> &str {
+        "Apply a patch that changes one or more files in a single call. Accepts a unified diff (as from 'diff -u' or 'git diff') or the simple format: '*** Begin Patch', then per file '*** Add File: <path>' (lines prefixed '+'), '*** Delete File: <path>', or '*** Update File: <path>' followed by hunks starting with '@@' (optionally '@@ <text of a line the hunk follows>') with ' ', '-' and '+' lines, then '*** End Patch'. Context is matched even if line numbers or whitespace are off. All-or-nothing: if any hunk fails, no file is changed and every failing hunk is reported. Patches are refused if a file changed since you last read it; read it again first."
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "patch": {
+                    "type": "string",
+                    "description": "The patch text; paths are relative to the workspace root"
+                }
+            },
+            "required": ["patch"]
+        })
+    }
+
+    async fn execute(&self, input: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let patch = input
+            .get("patch")
+            .and_then(|v| v.as_str())
+            .context("Missing patch")?;
+
+        let (planned, notes) = self.plan(patch, ctx)?;
+        self.write(&planned, ctx)?;
+
+        let files: Vec<serde_json::Value> = planned
+            .iter()
+            .map(|file| {
+                let action = match (&file.before, &file.after) {
+                    (None, _) => "created",
+                    (_, None) => "deleted",
+                    _ => "updated",
+                };
+                json!({ "file_path": file.display, "action": action })
+            })
+            .collect();
+        let mut observation = format!("Applied patch to {} file(s)", planned.len());
+        if !notes.is_empty() {
+            observation.push_str(&format!("; {}", notes.join("; ")));
+        }
+
+        Ok(ToolOutput {
+            output: json!({ "files": files, "notes": notes, "diff": combined_diff(&planned) }),
+            observation,
+            display: Some(
+                planned
+                    .iter()
+                    .map(|f| format!("✓ Patched {}", f.display))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            status: "success".to_string(),
+        })
+    }
+
+    fn preview(&self, input: &serde_json::Value, ctx: &ToolContext) -> Result<Option<String>> {
+        let Some(patch) = input.get("patch").and_then(|v| v.as_str()) else {
+            return Ok(None);
+        };
+        let (planned, _) = self.plan(patch, ctx)?;
+        Ok(Some(combined_diff(&planned)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    async fn apply(workspace: &TempWorkspace, patch: &str) -> Result<ToolOutput> {
+        ApplyPatchTool::new(EditConfig::default())
+            .execute(
+                json!({ "patch": patch }),
+                &ToolContext::new(workspace.path(), false),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn applies_unified_diffs_with_fuzz() {
+        let workspace = TempWorkspace::new();
+        workspace.write(
+            "src/a.rs",
+            "// header\n\nfn one() {\n    1\n}\n\nfn two() {\n    2\n}\n",
+        );
+        workspace.write("b.txt", "keep\ndrop\n");
+
+        // Line numbers are two off and the context has lost its indentation
+        let patch = "\
+diff --git a/src/a.rs b/src/a.rs
+--- a/src/a.rs
++++ b/src/a.rs
+@@ -5,3 +5,3 @@
+ fn two() {
+-2
++    22
+ }
+--- a/b.txt
++++ b/b.txt
+@@ -1,2 +1,1 @@
+ keep
+-drop
+--- /dev/null
++++ b/new/c.txt
+@@ -0,0 +1,2 @@
++c1
++c2
+";
+        let result = apply(&workspace, patch).await.unwrap();
+
+        assert_eq!(
+            workspace.read("src/a.rs"),
+            "// header\n\nfn one() {\n    1\n}\n\nfn two() {\n    22\n}\n"
+        );
+        assert_eq!(workspace.read("b.txt"), "keep\n");
+        assert_eq!(workspace.read("new/c.txt"), "c1\nc2\n");
+        assert_eq!(result.output["files"][2]["action"], "created");
+        let notes = result.output["notes"].to_string();
+        assert!(notes.contains("applied at line 7 (offset +2)"), "{}", notes);
+        assert!(notes.contains("matched ignoring Whitespace"), "{}", notes);
+    }
+
+    #[tokio::test]
+    async fn applies_the_simple_format() {
+        let workspace = TempWorkspace::new();
+        workspace.write("lib.rs", "fn a() {\n    x();\n}\n\nfn b() {\n    x();\n}\n");
+        workspace.write("old.txt", "bye\n");
+
+        let patch = "\
+*** Begin Patch
+*** Update File: lib.rs
+@@ fn b() {
+-    x();
++    y();
+*** Add File: added.txt
++hello
+*** Delete File: old.txt
+*** End Patch";
+        apply(&workspace, patch).await.unwrap();
+
+        assert_eq!(
+            workspace.read("lib.rs"),
+            "fn a() {\n    x();\n}\n\nfn b() {\n    y();\n}\n"
+        );
+        assert_eq!(workspace.read("added.txt"), "hello\n");
+        assert!(!workspace.join("old.txt").exists());
+        assert_eq!(patch_paths(patch), vec!["lib.rs", "added.txt", "old.txt"]);
+    }
+
+    #[tokio::test]
+    async fn anchored_insertions_go_after_the_anchor() {
+        let workspace = TempWorkspace::new();
+        workspace.write("lib.rs", "fn a() {}\n\nfn b() {\n    x();\n}\n");
+
+        let patch = "\
+*** Begin Patch
+*** Update File: lib.rs
+@@ fn b() {
++    setup();
+*** End Patch";
+        apply(&workspace, patch).await.unwrap();
+
+        assert_eq!(
+            workspace.read("lib.rs"),
+            "fn a() {}\n\nfn b() {\n    setup();\n    x();\n}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn unsupported_directives_are_rejected() {
+        let workspace = TempWorkspace::new();
+        workspace.write("old.rs", "x\n");
+
+        let patch = "\
+*** Begin Patch
+*** Update File: old.rs
+*** Move to: new.rs
+@@
+-x
++y
+*** End Patch";
+        let error = apply(&workspace, patch).await.unwrap_err().to_string();
+
+        assert!(
+            error.contains("Unsupported directive '*** Move to: new.rs' at line 3"),
+            "{}",
+            error
+        );
+        assert_eq!(workspace.read("old.rs"), "x\n");
+    }
+
+    #[tokio::test]
+    async fn unified_hunks_end_where_their_counts_say() {
+        let workspace = TempWorkspace::new();
+        workspace.write("notes.md", "# Notes\n-- old rule\nkeep\n");
+
+        // The removed and added lines look like file headers; the prose after the
+        // hunk is not context
+        let patch = "\
+--- a/notes.md
++++ b/notes.md
+@@ -1,3 +1,3 @@
+ # Notes
+--- old rule
++++ new rule
+ keep
+This renames the rule.
+";
+        apply(&workspace, patch).await.unwrap();
+        assert_eq!(workspace.read("notes.md"), "# Notes\n++ new rule\nkeep\n");
+
+        let overlong = "\
+--- a/notes.md
++++ b/notes.md
+@@ -1,1 +1,1 @@
+-# Notes
++# Log
+ keep
+";
+        let error = apply(&workspace, overlong).await.unwrap_err().to_string();
+        assert!(
+            error.contains("has more lines than its header counts (line 6 of the patch)"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_hunks_leave_every_file_untouched() {
+        let workspace = TempWorkspace::new();
+        workspace.write("a.txt", "one\ntwo\nthree\n");
+        workspace.write("b.txt", "alpha\nbeta\n");
+
+        let patch = "\
+--- a/a.txt
++++ b/a.txt
+@@ -1,2 +1,2 @@
+ one
+-two
++TWO
+--- a/b.txt
++++ b/b.txt
+@@ -1,2 +1,2 @@
+ alpha
+-gamma
++delta
+--- a/missing.txt
++++ b/missing.txt
+@@ -1 +1 @@
+-x
++y
+";
+        let error = apply(&workspace, patch).await.unwrap_err().to_string();
+
+        assert_eq!(workspace.read("a.txt"), "one\ntwo\nthree\n");
+        assert_eq!(workspace.read("b.txt"), "alpha\nbeta\n");
+        assert!(
+            error.contains("no files were changed. 2 problem(s)"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains(
+                "b.txt hunk 1 (@@ -1,2 +1,2 @@): context not found; closest match at line 1 \
+                 differs at line 2: expected \"gamma\", found \"beta\""
+            ),
+            "{}",
+            error
+        );
+        assert!(error.contains("missing.txt: file not found"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn refuses_files_changed_since_read_or_never_read() {
+        let workspace = TempWorkspace::new();
+        workspace.write("read.txt", "one\n");
+        workspace.write("unread.txt", "two\n");
+        let ctx = ToolContext::new(workspace.path(), false);
+        ctx.record_seen(&workspace.join("read.txt"), "one\n");
+        let patch = "\
+*** Begin Patch
+*** Update File: read.txt
+@@
+-one
++ONE
+*** Update File: unread.txt
+@@
+-two
++TWO
+*** End Patch";
+        let tool = ApplyPatchTool::new(EditConfig { require_read: true });
+
+        let error = tool
+            .execute(json!({ "patch": patch }), &ctx)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("File unread.txt has not been read in this session"),
+            "{}",
+            error
+        );
+
+        ctx.record_seen(&workspace.join("unread.txt"), "two\n");
+        workspace.write("read.txt", "changed\n");
+        let error = tool
+            .execute(json!({ "patch": patch }), &ctx)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("File read.txt changed since last read"),
+            "{}",
+            error
+        );
+        assert_eq!(workspace.read("read.txt"), "changed\n");
+        assert_eq!(workspace.read("unread.txt"), "two\n");
+
+        // Back to what was read
+        workspace.write("read.txt", "one\n");
+        tool.execute(json!({ "patch": patch }), &ctx).await.unwrap();
+        assert_eq!(workspace.read("read.txt"), "ONE\n");
+        assert_eq!(workspace.read("unread.txt"), "TWO\n");
+    }
+}
@@ -0,0 +1,443 @@
+use super::bash::ProcessGroup;
+use super::{Tool, ToolContext, ToolOutput};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+
+/// How long `kill` waits for the process to go away before reporting anyway
+const KILL_WAIT: Duration = Duration::from_secs(5);
+
+/// Output produced since the last read. Only the newest `limit` bytes are kept.
+struct PendingOutput {
+    data: VecDeque<u8>,
+    dropped: usize,
+    limit: usize,
+}
+
+impl PendingOutput {
+    fn new(limit: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            dropped: 0,
+            limit,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.data.extend(bytes);
+        if self.data.len() > self.limit {
+            let excess = self.data.len() - self.limit;
+            self.data.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    fn take(&mut self) -> String {
+        let bytes: Vec<u8> = self.data.drain(..).collect();
+        let mut text = String::new();
+        if self.dropped > 0 {
+            text.push_str(&format!("... [{} bytes dropped] ...\n", self.dropped));
+            self.dropped = 0;
+        }
+        text.push_str(&String::from_utf8_lossy(&bytes));
+        text
+    }
+}
+
+type SharedOutput = Arc<Mutex<PendingOutput>>;
+
+struct BackgroundProcess {
+    command: String,
+    started: Instant,
+    pid: Option<u32>,
+    child: Child,
+    // Kept armed after the leader exits so anything it left running is killed on cleanup
+    group: ProcessGroup,
+    stdout: SharedOutput,
+    stderr: SharedOutput,
+}
+
+impl BackgroundProcess {
+    /// Exit code once the process has finished (-1 if killed by a signal)
+    fn exit_code(&mut self) -> Option<i32> {
+        match self.child.try_wait() {
+            Ok(Some(status)) => Some(status.code().unwrap_or(-1)),
+            _ => None,
+        }
+    }
+
+    fn summary(&mut self, handle: &str) -> serde_json::Value {
+        let exit_code = self.exit_code();
+        json!({
+            "handle": handle,
+            "command": self.command,
+            "pid": self.pid,
+            "running": exit_code.is_none(),
+            "exit_code": exit_code,
+            "elapsed_secs": self.started.elapsed().as_secs()
+        })
+    }
+}
+
+/// Commands started with `run_in_background`, by handle. Clones share the same set.
+/// Dropping the last clone (when the tool registry goes away with the agent) kills
+/// every process that is still running.
+#[derive(Clone)]
+pub struct BackgroundProcesses {
+    inner: Arc<Mutex<Processes>>,
+}
+
+struct Processes {
+    next_id: usize,
+    processes: BTreeMap<String, BackgroundProcess>,
+}
+
+impl BackgroundProcesses {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Processes {
+                next_id: 1,
+                processes: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Start `command` in its own process group and return its handle
+    pub(super) fn spawn(
+        &self,
+        command: &str,
+        cwd: &Path,
+        max_output_bytes: usize,
+    ) -> Result<(String, Option<u32>)> {
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut c = Command::new("cmd");
+            c.args(["/C", command]);
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.args(["-c", command]);
+            c
+        };
+        cmd.current_dir(cwd);
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to start background command")?;
+        let pid = child.id();
+
+        let stdout = Arc::new(Mutex::new(PendingOutput::new(max_output_bytes)));
+        let stderr = Arc::new(Mutex::new(PendingOutput::new(max_output_bytes)));
+        let stdout_pipe = child.stdout.take().context("Failed to capture stdout")?;
+        let stderr_pipe = child.stderr.take().context("Failed to capture stderr")?;
+        tokio::spawn(collect(stdout_pipe, stdout.clone()));
+        tokio::spawn(collect(stderr_pipe, stderr.clone()));
+
+        let mut inner = self.inner.lock().unwrap();
+        let handle = format!("bg-{}", inner.next_id);
+        inner.next_id += 1;
+        inner.processes.insert(
+            handle.clone(),
+            BackgroundProcess {
+                command: command.to_string(),
+                started: Instant::now(),
+                pid,
+                child,
+                group: ProcessGroup::new(pid),
+                stdout,
+                stderr,
+            },
+        );
+        Ok((handle, pid))
+    }
+
+    /// Status of one process, plus the output it produced since the last read
+    pub(super) fn read(&self, handle: &str) -> Result<serde_json::Value> {
+        let mut inner = self.inner.lock().unwrap();
+        let process = inner
+            .processes
+            .get_mut(handle)
+            .with_context(|| format!("No background process with handle '{}'", handle))?;
+
+        let mut result = process.summary(handle);
+        result["stdout"] = json!(process.stdout.lock().unwrap().take());
+        result["stderr"] = json!(process.stderr.lock().unwrap().take());
+        Ok(result)
+    }
+
+    fn status(&self, handle: &str) -> Result<serde_json::Value> {
+        let mut inner = self.inner.lock().unwrap();
+        let process = inner
+            .processes
+            .get_mut(handle)
+            .with_context(|| format!("No background process with handle '{}'", handle))?;
+        Ok(process.summary(handle))
+    }
+
+    fn list(&self) -> Vec<serde_json::Value> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .processes
+            .iter_mut()
+            .map(|(handle, process)| process.summary(handle))
+            .collect()
+    }
+
+    /// Kill the process group and forget the handle. Returns the final status and
+    /// any output not read yet.
+    async fn kill(&self, handle: &str) -> Result<serde_json::Value> {
+        let mut process = self
+            .inner
+            .lock()
+            .unwrap()
+            .processes
+            .remove(handle)
+            .with_context(|| format!("No background process with handle '{}'", handle))?;
+
+        process.group.kill();
+        let _ = tokio::time::timeout(KILL_WAIT, process.child.wait()).await;
+
+        let mut result = process.summary(handle);
+        result["stdout"] = json!(process.stdout.lock().unwrap().take());
+        result["stderr"] = json!(process.stderr.lock().unwrap().take());
+        Ok(result)
+    }
+
+    /// Kill every process. Used before exiting without unwinding (`process::exit`).
+    pub fn kill_all(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        for process in inner.processes.values_mut() {
+            process.group.kill();
+        }
+        inner.processes.clear();
+    }
+}
+
+async fn collect(mut pipe: impl AsyncRead + Unpin, output: SharedOutput) {
+    let mut buf = [0u8; 8192];
+    while let Ok(n) = pipe.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        output.lock().unwrap().push(&buf[..n]);
+    }
+}
+
+/// Inspects and stops commands started by the bash tool with `run_in_background`
+pub struct ProcessTool {
+    processes: BackgroundProcesses,
+}
+
+impl ProcessTool {
+    pub fn new(processes: BackgroundProcesses) -> Self {
+        Self { processes }
+    }
+}
+
+#[async_trait]
+impl Tool for ProcessTool {
+    fn name(&self) -> &str {
+        "process"
+    }
+
+    fn description(&self) -> &str {
+        "Manage background processes started by the bash tool with 'run_in_background'. REQUIRED: Set 'operation' to one of: 'output' (new stdout/stderr since the last read, plus status), 'status' (running or exit code), 'kill' (stop the process and everything it started), or 'list' (all background processes)."
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "operation": {
+                    "type": "string",
+                    "enum": ["output", "status", "kill", "list"],
+                    "description": "Operation to perform"
+                },
+                "handle": {
+                    "type": "string",
+                    "description": "Handle returned by the bash tool, e.g. 'bg-1' (not needed for 'list')"
+                }
+            },
+            "required": ["operation"]
+        })
+    }
+
+    async fn execute(&self, input: serde_json::Value, _ctx: &ToolContext) -> Result<ToolOutput> {
+        let operation = input
+            .get("operation")
+            .and_then(|v| v.as_str())
+            .context("Missing operation")?;
+
+        if operation == "list" {
+            let processes = self.processes.list();
+            let running = processes.iter().filter(|p| p["running"] == true).count();
+            return Ok(ToolOutput {
+                observation: format!(
+                    "{} background processes ({} running)",
+                    processes.len(),
+                    running
+                ),
+                output: json!({ "processes": processes }),
+                display: None,
+                status: "success".to_string(),
+            });
+        }
+
+        let handle = input
+            .get("handle")
+            .and_then(|v| v.as_str())
+            .context("Missing handle")?;
+
+        let output = match operation {
+            "output" => self.processes.read(handle)?,
+            "status" => self.processes.status(handle)?,
+            "kill" => self.processes.kill(handle).await?,
+            _ => return Err(anyhow::anyhow!("Unknown operation: {}", operation)),
+        };
+
+        let state = match output["exit_code"].as_i64() {
+            Some(code) => format!("exited with code {}", code),
+            None if operation == "kill" => "did not exit after being killed".to_string(),
+            None => "is running".to_string(),
+        };
+        let observation = match operation {
+            "kill" => format!("Killed {}; it {}", handle, state),
+            _ => format!("Process {} {}", handle, state),
+        };
+
+        Ok(ToolOutput {
+            output,
+            observation,
+            display: None,
+            status: "success".to_string(),
+        })
+    }
+
+    fn is_read_only(&self, input: &serde_json::Value) -> bool {
+        matches!(
+            input.get("operation").and_then(|v| v.as_str()),
+            Some("output" | "status" | "list")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> ToolContext {
+        ToolContext::new(".", false)
+    }
+
+    #[test]
+    fn pending_output_keeps_newest_bytes() {
+        let mut output = PendingOutput::new(5);
+        output.push(b"abc");
+        output.push(b"defgh");
+        assert_eq!(output.take(), "... [3 bytes dropped] ...\ndefgh");
+        assert_eq!(output.take(), "");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reads_output_incrementally_and_reports_exit() {
+        let processes = BackgroundProcesses::new();
+        let tool = ProcessTool::new(processes.clone());
+        let (handle, _) = processes
+            .spawn(
+                "echo one; sleep 0.3; echo two >&2; exit 4",
+                Path::new("."),
+                1000,
+            )
+            .unwrap();
+        assert_eq!(handle, "bg-1");
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let first = tool
+            .execute(
+                json!({ "operation": "output", "handle": handle }),
+                &context(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(first.output["running"], true);
+        assert_eq!(first.output["stdout"], "one\n");
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let second = tool
+            .execute(
+                json!({ "operation": "output", "handle": handle }),
+                &context(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(second.output["running"], false);
+        assert_eq!(second.output["exit_code"], 4);
+        assert_eq!(second.output["stdout"], "");
+        assert_eq!(second.output["stderr"], "two\n");
+        assert_eq!(second.observation, "Process bg-1 exited with code 4");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn kill_and_drop_stop_the_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path();
+        let processes = BackgroundProcesses::new();
+        let tool = ProcessTool::new(processes.clone());
+
+        let (killed, _) = processes
+            .spawn("sleep 60 & echo $! > a.pid; wait", cwd, 1000)
+            .unwrap();
+        processes
+            .spawn("sleep 60 & echo $! > b.pid; wait", cwd, 1000)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let result = tool
+            .execute(json!({ "operation": "kill", "handle": killed }), &context())
+            .await
+            .unwrap();
+        assert_eq!(result.output["running"], false);
+        assert!(tool
+            .execute(
+                json!({ "operation": "status", "handle": killed }),
+                &context()
+            )
+            .await
+            .is_err());
+
+        // Whatever an exited leader left running is still in the group
+        let (exited, _) = processes
+            .spawn("sleep 60 & echo $! > c.pid", cwd, 1000)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(processes.status(&exited).unwrap()["running"], false);
+        tool.execute(json!({ "operation": "kill", "handle": exited }), &context())
+            .await
+            .unwrap();
+
+        // The remaining process goes away with the last handle to the set
+        drop(tool);
+        drop(processes);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for pid_file in ["a.pid", "b.pid", "c.pid"] {
+            let pid = std::fs::read_to_string(dir.path().join(pid_file)).unwrap();
+            let state =
+                std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+            assert!(state.is_empty() || state.contains(") Z"), "{}", state);
+        }
+    }
+}
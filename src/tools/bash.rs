@@ -1,18 +1,71 @@
-use super::{Tool, ToolOutput};
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use colored::Colorize;
+use serde::Deserialize;
 use serde_json::json;
+use std::collections::VecDeque;
 use std::io::{self, Write};
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::Mutex;
 
-pub struct BashTool;
+use super::background::BackgroundProcesses;
+use super::shell::{ShellSession, ShellStatus};
 
+/// Bash tool settings (the `bash` section of the config file)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BashConfig {
+    /// Timeout for commands that don't pass `timeout_ms`
+    pub default_timeout_ms: u64,
+    /// Output kept per stream; anything beyond is cut from the middle
+    pub max_output_bytes: usize,
+    /// Print output to the terminal while the command runs
+    pub live_output: bool,
+    /// Keep one shell per session so `cd`, `export` etc. carry over between commands
+    /// (Unix only)
+    pub persistent_shell: bool,
+}
+
+impl Default for BashConfig {
+    fn default() -> Self {
+        Self {
+            default_timeout_ms: 120_000,
+            max_output_bytes: 30_000,
+            live_output: true,
+            persistent_shell: true,
+        }
+    }
+}
+
+pub struct BashTool {
+    config: BashConfig,
+    shell: Mutex<Option<ShellSession>>,
+    background: BackgroundProcesses,
+}
+
+impl BashTool {
+    pub fn new(config: BashConfig, background: BackgroundProcesses) -> Self {
+        Self {
+            config,
+            shell: Mutex::new(None),
+            background,
+        }
+    }
+}
+
+#[async_trait]
 impl Tool for BashTool {
     fn name(&self) -> &str {
         "bash"
     }
 
     fn description(&self) -> &str {
-        "Execute shell commands in the system. Provide the full command string in the 'command' parameter. Optionally specify 'cwd' to set the working directory. Set 'skip_approval' to true for read-only commands to skip user confirmation."
+        "Execute shell commands in the system. Provide the full command string in the 'command' parameter. Commands run in a persistent shell, so directory changes, exported variables, functions and activated environments carry over to later calls. Optionally specify 'cwd' to change to a directory first and 'timeout_ms' for long-running commands. Set 'restart' to true to start a fresh shell. Set 'run_in_background' to true for servers, watchers and other long-running commands: it returns a handle at once, to be used with the 'process' tool to read output, check status or kill the command. Very long output is truncated to its beginning and end."
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -25,12 +78,22 @@ impl Tool for BashTool {
                 },
                 "cwd": {
                     "type": "string",
-                    "description": "Working directory for the command (optional)"
+                    "description": "Working directory for the command, relative to the workspace root or absolute (optional; in the persistent shell this also changes the directory for later commands). The shell starts in the workspace root"
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": format!(
+                        "Kill the command after this many milliseconds (default: {})",
+                        self.config.default_timeout_ms
+                    )
+                },
+                "run_in_background": {
+                    "type": "boolean",
+                    "description": "Start the command in the background and return a process handle instead of waiting for it (no timeout applies)"
                 },
-                "skip_approval": {
+                "restart": {
                     "type": "boolean",
-                    "description": "Skip user approval prompt. Use true ONLY for read-only commands (e.g., git status, ls, ps). Default: false",
-                    "default": false
+                    "description": "Restart the shell, discarding its state, before running 'command' (which may then be empty)"
                 },
                 "description": {
                     "type": "string",
@@ -41,48 +104,165 @@ impl Tool for BashTool {
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput> {
         let command = input
             .get("command")
             .and_then(|v| v.as_str())
             .context("Missing command")?;
-        let cwd = input.get("cwd").and_then(|v| v.as_str());
-        let skip_approval = input
-            .get("skip_approval")
+        let cwd = input
+            .get("cwd")
+            .and_then(|v| v.as_str())
+            .map(|dir| ctx.resolve(dir))
+            .transpose()?;
+        let cwd = cwd.as_deref();
+        let timeout_ms = input
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(self.config.default_timeout_ms);
+        let run_in_background = input
+            .get("run_in_background")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
-        let description = input.get("description").and_then(|v| v.as_str());
-
-        // Approval mechanism
-        if !skip_approval {
-            println!("\n🔍 Bash Command Approval Required:");
-            if let Some(desc) = description {
-                println!("   Description: {}", desc);
-            }
-            println!("   Command: {}", command);
-            if let Some(working_dir) = cwd {
-                println!("   Working directory: {}", working_dir);
-            }
-            print!("\nExecute this command? [y/N]: ");
-            io::stdout().flush()?;
-
-            let mut input_line = String::new();
-            io::stdin().read_line(&mut input_line)?;
-            let approved = matches!(input_line.trim().to_lowercase().as_str(), "y" | "yes");
-
-            if !approved {
+        let restart = input
+            .get("restart")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if restart {
+            *self.shell.lock().await = None;
+            if command.trim().is_empty() {
                 return Ok(ToolOutput {
-                    output: json!({
-                        "approved": false,
-                        "command": command
-                    }),
-                    observation: "Command execution cancelled by user".to_string(),
-                    display: Some("User declined to execute the command".to_string()),
-                    status: "cancelled".to_string(),
+                    output: json!({ "restarted": true }),
+                    observation: "Shell restarted".to_string(),
+                    display: None,
+                    status: "success".to_string(),
                 });
             }
         }
 
+        if run_in_background {
+            return self.start_background(command, cwd, ctx.root()).await;
+        }
+
+        let timeout = Duration::from_millis(timeout_ms);
+        let run = if self.config.persistent_shell && cfg!(unix) {
+            self.run_in_shell(command, cwd, ctx.root(), timeout).await?
+        } else {
+            self.run_once(command, cwd, ctx.root(), timeout).await?
+        };
+        Ok(self.build_output(run, timeout_ms))
+    }
+}
+
+/// Captured result of one command
+struct CommandRun {
+    /// None if the command timed out
+    exit_code: Option<i32>,
+    stdout: OutputCapture,
+    stderr: OutputCapture,
+    /// The persistent shell was lost (timeout or `exit`) and will be started afresh
+    shell_reset: bool,
+}
+
+impl BashTool {
+    /// Start the command as a background process. Without `cwd` it starts in the
+    /// persistent shell's current directory, or the workspace root.
+    async fn start_background(
+        &self,
+        command: &str,
+        cwd: Option<&Path>,
+        root: &Path,
+    ) -> Result<ToolOutput> {
+        let shell_dir = match cwd {
+            Some(_) => None,
+            None => self.shell_directory().await,
+        };
+        let cwd = cwd.or(shell_dir.as_deref()).unwrap_or(root);
+        let (handle, pid) = self
+            .background
+            .spawn(command, cwd, self.config.max_output_bytes)?;
+
+        Ok(ToolOutput {
+            output: json!({ "handle": handle, "pid": pid }),
+            observation: format!(
+                "Started in the background as {}. Use the process tool to read its output, check its status or kill it",
+                handle
+            ),
+            display: None,
+            status: "success".to_string(),
+        })
+    }
+
+    /// Current directory of the persistent shell, if one is running
+    async fn shell_directory(&self) -> Option<PathBuf> {
+        let mut slot = self.shell.lock().await;
+        let shell = slot.as_mut()?;
+        let mut stdout = OutputCapture::new(4096);
+        let mut stderr = OutputCapture::new(4096);
+        match shell
+            .run("pwd", None, &mut stdout, &mut stderr, false)
+            .await
+        {
+            Ok(ShellStatus::Finished(0)) => Some(stdout.into_string().trim_end().into()),
+            _ => None,
+        }
+    }
+
+    /// Run the command in the session's long-lived shell, starting it if needed
+    async fn run_in_shell(
+        &self,
+        command: &str,
+        cwd: Option<&Path>,
+        root: &Path,
+        timeout: Duration,
+    ) -> Result<CommandRun> {
+        let mut slot = self.shell.lock().await;
+        // Taken out while the command runs: if this future is dropped (turn interrupted)
+        // the shell is dropped with it, killing whatever was running
+        let mut shell = match slot.take() {
+            Some(shell) => shell,
+            None => ShellSession::start(root)?,
+        };
+
+        let mut stdout = OutputCapture::new(self.config.max_output_bytes);
+        let mut stderr = OutputCapture::new(self.config.max_output_bytes);
+        let result = tokio::time::timeout(
+            timeout,
+            shell.run(
+                command,
+                cwd,
+                &mut stdout,
+                &mut stderr,
+                self.config.live_output,
+            ),
+        )
+        .await;
+
+        let (exit_code, shell_reset) = match result {
+            Ok(Ok(ShellStatus::Finished(code))) => {
+                *slot = Some(shell);
+                (Some(code), false)
+            }
+            Ok(Ok(ShellStatus::Exited(code))) => (Some(code), true),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => (None, true),
+        };
+
+        Ok(CommandRun {
+            exit_code,
+            stdout,
+            stderr,
+            shell_reset,
+        })
+    }
+
+    /// Run the command in a fresh `sh -c` (or `cmd /C` on Windows)
+    async fn run_once(
+        &self,
+        command: &str,
+        cwd: Option<&Path>,
+        root: &Path,
+        timeout: Duration,
+    ) -> Result<CommandRun> {
         let mut cmd = if cfg!(target_os = "windows") {
             let mut c = Command::new("cmd");
             c.args(["/C", command]);
@@ -93,51 +273,435 @@ impl Tool for BashTool {
             c
         };
 
-        if let Some(working_dir) = cwd {
-            cmd.current_dir(working_dir);
-        }
+        cmd.current_dir(cwd.unwrap_or(root));
 
-        let output = cmd
+        // Own process group, so a timeout or interrupt can kill everything the command started
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output()
+            .spawn()
             .context("Failed to execute command")?;
+        let mut group = ProcessGroup::new(child.id());
+
+        let live = self.config.live_output;
+        let mut stdout = OutputCapture::new(self.config.max_output_bytes);
+        let mut stderr = OutputCapture::new(self.config.max_output_bytes);
+        let stdout_pipe = child.stdout.take().context("Failed to capture stdout")?;
+        let stderr_pipe = child.stderr.take().context("Failed to capture stderr")?;
 
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        let success = output.status.success();
+        let run = async {
+            tokio::try_join!(
+                stdout.read_from(stdout_pipe, live),
+                stderr.read_from(stderr_pipe, live)
+            )?;
+            child.wait().await.context("Failed to wait for command")
+        };
+        let exit_code = match tokio::time::timeout(timeout, run).await {
+            Ok(status) => {
+                group.disarm();
+                Some(status?.code().unwrap_or(-1))
+            }
+            Err(_) => {
+                group.kill();
+                None
+            }
+        };
 
-        let observation = if success {
-            if stdout.is_empty() && stderr.is_empty() {
+        Ok(CommandRun {
+            exit_code,
+            stdout,
+            stderr,
+            shell_reset: false,
+        })
+    }
+
+    fn build_output(&self, run: CommandRun, timeout_ms: u64) -> ToolOutput {
+        let CommandRun {
+            exit_code,
+            stdout,
+            stderr,
+            shell_reset,
+        } = run;
+        let timed_out = exit_code.is_none();
+        let success = exit_code == Some(0);
+
+        let mut observation = if timed_out {
+            format!("Command timed out after {} ms and was killed", timeout_ms)
+        } else if success {
+            if stdout.total == 0 && stderr.total == 0 {
                 "Command executed successfully (no output)".to_string()
             } else {
-                format!("Command executed successfully")
+                "Command executed successfully".to_string()
             }
         } else {
-            format!(
-                "Command failed with exit code: {}",
-                output.status.code().unwrap_or(-1)
-            )
+            format!("Command failed with exit code: {}", exit_code.unwrap_or(-1))
         };
+        if shell_reset {
+            observation.push_str(
+                ". The shell was restarted, so directory and environment changes were lost",
+            );
+        }
+
+        let truncated = stdout.is_truncated() || stderr.is_truncated();
+        let stdout_bytes = stdout.total;
+        let stderr_bytes = stderr.total;
+        let stdout = stdout.into_string();
+        let stderr = stderr.into_string();
 
-        let display = if !stdout.is_empty() {
-            stdout.clone()
-        } else if !stderr.is_empty() {
-            stderr.clone()
+        // Output already went to the terminal while the command ran
+        let display = if self.config.live_output {
+            None
+        } else if !stdout.is_empty() {
+            Some(stdout.clone())
         } else {
-            String::new()
+            Some(stderr.clone())
         };
 
-        Ok(ToolOutput {
+        ToolOutput {
             output: json!({
                 "stdout": stdout,
                 "stderr": stderr,
-                "exit_code": output.status.code().unwrap_or(-1),
-                "success": success
+                "exit_code": exit_code.unwrap_or(-1),
+                "success": success,
+                "timed_out": timed_out,
+                "truncated": truncated,
+                "stdout_bytes": stdout_bytes,
+                "stderr_bytes": stderr_bytes,
+                "shell_reset": shell_reset
             }),
             observation,
-            display: Some(display),
+            display,
             status: if success { "success" } else { "error" }.to_string(),
-        })
+        }
+    }
+}
+
+/// Keeps the first and last `limit / 2` bytes of a stream and counts the rest
+pub(super) struct OutputCapture {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    total: usize,
+    limit: usize,
+}
+
+impl OutputCapture {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            total: 0,
+            limit,
+        }
+    }
+
+    pub(super) fn push(&mut self, mut bytes: &[u8]) {
+        self.total += bytes.len();
+
+        let head_room = (self.limit - self.limit / 2).saturating_sub(self.head.len());
+        let into_head = head_room.min(bytes.len());
+        self.head.extend_from_slice(&bytes[..into_head]);
+        bytes = &bytes[into_head..];
+
+        let tail_limit = self.limit / 2;
+        self.tail.extend(bytes);
+        if self.tail.len() > tail_limit {
+            self.tail.drain(..self.tail.len() - tail_limit);
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.total > self.head.len() + self.tail.len()
+    }
+
+    fn into_string(self) -> String {
+        let omitted = self.total - self.head.len() - self.tail.len();
+        let mut text = String::from_utf8_lossy(&self.head).into_owned();
+        if omitted > 0 {
+            text.push_str(&format!("\n\n... [{} bytes omitted] ...\n\n", omitted));
+        }
+        let tail: Vec<u8> = self.tail.into();
+        text.push_str(&String::from_utf8_lossy(&tail));
+        text
+    }
+
+    async fn read_from(&mut self, mut pipe: impl AsyncRead + Unpin, live: bool) -> Result<()> {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = pipe.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            if live {
+                print!("{}", String::from_utf8_lossy(&buf[..n]).dimmed());
+                let _ = io::stdout().flush();
+            }
+            self.push(&buf[..n]);
+        }
+    }
+}
+
+/// Kills the command's whole process group when dropped, unless it exited normally.
+/// Covers timeouts and turns interrupted while the command is running.
+pub(super) struct ProcessGroup(Option<u32>);
+
+impl ProcessGroup {
+    pub(super) fn new(leader: Option<u32>) -> Self {
+        Self(leader)
+    }
+
+    pub(super) fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0 {
+            // The group id equals the leader's pid (see `process_group(0)`)
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        self.0 = None;
+    }
+
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> ToolContext {
+        ToolContext::new(".", false)
+    }
+
+    fn quiet_tool(max_output_bytes: usize) -> BashTool {
+        BashTool::new(
+            BashConfig {
+                max_output_bytes,
+                live_output: false,
+                ..Default::default()
+            },
+            BackgroundProcesses::new(),
+        )
+    }
+
+    async fn run(tool: &BashTool, command: &str) -> ToolOutput {
+        tool.execute(json!({ "command": command }), &context())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn capture_keeps_head_and_tail() {
+        let mut capture = OutputCapture::new(10);
+        capture.push(b"abcdefgh");
+        capture.push(b"ijklmnopqrst");
+
+        assert!(capture.is_truncated());
+        assert_eq!(capture.total, 20);
+        assert_eq!(
+            capture.into_string(),
+            "abcde\n\n... [10 bytes omitted] ...\n\npqrst"
+        );
+
+        let mut small = OutputCapture::new(10);
+        small.push(b"short");
+        assert!(!small.is_truncated());
+        assert_eq!(small.into_string(), "short");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn truncates_large_output() {
+        let result = quiet_tool(1000)
+            .execute(
+                json!({
+                "command": "seq 1 20000" }),
+                &context(),
+            )
+            .await
+            .unwrap();
+
+        let output = &result.output;
+        assert_eq!(output["success"], true);
+        assert_eq!(output["truncated"], true);
+        assert_eq!(output["stdout_bytes"], 108_894);
+        let stdout = output["stdout"].as_str().unwrap();
+        assert!(stdout.starts_with("1\n2\n3\n"));
+        assert!(stdout.ends_with("19999\n20000\n"));
+        assert!(stdout.contains("[107894 bytes omitted]"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn timeout_kills_the_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let started = std::time::Instant::now();
+
+        let result = quiet_tool(1000)
+            .execute(
+                json!({
+                "command": "sleep 30 & echo $! > bg.pid; echo started; wait",
+                "cwd": dir.path().to_str().unwrap(),
+                "timeout_ms": 300 }),
+                &context(),
+            )
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(result.status, "error");
+        assert_eq!(result.output["timed_out"], true);
+        assert_eq!(result.output["stdout"], "started\n");
+
+        // The background sleep was in the same group and is gone too
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let pid = std::fs::read_to_string(dir.path().join("bg.pid")).unwrap();
+        let state =
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(state.is_empty() || state.contains(") Z"), "{}", state);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_state_persists_between_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = quiet_tool(1000);
+
+        let result = tool
+            .execute(
+                json!({
+                "command": "export GREETING=hello; f() { echo \"fn $1\"; }",
+                "cwd": dir.path().to_str().unwrap() }),
+                &context(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.status, "success");
+
+        let result = run(&tool, "pwd; echo $GREETING; f x").await;
+        let expected = format!(
+            "{}\nhello\nfn x\n",
+            dir.path().canonicalize().unwrap().display()
+        );
+        assert_eq!(result.output["stdout"], expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_reports_exit_codes_and_streams() {
+        let tool = quiet_tool(1000);
+
+        let result = run(&tool, "false").await;
+        assert_eq!(result.status, "error");
+        assert_eq!(result.output["exit_code"], 1);
+        assert_eq!(result.output["shell_reset"], false);
+
+        let result = run(&tool, "printf partial; echo oops >&2").await;
+        assert_eq!(result.output["success"], true);
+        assert_eq!(result.output["stdout"], "partial");
+        assert_eq!(result.output["stderr"], "oops\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_restarts_after_exit_restart_and_timeout() {
+        let tool = quiet_tool(1000);
+
+        run(&tool, "MARK=1").await;
+        let result = run(&tool, "exit 3").await;
+        assert_eq!(result.output["exit_code"], 3);
+        assert_eq!(result.output["shell_reset"], true);
+        assert_eq!(
+            run(&tool, "echo \"[$MARK]\"").await.output["stdout"],
+            "[]\n"
+        );
+
+        run(&tool, "MARK=2").await;
+        let result = tool
+            .execute(json!({ "command": "", "restart": true }), &context())
+            .await
+            .unwrap();
+        assert_eq!(result.observation, "Shell restarted");
+        assert_eq!(
+            run(&tool, "echo \"[$MARK]\"").await.output["stdout"],
+            "[]\n"
+        );
+
+        run(&tool, "MARK=3").await;
+        let result = tool
+            .execute(
+                json!({ "command": "sleep 30", "timeout_ms": 200 }),
+                &context(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.output["timed_out"], true);
+        assert_eq!(result.output["shell_reset"], true);
+        assert_eq!(
+            run(&tool, "echo \"[$MARK]\"").await.output["stdout"],
+            "[]\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_survives_unterminated_quotes_and_heredocs() {
+        let tool = quiet_tool(1000);
+        let ctx = context();
+        let run_briefly =
+            |command: &str| tool.execute(json!({ "command": command, "timeout_ms": 5000 }), &ctx);
+
+        run(&tool, "MARK=1").await;
+        let result = run_briefly("echo \"unclosed").await.unwrap();
+        assert_eq!(result.output["timed_out"], false);
+        assert_eq!(result.output["exit_code"], 2);
+        assert_ne!(result.output["stderr"], "");
+
+        let result = run_briefly("cat <<EOF\nno terminator").await.unwrap();
+        assert_eq!(result.output["timed_out"], false);
+        assert_eq!(result.output["stdout"], "no terminator\n");
+
+        // Neither wedged nor restarted the shell
+        let result = run_briefly("echo \"[$MARK]\"").await.unwrap();
+        assert_eq!(result.output["shell_reset"], false);
+        assert_eq!(result.output["stdout"], "[1]\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn background_commands_start_in_the_shell_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = quiet_tool(1000);
+        run(&tool, &format!("cd '{}'", dir.path().display())).await;
+
+        let result = tool
+            .execute(
+                json!({
+                "command": "pwd",
+                "run_in_background": true }),
+                &context(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.output["handle"], "bg-1");
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let output = tool.background.read("bg-1").unwrap();
+        assert_eq!(output["exit_code"], 0);
+        assert_eq!(
+            output["stdout"],
+            format!("{}\n", dir.path().canonicalize().unwrap().display())
+        );
     }
 }
@@ -0,0 +1,651 @@
+use super::file_search::walker;
+use super::{Tool, ToolContext, ToolOutput};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use tree_sitter::{Node, Parser, Tree};
+
+/// Definitions returned by a workspace search at most
+const MAX_DEFINITIONS: usize = 50;
+
+/// Larger files are skipped by the workspace search (usually generated code)
+pub(crate) const MAX_FILE_BYTES: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    Rust,
+    Python,
+    TypeScript,
+    Tsx,
+    Go,
+}
+
+impl Language {
+    fn for_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Self::Rust),
+            "py" | "pyi" => Some(Self::Python),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Python => "python",
+            Self::TypeScript | Self::Tsx => "typescript",
+            Self::Go => "go",
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+}
+
+/// A definition found in a source file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Symbol {
+    pub name: String,
+    /// function, method, struct, class, trait, interface, enum, type, impl, module, ...
+    pub kind: &'static str,
+    /// The type, class or module it is defined in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Whether definitions can be extracted from this file type
+pub fn is_supported(path: &Path) -> bool {
+    Language::for_path(path).is_some()
+}
+
+fn parse(path: &Path, source: &str) -> Option<(Language, Tree)> {
+    let language = Language::for_path(path)?;
+    let mut parser = Parser::new();
+    parser.set_language(&language.grammar()).ok()?;
+    Some((language, parser.parse(source, None)?))
+}
+
+/// Definitions in `source`, in file order, or None if the language is not supported
+pub fn file_symbols(path: &Path, source: &str) -> Option<Vec<Symbol>> {
+    let (language, tree) = parse(path, source)?;
+    let mut symbols = Vec::new();
+    collect(
+        language,
+        tree.root_node(),
+        source.as_bytes(),
+        None,
+        &mut symbols,
+    );
+    Some(symbols)
+}
+
+/// Every identifier used in `source` (names, types, fields), or None if the language
+/// is not supported
+pub fn file_identifiers(path: &Path, source: &str) -> Option<BTreeSet<String>> {
+    let (_, tree) = parse(path, source)?;
+    let mut identifiers = BTreeSet::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.kind().ends_with("identifier") && node.child_count() == 0 {
+            identifiers.insert(text(node, source.as_bytes()));
+        }
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        loop {
+            if !cursor.goto_parent() {
+                return Some(identifiers);
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+        }
+    }
+}
+
+fn text(node: Node, source: &[u8]) -> String {
+    node.utf8_text(source).unwrap_or_default().to_string()
+}
+
+fn field_text(node: Node, field: &str, source: &[u8]) -> Option<String> {
+    node.child_by_field_name(field).map(|n| text(n, source))
+}
+
+/// `Foo` from `Foo<T>`, `*Foo` or `&mut Foo`
+fn base_type_name(name: &str) -> String {
+    let name = name.split('<').next().unwrap_or(name);
+    name.trim_start_matches(['&', '*'])
+        .trim_start_matches("mut ")
+        .trim()
+        .to_string()
+}
+
+/// Visit the children of `node`, recording definitions. Function bodies are not
+/// searched; type and module bodies are, with the type or module as container.
+fn collect(
+    language: Language,
+    node: Node,
+    source: &[u8],
+    container: Option<&str>,
+    symbols: &mut Vec<Symbol>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let (kind, name, nested) = match language {
+            Language::Rust => rust_symbol(child, source),
+            Language::Python => python_symbol(child, source, container),
+            Language::TypeScript | Language::Tsx => typescript_symbol(child, source, container),
+            Language::Go => go_symbol(child, source),
+        };
+
+        if let (Some(kind), Some(name)) = (kind, &name) {
+            let container = match (language, kind) {
+                // Go methods belong to their receiver type
+                (Language::Go, "method") => go_receiver(child, source),
+                _ => container.map(str::to_string),
+            };
+            symbols.push(Symbol {
+                name: name.clone(),
+                kind,
+                container,
+                start_line: child.start_position().row + 1,
+                end_line: child.end_position().row + 1,
+            });
+        }
+        match nested {
+            Nested::No => {}
+            Nested::Same => collect(language, child, source, container, symbols),
+            Nested::Inside(body) => {
+                if let Some(body) = child.child_by_field_name(body) {
+                    let inner = name.as_deref().map(base_type_name);
+                    collect(language, body, source, inner.as_deref(), symbols);
+                }
+            }
+        }
+    }
+}
+
+/// Whether to look for definitions inside a node
+enum Nested {
+    No,
+    /// In its children, with the same container (wrappers such as `export`)
+    Same,
+    /// In this field, with the node's name as container
+    Inside(&'static str),
+}
+
+type Found = (Option<&'static str>, Option<String>, Nested);
+
+fn rust_symbol(node: Node, source: &[u8]) -> Found {
+    let name = || field_text(node, "name", source);
+    match node.kind() {
+        "function_item" | "function_signature_item" => {
+            // Functions in an impl or trait body, not in a module
+            let in_type = node
+                .parent()
+                .and_then(|body| body.parent())
+                .is_some_and(|owner| matches!(owner.kind(), "impl_item" | "trait_item"));
+            let kind = if in_type { "method" } else { "function" };
+            (Some(kind), name(), Nested::No)
+        }
+        "struct_item" => (Some("struct"), name(), Nested::No),
+        "enum_item" => (Some("enum"), name(), Nested::No),
+        "union_item" => (Some("union"), name(), Nested::No),
+        "type_item" | "associated_type" => (Some("type"), name(), Nested::No),
+        "const_item" => (Some("const"), name(), Nested::No),
+        "static_item" => (Some("static"), name(), Nested::No),
+        "macro_definition" => (Some("macro"), name(), Nested::No),
+        "trait_item" => (Some("trait"), name(), Nested::Inside("body")),
+        "mod_item" => (Some("module"), name(), Nested::Inside("body")),
+        "impl_item" => {
+            let name = field_text(node, "type", source);
+            (Some("impl"), name, Nested::Inside("body"))
+        }
+        _ => (None, None, Nested::No),
+    }
+}
+
+fn python_symbol(node: Node, source: &[u8], container: Option<&str>) -> Found {
+    let name = || field_text(node, "name", source);
+    match node.kind() {
+        "function_definition" => {
+            let kind = if container.is_some() {
+                "method"
+            } else {
+                "function"
+            };
+            (Some(kind), name(), Nested::No)
+        }
+        "class_definition" => (Some("class"), name(), Nested::Inside("body")),
+        "decorated_definition" => (None, None, Nested::Same),
+        _ => (None, None, Nested::No),
+    }
+}
+
+fn typescript_symbol(node: Node, source: &[u8], container: Option<&str>) -> Found {
+    let name = || field_text(node, "name", source);
+    match node.kind() {
+        "function_declaration" | "generator_function_declaration" | "function_signature" => {
+            (Some("function"), name(), Nested::No)
+        }
+        "method_definition" | "abstract_method_signature" | "method_signature"
+            if container.is_some() =>
+        {
+            (Some("method"), name(), Nested::No)
+        }
+        "class_declaration" | "abstract_class_declaration" => {
+            (Some("class"), name(), Nested::Inside("body"))
+        }
+        "interface_declaration" => (Some("interface"), name(), Nested::Inside("body")),
+        "type_alias_declaration" => (Some("type"), name(), Nested::No),
+        "enum_declaration" => (Some("enum"), name(), Nested::No),
+        "internal_module" | "module" => (Some("namespace"), name(), Nested::Inside("body")),
+        // `const handler = () => ...` and `export ...` wrap definitions
+        "lexical_declaration" | "export_statement" | "ambient_declaration" => {
+            (None, None, Nested::Same)
+        }
+        "variable_declarator" => {
+            let is_function = node
+                .child_by_field_name("value")
+                .is_some_and(|v| matches!(v.kind(), "arrow_function" | "function_expression"));
+            if is_function {
+                (Some("function"), name(), Nested::No)
+            } else {
+                (None, None, Nested::No)
+            }
+        }
+        _ => (None, None, Nested::No),
+    }
+}
+
+fn go_symbol(node: Node, source: &[u8]) -> Found {
+    let name = || field_text(node, "name", source);
+    match node.kind() {
+        "function_declaration" => (Some("function"), name(), Nested::No),
+        "method_declaration" => (Some("method"), name(), Nested::No),
+        "type_declaration" => (None, None, Nested::Same),
+        "type_spec" | "type_alias" => {
+            let kind = match node.child_by_field_name("type").map(|t| t.kind()) {
+                Some("struct_type") => "struct",
+                Some("interface_type") => "interface",
+                _ => "type",
+            };
+            (Some(kind), name(), Nested::No)
+        }
+        _ => (None, None, Nested::No),
+    }
+}
+
+/// `Server` from `func (s *Server) Start()`
+fn go_receiver(node: Node, source: &[u8]) -> Option<String> {
+    let receiver = node.child_by_field_name("receiver")?;
+    let mut cursor = receiver.walk();
+    let parameter = receiver.named_children(&mut cursor).next()?;
+    let receiver_type = field_text(parameter, "type", source)?;
+    Some(base_type_name(&receiver_type))
+}
+
+/// Whether `symbol` is what `query` names: `save`, `Session::save` or `Session.save`
+fn matches_query(symbol: &Symbol, query: &str) -> bool {
+    let (container, name) = match query.rsplit_once("::").or_else(|| query.rsplit_once('.')) {
+        Some((container, name)) => (Some(container), name),
+        None => (None, query),
+    };
+    if symbol.name != name {
+        return false;
+    }
+    match container {
+        Some(container) => symbol.container.as_deref().is_some_and(|c| {
+            c == container || c.rsplit("::").next() == container.rsplit("::").next()
+        }),
+        None => true,
+    }
+}
+
+fn format_symbol(symbol: &Symbol) -> String {
+    let name = match &symbol.container {
+        Some(container) => format!("{}::{}", container, symbol.name),
+        None => symbol.name.clone(),
+    };
+    format!(
+        "{} {} (lines {}-{})",
+        symbol.kind, name, symbol.start_line, symbol.end_line
+    )
+}
+
+/// Outlines source files and finds where symbols are defined, using tree-sitter
+pub struct CodeSymbolsTool;
+
+impl CodeSymbolsTool {
+    fn outline(&self, path: &Path, file_path: &str) -> Result<ToolOutput> {
+        let language = Language::for_path(path).with_context(|| {
+            format!(
+                "Unsupported file type: {} (supported: Rust, Python, TypeScript, Go)",
+                file_path
+            )
+        })?;
+        let source =
+            fs::read_to_string(path).context(format!("Failed to read file: {}", file_path))?;
+        let symbols = file_symbols(path, &source).context("Failed to parse file")?;
+
+        let display = symbols
+            .iter()
+            .map(|s| {
+                let indent = if s.container.is_some() { "  " } else { "" };
+                format!(
+                    "{}{} {} (lines {}-{})",
+                    indent, s.kind, s.name, s.start_line, s.end_line
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(ToolOutput {
+            observation: format!("Found {} symbol(s) in {}", symbols.len(), file_path),
+            output: json!({
+                "file_path": file_path,
+                "language": language.name(),
+                "symbols": symbols
+            }),
+            display: Some(display),
+            status: "success".to_string(),
+        })
+    }
+}
+
+/// Definitions named `query` in supported files under `dir`, in path order
+fn find_definitions(
+    dir: &Path,
+    query: &str,
+    display: impl Fn(&Path) -> String,
+) -> Result<(Vec<(String, Symbol)>, bool)> {
+    let name = query.rsplit([':', '.']).next().unwrap_or(query);
+    let mut matches = Vec::new();
+    for entry in walker(dir, None)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let supported = is_supported(path)
+            && entry
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.len() <= MAX_FILE_BYTES);
+        if !supported {
+            continue;
+        }
+        let Ok(source) = fs::read_to_string(path) else {
+            continue;
+        };
+        // Cheap check before parsing
+        if !source.contains(name) {
+            continue;
+        }
+        for symbol in file_symbols(path, &source).unwrap_or_default() {
+            if !matches_query(&symbol, query) {
+                continue;
+            }
+            if matches.len() == MAX_DEFINITIONS {
+                return Ok((matches, true));
+            }
+            matches.push((display(path), symbol));
+        }
+    }
+    Ok((matches, false))
+}
+
+#[async_trait]
+impl Tool for CodeSymbolsTool {
+    fn name(&self) -> &str {
+        "code_symbols"
+    }
+
+    fn description(&self) -> &str {
+        "List the functions, types and other definitions in a source file, or find where a symbol is defined in the workspace. Supports Rust, Python, TypeScript and Go. REQUIRED: Set 'operation' to 'outline' (with 'file_path') or 'definition' (with 'symbol', e.g. 'save', 'Session::save' or 'Session.save')."
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "operation": {
+                    "type": "string",
+                    "enum": ["outline", "definition"],
+                    "description": "outline: symbols in one file; definition: where a symbol is defined"
+                },
+                "file_path": {
+                    "type": "string",
+                    "description": "File to outline, relative to the workspace root or absolute"
+                },
+                "symbol": {
+                    "type": "string",
+                    "description": "Name to look up, optionally qualified with its type or module"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Directory to search for definitions (default: the workspace root)"
+                }
+            },
+            "required": ["operation"]
+        })
+    }
+
+    async fn execute(&self, input: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput> {
+        let operation = input
+            .get("operation")
+            .and_then(|v| v.as_str())
+            .context("Missing operation")?;
+
+        match operation {
+            "outline" => {
+                let file_path = input
+                    .get("file_path")
+                    .and_then(|v| v.as_str())
+                    .context("Missing file_path")?;
+                let path = ctx.resolve(file_path)?;
+                self.outline(&path, file_path)
+            }
+            "definition" => {
+                let query = input
+                    .get("symbol")
+                    .and_then(|v| v.as_str())
+                    .context("Missing symbol")?
+                    .to_string();
+                let path = input.get("path").and_then(|v| v.as_str()).unwrap_or(".");
+                let dir = ctx.resolve(path)?;
+                let ctx = ctx.clone();
+
+                let search_query = query.clone();
+                let (matches, truncated) = tokio::task::spawn_blocking(move || {
+                    find_definitions(&dir, &search_query, |p| ctx.display_path(p))
+                })
+                .await
+                .context("Search task failed")??;
+
+                let display = matches
+                    .iter()
+                    .map(|(file, symbol)| format!("{}: {}", file, format_symbol(symbol)))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let matches: Vec<serde_json::Value> = matches
+                    .into_iter()
+                    .map(|(file, symbol)| {
+                        let mut found = json!(symbol);
+                        found["file"] = json!(file);
+                        found
+                    })
+                    .collect();
+                let observation = if matches.is_empty() {
+                    format!("No definition of '{}' found", query)
+                } else {
+                    format!("Found {} definition(s) of '{}'", matches.len(), query)
+                };
+                Ok(ToolOutput {
+                    output: json!({ "symbol": query, "matches": matches, "truncated": truncated }),
+                    observation,
+                    display: (!display.is_empty()).then_some(display),
+                    status: "success".to_string(),
+                })
+            }
+            _ => Err(anyhow::anyhow!("Unknown operation: {}", operation)),
+        }
+    }
+
+    fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    fn outline(path: &str, source: &str) -> Vec<String> {
+        file_symbols(Path::new(path), source)
+            .unwrap()
+            .iter()
+            .map(format_symbol)
+            .collect()
+    }
+
+    #[test]
+    fn outlines_each_language() {
+        let rust = "\
+pub struct Session<T> {
+    id: T,
+}
+
+impl<T> Session<T> {
+    pub fn save(&self) {
+        fn helper() {}
+    }
+}
+
+mod util {
+    pub fn join() {}
+}
+";
+        assert_eq!(
+            outline("a.rs", rust),
+            vec![
+                "struct Session (lines 1-3)",
+                "impl Session<T> (lines 5-9)",
+                "method Session::save (lines 6-8)",
+                "module util (lines 11-13)",
+                "function util::join (lines 12-12)",
+            ]
+        );
+
+        let python = "\
+class Agent:
+    @property
+    def name(self):
+        return 1
+
+def main():
+    pass
+";
+        assert_eq!(
+            outline("a.py", python),
+            vec![
+                "class Agent (lines 1-4)",
+                "method Agent::name (lines 3-4)",
+                "function main (lines 6-7)",
+            ]
+        );
+
+        let typescript = "\
+export interface Tool { run(): void; }
+export class Bash implements Tool {
+  run() {}
+}
+export const handler = () => 1;
+type Id = string;
+";
+        assert_eq!(
+            outline("a.ts", typescript),
+            vec![
+                "interface Tool (lines 1-1)",
+                "method Tool::run (lines 1-1)",
+                "class Bash (lines 2-4)",
+                "method Bash::run (lines 3-3)",
+                "function handler (lines 5-5)",
+                "type Id (lines 6-6)",
+            ]
+        );
+
+        let go = "\
+package main
+
+type Server struct{}
+
+func (s *Server) Start() {}
+
+func main() {}
+";
+        assert_eq!(
+            outline("a.go", go),
+            vec![
+                "struct Server (lines 3-3)",
+                "method Server::Start (lines 5-5)",
+                "function main (lines 7-7)",
+            ]
+        );
+        assert!(file_symbols(Path::new("a.txt"), "").is_none());
+    }
+
+    #[tokio::test]
+    async fn finds_definitions_across_the_workspace() {
+        let workspace = TempWorkspace::new();
+        workspace.write(
+            "src/session.rs",
+            "struct Session;\nimpl Session {\n    fn save(&self) {}\n}\n",
+        );
+        workspace.write("src/other.rs", "fn save() {}\n");
+        workspace.write(
+            "tools/save.py",
+            "class Store:\n    def save(self):\n        pass\n",
+        );
+        let ctx = ToolContext::new(workspace.path(), false);
+        let find = |symbol: &str| {
+            let ctx = ctx.clone();
+            let symbol = symbol.to_string();
+            async move {
+                CodeSymbolsTool
+                    .execute(json!({ "operation": "definition", "symbol": symbol }), &ctx)
+                    .await
+                    .unwrap()
+                    .display
+            }
+        };
+
+        assert_eq!(
+            find("save").await.unwrap(),
+            "src/other.rs: function save (lines 1-1)\n\
+             src/session.rs: method Session::save (lines 3-3)\n\
+             tools/save.py: method Store::save (lines 2-3)"
+        );
+        assert_eq!(
+            find("Session::save").await.unwrap(),
+            "src/session.rs: method Session::save (lines 3-3)"
+        );
+        assert_eq!(
+            find("Store.save").await.unwrap(),
+            "tools/save.py: method Store::save (lines 2-3)"
+        );
+        assert_eq!(find("missing").await, None);
+    }
+}
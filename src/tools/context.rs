@@ -0,0 +1,202 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::checkpoint::CheckpointStore;
+
+/// Workspace settings (the `workspace` section of the config file)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// Reject tool paths that leave the workspace, including through `..` and symlinks
+    pub strict: bool,
+}
+
+/// What every tool call runs against: the session's workspace root, checkpoint store
+/// and the files read so far. Relative paths in tool arguments resolve against the
+/// root rather than against the process directory.
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    root: PathBuf,
+    strict: bool,
+    checkpoints: Option<Arc<CheckpointStore>>,
+    /// Content hash of each file as the agent last read or wrote it
+    seen: Arc<Mutex<HashMap<PathBuf, u64>>>,
+}
+
+impl ToolContext {
+    pub fn new(root: impl AsRef<Path>, strict: bool) -> Self {
+        let root = root.as_ref();
+        let root = root
+            .canonicalize()
+            .unwrap_or_else(|_| normalize(&std::env::current_dir().unwrap_or_default().join(root)));
+        Self {
+            root,
+            strict,
+            checkpoints: None,
+            seen: Arc::default(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn checkpoints(&self) -> Option<&CheckpointStore> {
+        self.checkpoints.as_deref()
+    }
+
+    pub fn set_checkpoints(&mut self, store: CheckpointStore) {
+        self.checkpoints = Some(Arc::new(store));
+    }
+
+    /// Save `path` to the current checkpoint, if there is a store. Tools call this
+    /// before modifying a file.
+    pub fn snapshot(&self, path: &Path) -> Result<()> {
+        match &self.checkpoints {
+            Some(store) => store.snapshot(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Remember `content` as what the agent knows of `path`. Tools call this after
+    /// reading or writing a file.
+    pub fn record_seen(&self, path: &Path, content: &str) {
+        self.seen
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), content_hash(content));
+    }
+
+    pub fn forget_seen(&self, path: &Path) {
+        self.seen.lock().unwrap().remove(path);
+    }
+
+    /// Refuse to edit `path` if its `current` content is not what the agent last saw,
+    /// so edits based on stale line numbers or text don't clobber other changes. With
+    /// `require_read`, files the agent never read are refused too.
+    pub fn check_seen(&self, path: &Path, current: &str, require_read: bool) -> Result<()> {
+        match self.seen.lock().unwrap().get(path) {
+            Some(&hash) if hash != content_hash(current) => anyhow::bail!(
+                "File {} changed since last read; read it again before editing",
+                self.display_path(path)
+            ),
+            None if require_read => anyhow::bail!(
+                "File {} has not been read in this session; read it before editing",
+                self.display_path(path)
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Absolute path for a path argument. In strict mode, paths outside the root are
+    /// an error, whether they get there through `..`, an absolute path or a symlink.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let resolved = normalize(&self.root.join(path));
+        if self.strict && !self.contains(&resolved) {
+            anyhow::bail!(
+                "Path '{}' is outside the workspace {} (strict mode)",
+                path,
+                self.root.display()
+            );
+        }
+        Ok(resolved)
+    }
+
+    /// `path` relative to the root if it is inside, as given otherwise
+    pub fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Whether `path` (absolute, normalized) stays inside the root once symlinks in its
+    /// existing part are followed
+    fn contains(&self, path: &Path) -> bool {
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+        // The file itself may not exist yet; check its deepest existing ancestor
+        path.ancestors()
+            .find_map(|ancestor| ancestor.canonicalize().ok())
+            .is_some_and(|real| real.starts_with(&self.root))
+    }
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Resolve `.` and `..` without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    #[test]
+    fn resolves_relative_paths_against_the_root() {
+        let workspace = TempWorkspace::new();
+        let context = ToolContext::new(workspace.path(), false);
+        let root = workspace.path().canonicalize().unwrap();
+
+        assert_eq!(
+            context.resolve("src/main.rs").unwrap(),
+            root.join("src/main.rs")
+        );
+        assert_eq!(context.resolve("./a/../b.txt").unwrap(), root.join("b.txt"));
+        assert_eq!(
+            context.resolve("/etc/hosts").unwrap(),
+            Path::new("/etc/hosts")
+        );
+        assert_eq!(context.display_path(&root.join("src/lib.rs")), "src/lib.rs");
+        assert_eq!(context.display_path(Path::new("/etc/hosts")), "/etc/hosts");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn strict_mode_rejects_escapes() {
+        let workspace = TempWorkspace::new();
+        let outside = TempWorkspace::new();
+        workspace.write("src/lib.rs", "");
+        std::os::unix::fs::symlink(outside.path(), workspace.join("link")).unwrap();
+        let context = ToolContext::new(workspace.path(), true);
+
+        assert!(context.resolve("src/lib.rs").is_ok());
+        assert!(context.resolve("src/new/file.rs").is_ok());
+        assert!(context.resolve("src/../../escape.txt").is_err());
+        assert!(context.resolve("/etc/hosts").is_err());
+        assert!(context.resolve("link/file.txt").is_err());
+        assert!(ToolContext::new(workspace.path(), false)
+            .resolve("link/file.txt")
+            .is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+/// Lines of context around each change
+const CONTEXT: usize = 3;
+
+/// Above this many line pairs the changed region is shown as removed and re-added
+/// rather than aligned
+const MAX_ALIGN_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Unified diff from `old` to `new`, or an empty string if they are equal. `old` is
+/// None for a file that does not exist yet.
+pub fn unified_diff(path: &str, old: Option<&str>, new: &str) -> String {
+    let old_lines: Vec<&str> = old.unwrap_or_default().lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+    if lines.iter().all(|l| matches!(l, Line::Same(_))) {
+        return String::new();
+    }
+
+    let mut out = match old {
+        Some(_) => format!("--- a/{}\n", path),
+        None => "--- /dev/null\n".to_string(),
+    };
+    out.push_str(&format!("+++ b/{}\n", path));
+    for (start, end) in hunk_ranges(&lines) {
+        write_hunk(&mut out, &lines, start, end);
+    }
+    out
+}
+
+/// Line-level diff: common prefix and suffix, then an LCS alignment of the middle
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_mid, new_mid) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|l| Line::Same(l)).collect();
+    if old_mid.len() * new_mid.len() <= MAX_ALIGN_CELLS {
+        lines.extend(align(old_mid, new_mid));
+    } else {
+        lines.extend(old_mid.iter().map(|l| Line::Removed(l)));
+        lines.extend(new_mid.iter().map(|l| Line::Added(l)));
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|l| Line::Same(l)));
+    lines
+}
+
+/// Longest-common-subsequence alignment, removals before additions
+fn align<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j]: LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Ranges of `lines` to show: every change plus its context, merged where they touch
+fn hunk_ranges(lines: &[Line]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if matches!(line, Line::Same(_)) {
+            continue;
+        }
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + 1 + CONTEXT).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    ranges
+}
+
+fn write_hunk(out: &mut String, lines: &[Line], start: usize, end: usize) {
+    let old_before = lines[..start]
+        .iter()
+        .filter(|l| !matches!(l, Line::Added(_)))
+        .count();
+    let new_before = lines[..start]
+        .iter()
+        .filter(|l| !matches!(l, Line::Removed(_)))
+        .count();
+    let hunk = &lines[start..end];
+    let old_count = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+    let new_count = hunk
+        .iter()
+        .filter(|l| !matches!(l, Line::Removed(_)))
+        .count();
+
+    // An empty side is numbered by the line before it
+    let first = |before: usize, count: usize| if count == 0 { before } else { before + 1 };
+    out.push_str(&format!(
+        "@@ -{},{} +{},{} @@\n",
+        first(old_before, old_count),
+        old_count,
+        first(new_before, new_count),
+        new_count
+    ));
+    for line in hunk {
+        let (sign, text) = match line {
+            Line::Same(text) => (' ', text),
+            Line::Removed(text) => ('-', text),
+            Line::Added(text) => ('+', text),
+        };
+        out.push(sign);
+        out.push_str(text);
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_changes_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n";
+        assert_eq!(
+            unified_diff("src/n.txt", Some(old), new),
+            "--- a/src/n.txt\n+++ b/src/n.txt\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -12,3 +12,4 @@\n 12\n 13\n 14\n+15\n"
+        );
+    }
+
+    #[test]
+    fn diffs_new_and_unchanged_files() {
+        assert_eq!(
+            unified_diff("a.rs", None, "fn main() {}\n"),
+            "--- /dev/null\n+++ b/a.rs\n@@ -0,0 +1,1 @@\n+fn main() {}\n"
+        );
+        assert_eq!(unified_diff("a.rs", Some("same\n"), "same\n"), "");
+        assert_eq!(
+            unified_diff("a.rs", Some("a\nb\nc\n"), "a\nc\n"),
+            "--- a/a.rs\n+++ b/a.rs\n@@ -1,3 +1,2 @@\n a\n-b\n c\n"
+        );
+    }
+}
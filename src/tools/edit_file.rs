@@ -1,19 +1,38 @@
-use super::{Tool, ToolOutput};
+use super::diff::unified_diff;
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
 use serde_json::json;
 use std::fs;
-use std::io::Write;
 use std::path::Path;
 
-pub struct EditFileTool;
+/// Edit tool settings (the `edit` section of the config file)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EditConfig {
+    /// Refuse to modify files that were not read in this session
+    pub require_read: bool,
+}
+
+pub struct EditFileTool {
+    config: EditConfig,
+}
+
+impl EditFileTool {
+    pub fn new(config: EditConfig) -> Self {
+        Self { config }
+    }
+}
 
+#[async_trait]
 impl Tool for EditFileTool {
     fn name(&self) -> &str {
         "edit_file"
     }
 
     fn description(&self) -> &str {
-        "Create new files or edit existing files with various operations. REQUIRED: Set 'operation' to one of: 'create_file' (new file), 'replace_by_string' (find/replace unique text), 'replace_by_lines' (replace line range), or 'read_file' (view contents)."
+        "Create new files or edit existing files with various operations. REQUIRED: Set 'operation' to one of: 'create_file' (new file), 'replace_by_string' (find/replace unique text), 'replace_by_lines' (replace line range), or 'read_file' (view contents). Edits are refused if the file changed since you last read it; read it again first."
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -27,7 +46,7 @@ impl Tool for EditFileTool {
                 },
                 "file_path": {
                     "type": "string",
-                    "description": "Path to the file"
+                    "description": "Path to the file, relative to the workspace root or absolute"
                 },
                 "content": {
                     "type": "string",
@@ -54,7 +73,7 @@ impl Tool for EditFileTool {
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput> {
         let operation = input
             .get("operation")
             .and_then(|v| v.as_str())
@@ -63,14 +82,98 @@ impl Tool for EditFileTool {
             .get("file_path")
             .and_then(|v| v.as_str())
             .context("Missing file_path")?;
+        let path = ctx.resolve(file_path)?;
+        let path = path.as_path();
+
+        if operation == "read_file" {
+            let start_line = input
+                .get("start_line")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize);
+            let end_line = input
+                .get("end_line")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize);
+            let max_length = input
+                .get("max_length")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize);
+            return self.read_file(path, file_path, start_line, end_line, max_length, ctx);
+        }
+
+        let edit = self.plan(operation, path, file_path, &input, ctx)?;
+        let diff = unified_diff(&ctx.display_path(path), edit.before.as_deref(), &edit.after);
+
+        ctx.snapshot(path)?;
+        if edit.before.is_none() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, &edit.after).context(format!("Failed to write file: {}", file_path))?;
+        ctx.record_seen(path, &edit.after);
+
+        let mut output = edit.output;
+        output["diff"] = json!(diff);
+        Ok(ToolOutput {
+            output,
+            observation: edit.observation,
+            display: Some(edit.display),
+            status: "success".to_string(),
+        })
+    }
+
+    fn is_read_only(&self, input: &serde_json::Value) -> bool {
+        input.get("operation").and_then(|v| v.as_str()) == Some("read_file")
+    }
+
+    fn preview(&self, input: &serde_json::Value, ctx: &ToolContext) -> Result<Option<String>> {
+        let operation = input.get("operation").and_then(|v| v.as_str());
+        let file_path = input.get("file_path").and_then(|v| v.as_str());
+        let (Some(operation), Some(file_path)) = (operation, file_path) else {
+            return Ok(None);
+        };
+        if operation == "read_file" {
+            return Ok(None);
+        }
+
+        let path = ctx.resolve(file_path)?;
+        let edit = self.plan(operation, &path, file_path, input, ctx)?;
+        Ok(Some(unified_diff(
+            &ctx.display_path(&path),
+            edit.before.as_deref(),
+            &edit.after,
+        )))
+    }
+}
+
+/// A write worked out but not applied yet
+struct PlannedEdit {
+    /// Current content, None if the file is being created
+    before: Option<String>,
+    after: String,
+    output: serde_json::Value,
+    observation: String,
+    display: String,
+}
 
+impl EditFileTool {
+    /// Work out what a write operation would change, without touching the file
+    fn plan(
+        &self,
+        operation: &str,
+        path: &Path,
+        file_path: &str,
+        input: &serde_json::Value,
+        ctx: &ToolContext,
+    ) -> Result<PlannedEdit> {
         match operation {
             "create_file" => {
                 let content = input
                     .get("content")
                     .and_then(|v| v.as_str())
                     .context("Missing content")?;
-                self.create_file(file_path, content)
+                self.create_file(path, file_path, content)
             }
             "replace_by_string" => {
                 let old_string = input
@@ -81,7 +184,7 @@ impl Tool for EditFileTool {
                     .get("content")
                     .and_then(|v| v.as_str())
                     .context("Missing content")?;
-                self.replace_by_string(file_path, old_string, new_string)
+                self.replace_by_string(path, file_path, old_string, new_string, ctx)
             }
             "replace_by_lines" => {
                 let start_line = input
@@ -96,62 +199,41 @@ impl Tool for EditFileTool {
                     .get("content")
                     .and_then(|v| v.as_str())
                     .context("Missing content")?;
-                self.replace_by_lines(file_path, start_line, end_line, new_content)
-            }
-            "read_file" => {
-                let start_line = input
-                    .get("start_line")
-                    .and_then(|v| v.as_u64())
-                    .map(|n| n as usize);
-                let end_line = input
-                    .get("end_line")
-                    .and_then(|v| v.as_u64())
-                    .map(|n| n as usize);
-                let max_length = input
-                    .get("max_length")
-                    .and_then(|v| v.as_u64())
-                    .map(|n| n as usize);
-                self.read_file(file_path, start_line, end_line, max_length)
+                self.replace_by_lines(path, file_path, start_line, end_line, new_content, ctx)
             }
             _ => Err(anyhow::anyhow!("Unknown operation: {}", operation)),
         }
     }
-}
 
-impl EditFileTool {
-    fn create_file(&self, file_path: &str, content: &str) -> Result<ToolOutput> {
-        if Path::new(file_path).exists() {
+    fn create_file(&self, path: &Path, file_path: &str, content: &str) -> Result<PlannedEdit> {
+        if path.exists() {
             return Err(anyhow::anyhow!("File already exists: {}", file_path));
         }
 
-        if let Some(parent) = Path::new(file_path).parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        let mut file =
-            fs::File::create(file_path).context(format!("Failed to create file: {}", file_path))?;
-        file.write_all(content.as_bytes())?;
-
-        Ok(ToolOutput {
+        Ok(PlannedEdit {
+            before: None,
+            after: content.to_string(),
             output: json!({ "file_path": file_path, "lines": content.lines().count() }),
             observation: format!(
                 "Created file {} with {} lines",
                 file_path,
                 content.lines().count()
             ),
-            display: Some(format!("✓ Created {}", file_path)),
-            status: "success".to_string(),
+            display: format!("✓ Created {}", file_path),
         })
     }
 
     fn replace_by_string(
         &self,
+        path: &Path,
         file_path: &str,
         old_string: &str,
         new_string: &str,
-    ) -> Result<ToolOutput> {
+        ctx: &ToolContext,
+    ) -> Result<PlannedEdit> {
         let content =
-            fs::read_to_string(file_path).context(format!("Failed to read file: {}", file_path))?;
+            fs::read_to_string(path).context(format!("Failed to read file: {}", file_path))?;
+        ctx.check_seen(path, &content, self.config.require_read)?;
 
         let occurrences = content.matches(old_string).count();
 
@@ -168,26 +250,27 @@ impl EditFileTool {
 
         let new_content = content.replace(old_string, new_string);
 
-        fs::write(file_path, &new_content)
-            .context(format!("Failed to write file: {}", file_path))?;
-
-        Ok(ToolOutput {
+        Ok(PlannedEdit {
+            before: Some(content),
+            after: new_content,
             output: json!({ "file_path": file_path, "modified": true }),
             observation: format!("Replaced string in {}", file_path),
-            display: Some(format!("✓ Modified {}", file_path)),
-            status: "success".to_string(),
+            display: format!("✓ Modified {}", file_path),
         })
     }
 
     fn replace_by_lines(
         &self,
+        path: &Path,
         file_path: &str,
         start_line: usize,
         end_line: usize,
         new_content: &str,
-    ) -> Result<ToolOutput> {
+        ctx: &ToolContext,
+    ) -> Result<PlannedEdit> {
         let content =
-            fs::read_to_string(file_path).context(format!("Failed to read file: {}", file_path))?;
+            fs::read_to_string(path).context(format!("Failed to read file: {}", file_path))?;
+        ctx.check_seen(path, &content, self.config.require_read)?;
 
         let lines: Vec<&str> = content.lines().collect();
 
@@ -206,13 +289,12 @@ impl EditFileTool {
 
         let new_file_content = new_lines.join("\n") + "\n";
 
-        fs::write(file_path, &new_file_content)
-            .context(format!("Failed to write file: {}", file_path))?;
-
         let old_count = end_line - start_line + 1;
         let new_count = new_content.lines().count();
 
-        Ok(ToolOutput {
+        Ok(PlannedEdit {
+            before: Some(content),
+            after: new_file_content,
             output: json!({
                 "file_path": file_path,
                 "modified": true,
@@ -223,23 +305,25 @@ impl EditFileTool {
                 "Replaced lines {}-{} in {}",
                 start_line, end_line, file_path
             ),
-            display: Some(format!(
+            display: format!(
                 "✓ Modified {} ({} -> {} lines)",
                 file_path, old_count, new_count
-            )),
-            status: "success".to_string(),
+            ),
         })
     }
 
     fn read_file(
         &self,
+        path: &Path,
         file_path: &str,
         start_line: Option<usize>,
         end_line: Option<usize>,
         max_length: Option<usize>,
+        ctx: &ToolContext,
     ) -> Result<ToolOutput> {
         let content =
-            fs::read_to_string(file_path).context(format!("Failed to read file: {}", file_path))?;
+            fs::read_to_string(path).context(format!("Failed to read file: {}", file_path))?;
+        ctx.record_seen(path, &content);
 
         let lines: Vec<&str> = content.lines().collect();
         let total_lines = lines.len();
@@ -294,3 +378,98 @@ impl EditFileTool {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    async fn edit(
+        tool: &EditFileTool,
+        ctx: &ToolContext,
+        input: serde_json::Value,
+    ) -> Result<ToolOutput> {
+        tool.execute(input, ctx).await
+    }
+
+    #[tokio::test]
+    async fn refuses_edits_to_files_changed_since_last_read() {
+        let workspace = TempWorkspace::new();
+        workspace.write("a.txt", "one\ntwo\nthree\n");
+        let tool = EditFileTool::new(EditConfig::default());
+        let ctx = ToolContext::new(workspace.path(), false);
+        let read = json!({ "operation": "read_file", "file_path": "a.txt" });
+        let replace_line_2 = json!({
+            "operation": "replace_by_lines",
+            "file_path": "a.txt",
+            "start_line": 2,
+            "end_line": 2,
+            "content": "TWO"
+        });
+
+        edit(&tool, &ctx, read.clone()).await.unwrap();
+        // Someone else inserts a line above the one the agent wants to change
+        workspace.write("a.txt", "zero\none\ntwo\nthree\n");
+        let error = edit(&tool, &ctx, replace_line_2.clone())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "File a.txt changed since last read; read it again before editing"
+        );
+        assert_eq!(workspace.read("a.txt"), "zero\none\ntwo\nthree\n");
+
+        edit(&tool, &ctx, read).await.unwrap();
+        edit(&tool, &ctx, replace_line_2).await.unwrap();
+        // The agent's own edits keep the file current
+        edit(
+            &tool,
+            &ctx,
+            json!({
+                "operation": "replace_by_string",
+                "file_path": "a.txt",
+                "old_string": "three",
+                "content": "3"
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(workspace.read("a.txt"), "zero\nTWO\ntwo\n3\n");
+    }
+
+    #[tokio::test]
+    async fn require_read_refuses_unread_files() {
+        let workspace = TempWorkspace::new();
+        workspace.write("a.txt", "one\n");
+        let tool = EditFileTool::new(EditConfig { require_read: true });
+        let ctx = ToolContext::new(workspace.path(), false);
+        let replace = json!({
+            "operation": "replace_by_string",
+            "file_path": "a.txt",
+            "old_string": "one",
+            "content": "1"
+        });
+
+        let error = edit(&tool, &ctx, replace.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("has not been read"), "{}", error);
+        // New files need no read
+        edit(
+            &tool,
+            &ctx,
+            json!({ "operation": "create_file", "file_path": "b.txt", "content": "b\n" }),
+        )
+        .await
+        .unwrap();
+
+        edit(
+            &tool,
+            &ctx,
+            json!({ "operation": "read_file", "file_path": "a.txt" }),
+        )
+        .await
+        .unwrap();
+        edit(&tool, &ctx, replace).await.unwrap();
+        assert_eq!(workspace.read("a.txt"), "1\n");
+    }
+}
@@ -1,17 +1,49 @@
-use super::{Tool, ToolOutput};
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ignore::types::TypesBuilder;
+use ignore::{Walk, WalkBuilder};
+use regex::{Regex, RegexBuilder};
 use serde_json::json;
-use std::process::{Command, Stdio};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Lines shown before and after each match at most
+const MAX_CONTEXT_LINES: usize = 10;
+
+/// Results returned when the call does not set `max_results`
+const DEFAULT_MAX_RESULTS: usize = 200;
+
+/// Files whose first bytes contain a NUL are treated as binary and skipped
+const BINARY_CHECK_BYTES: usize = 8192;
+
+/// Entries listed by tree at most
+const MAX_TREE_ENTRIES: usize = 1000;
+
+/// Directories with more entries than this are shown collapsed
+const MAX_DIR_ENTRIES: usize = 200;
+
+/// Dependency and build output directories, always shown collapsed
+const COLLAPSED_DIRS: &[&str] = &[
+    "node_modules",
+    "target",
+    ".git",
+    "__pycache__",
+    ".venv",
+    "venv",
+];
 
 pub struct FileSearchTool;
 
+#[async_trait]
 impl Tool for FileSearchTool {
     fn name(&self) -> &str {
         "file_search"
     }
 
     fn description(&self) -> &str {
-        "Search for files using glob patterns or grep for content in files using ripgrep. REQUIRED: Set 'operation' to 'glob' for filename pattern matching (e.g., '**/*.rs'), or 'grep' for content search using regex patterns."
+        "Search for files using glob patterns or grep for content in files. REQUIRED: Set 'operation' to 'glob' for filename pattern matching (e.g., '**/*.rs'), or 'grep' for content search using regex patterns. grep skips hidden and gitignored files and can show context lines, only file names ('output_mode': 'files_with_matches') or counts per file ('output_mode': 'count'). Use 'tree' (or 'list_dir' for one level) to see the directory structure with file sizes instead of running ls."
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -20,20 +52,20 @@ impl Tool for FileSearchTool {
             "properties": {
                 "operation": {
                     "type": "string",
-                    "enum": ["glob", "grep"],
-                    "description": "Operation to perform: glob for filename patterns, grep for content search"
+                    "enum": ["glob", "grep", "tree", "list_dir"],
+                    "description": "Operation to perform: glob for filename patterns, grep for content search, tree or list_dir for the directory structure"
                 },
                 "pattern": {
                     "type": "string",
-                    "description": "Pattern to search for (glob pattern or regex)"
+                    "description": "Pattern to search for (glob pattern or regex; not used by tree and list_dir)"
                 },
                 "path": {
                     "type": "string",
-                    "description": "Directory to search in (default: current directory)"
+                    "description": "Directory to search in, relative to the workspace root or absolute (default: the workspace root)"
                 },
                 "file_type": {
                     "type": "string",
-                    "description": "File type filter for grep (e.g., 'rs', 'py', 'js')"
+                    "description": "File type filter for grep: a ripgrep type (e.g., 'rust', 'py', 'js') or a file extension"
                 },
                 "case_sensitive": {
                     "type": "boolean",
@@ -41,56 +73,122 @@ impl Tool for FileSearchTool {
                 },
                 "max_results": {
                     "type": "integer",
-                    "description": "Maximum number of results to return"
+                    "description": "Maximum number of results in total: matching lines, or files for files_with_matches and count (default: 200)"
+                },
+                "output_mode": {
+                    "type": "string",
+                    "enum": ["content", "files_with_matches", "count"],
+                    "description": "grep output: matching lines (default), file names only, or match counts per file"
+                },
+                "context_lines": {
+                    "type": "integer",
+                    "description": "Lines to show before and after each match (grep, at most 10)"
+                },
+                "before_context": {
+                    "type": "integer",
+                    "description": "Lines to show before each match, overriding context_lines"
+                },
+                "after_context": {
+                    "type": "integer",
+                    "description": "Lines to show after each match, overriding context_lines"
+                },
+                "max_depth": {
+                    "type": "integer",
+                    "description": "Levels to list for tree (default: 3) and list_dir (default: 1)"
+                },
+                "include_ignored": {
+                    "type": "boolean",
+                    "description": "tree/list_dir: also list hidden and gitignored files (default: false)"
                 }
             },
-            "required": ["operation", "pattern"]
+            "required": ["operation"]
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput> {
         let operation = input
             .get("operation")
             .and_then(|v| v.as_str())
             .context("Missing operation")?;
-        let pattern = input
-            .get("pattern")
-            .and_then(|v| v.as_str())
-            .context("Missing pattern")?;
+        let pattern = || {
+            input
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .context("Missing pattern")
+        };
         let path = input.get("path").and_then(|v| v.as_str()).unwrap_or(".");
+        let dir = ctx.resolve(path)?;
 
         match operation {
-            "glob" => self.glob(pattern, path),
+            "glob" => self.glob(pattern()?, path, &dir, ctx),
             "grep" => {
-                let file_type = input.get("file_type").and_then(|v| v.as_str());
-                let case_sensitive = input
-                    .get("case_sensitive")
-                    .and_then(|v| v.as_bool())
-                    .unwrap_or(true);
-                let max_results = input
-                    .get("max_results")
+                let options = GrepOptions::from_input(&input)?;
+                self.grep(pattern()?, path, dir, options, ctx).await
+            }
+            "tree" | "list_dir" => {
+                let default_depth = if operation == "tree" { 3 } else { 1 };
+                let max_depth = input
+                    .get("max_depth")
                     .and_then(|v| v.as_u64())
-                    .map(|n| n as usize);
-
-                self.grep(pattern, path, file_type, case_sensitive, max_results)
+                    .map_or(default_depth, |n| (n as usize).max(1));
+                let include_ignored = input
+                    .get("include_ignored")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let display = if Path::new(path).is_absolute() {
+                    dir.to_string_lossy().into_owned()
+                } else {
+                    match ctx.display_path(&dir) {
+                        root if root.is_empty() => ".".to_string(),
+                        relative => relative,
+                    }
+                };
+                tokio::task::spawn_blocking(move || {
+                    tree(&dir, &display, max_depth, include_ignored)
+                })
+                .await
+                .context("Listing task failed")?
             }
             _ => Err(anyhow::anyhow!("Unknown operation: {}", operation)),
         }
     }
+
+    fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+        true
+    }
 }
 
 impl FileSearchTool {
-    fn glob(&self, pattern: &str, path: &str) -> Result<ToolOutput> {
-        let glob_pattern = if path == "." {
-            pattern.to_string()
-        } else {
-            format!("{}/{}", path.trim_end_matches('/'), pattern)
-        };
+    fn glob(&self, pattern: &str, path: &str, dir: &Path, ctx: &ToolContext) -> Result<ToolOutput> {
+        if ctx.is_strict()
+            && Path::new(pattern)
+                .components()
+                .any(|c| c == Component::ParentDir)
+        {
+            return Err(anyhow::anyhow!(
+                "Glob pattern '{}' leaves the workspace (strict mode)",
+                pattern
+            ));
+        }
+        let glob_pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&dir.to_string_lossy()).trim_end_matches('/'),
+            pattern
+        );
 
+        // Report paths the way they were asked for: absolute for an absolute `path`,
+        // relative to the workspace root otherwise
+        let absolute = Path::new(path).is_absolute();
         let paths: Vec<String> = glob::glob(&glob_pattern)
             .context("Invalid glob pattern")?
             .filter_map(|entry| entry.ok())
-            .filter_map(|p| p.to_str().map(String::from))
+            .map(|p| {
+                if absolute {
+                    p.to_string_lossy().into_owned()
+                } else {
+                    ctx.display_path(&p)
+                }
+            })
             .collect();
 
         let count = paths.len();
@@ -108,251 +206,586 @@ impl FileSearchTool {
         })
     }
 
-    fn grep(
+    async fn grep(
         &self,
         pattern: &str,
         path: &str,
-        file_type: Option<&str>,
-        case_sensitive: bool,
-        max_results: Option<usize>,
+        dir: PathBuf,
+        options: GrepOptions,
+        ctx: &ToolContext,
     ) -> Result<ToolOutput> {
-        // Try grep tools in order of preference: ripgrep > grep > findstr
-        if let Ok(output) = self.try_ripgrep(pattern, path, file_type, case_sensitive, max_results)
-        {
-            return Ok(output);
-        }
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .context("Invalid regex pattern")?;
+        let walker = walker(&dir, options.file_type.as_deref())?;
+
+        // Report paths the way they were asked for, like glob
+        let absolute = Path::new(path).is_absolute();
+        let ctx = ctx.clone();
+        let display = move |p: &Path| {
+            if absolute {
+                p.to_string_lossy().into_owned()
+            } else {
+                ctx.display_path(p)
+            }
+        };
+        tokio::task::spawn_blocking(move || search(&regex, walker, &options, display))
+            .await
+            .context("Search task failed")?
+    }
+}
 
-        if let Ok(output) = self.try_grep(pattern, path, case_sensitive, max_results) {
-            return Ok(output);
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GrepMode {
+    /// Matching lines, with context
+    Content,
+    /// Only the names of files with a match
+    Files,
+    /// Number of matching lines per file
+    Count,
+}
 
-        if let Ok(output) = self.try_findstr(pattern, path, case_sensitive, max_results) {
-            return Ok(output);
-        }
+struct GrepOptions {
+    file_type: Option<String>,
+    case_sensitive: bool,
+    mode: GrepMode,
+    before: usize,
+    after: usize,
+    /// Matching lines (content mode) or files (other modes), across all files
+    max_results: usize,
+}
 
-        Err(anyhow::anyhow!(
-            "No grep tool available. Please install ripgrep (recommended), or ensure grep (Unix) or findstr (Windows) is available."
-        ))
+impl GrepOptions {
+    fn from_input(input: &serde_json::Value) -> Result<Self> {
+        let lines = |key: &str| {
+            input
+                .get(key)
+                .and_then(|v| v.as_u64())
+                .map(|n| (n as usize).min(MAX_CONTEXT_LINES))
+        };
+        let context = lines("context_lines").unwrap_or(0);
+        let mode = match input.get("output_mode").and_then(|v| v.as_str()) {
+            None | Some("content") => GrepMode::Content,
+            Some("files_with_matches") => GrepMode::Files,
+            Some("count") => GrepMode::Count,
+            Some(other) => anyhow::bail!("Unknown output_mode: {}", other),
+        };
+        Ok(Self {
+            file_type: input
+                .get("file_type")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            case_sensitive: input
+                .get("case_sensitive")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true),
+            mode,
+            before: lines("before_context").unwrap_or(context),
+            after: lines("after_context").unwrap_or(context),
+            max_results: input
+                .get("max_results")
+                .and_then(|v| v.as_u64())
+                .map_or(DEFAULT_MAX_RESULTS, |n| n as usize),
+        })
     }
+}
 
-    fn try_ripgrep(
-        &self,
-        pattern: &str,
-        path: &str,
-        file_type: Option<&str>,
-        case_sensitive: bool,
-        max_results: Option<usize>,
-    ) -> Result<ToolOutput> {
-        let mut cmd = Command::new("rg");
-        cmd.arg("--json").arg("--no-heading").arg(pattern).arg(path);
-
-        if !case_sensitive {
-            cmd.arg("-i");
+/// Files under `dir` in path order, skipping hidden and gitignored files (also
+/// outside git repositories) and, with `file_type`, other kinds of files. The type
+/// is a ripgrep type name such as `rust` or `py`, or else a file extension.
+pub(crate) fn walker(dir: &Path, file_type: Option<&str>) -> Result<Walk> {
+    let mut builder = WalkBuilder::new(dir);
+    builder
+        .require_git(false)
+        .sort_by_file_path(|a, b| a.cmp(b));
+    if let Some(file_type) = file_type {
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        if !types.definitions().iter().any(|d| d.name() == file_type) {
+            types
+                .add(file_type, &format!("*.{}", file_type))
+                .with_context(|| format!("Invalid file_type '{}'", file_type))?;
         }
+        builder.types(types.select(file_type).build()?);
+    }
+    Ok(builder.build())
+}
 
-        if let Some(ft) = file_type {
-            cmd.arg("-t").arg(ft);
+fn search(
+    regex: &Regex,
+    walker: Walk,
+    options: &GrepOptions,
+    display: impl Fn(&Path) -> String,
+) -> Result<ToolOutput> {
+    let mut matches = Vec::new();
+    let mut counts = Vec::new();
+    let mut total = 0;
+    let mut truncated = false;
+
+    for entry in walker.filter_map(|e| e.ok()) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
         }
-
-        if let Some(max) = max_results {
-            cmd.arg("--max-count").arg(max.to_string());
+        let Ok(bytes) = std::fs::read(entry.path()) else {
+            continue;
+        };
+        if bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = text.lines().collect();
+        let hits: Vec<usize> = (0..lines.len())
+            .filter(|&i| regex.is_match(lines[i]))
+            .collect();
+        if hits.is_empty() {
+            continue;
         }
 
-        let output = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .context("ripgrep not available")?;
-
-        if !output.status.success() && output.stdout.is_empty() {
-            return Ok(ToolOutput {
-                output: json!({ "matches": [], "tool": "ripgrep" }),
-                observation: "No matches found".to_string(),
-                display: None,
-                status: "success".to_string(),
-            });
+        let file = display(entry.path());
+        if options.mode != GrepMode::Content {
+            if counts.len() == options.max_results {
+                truncated = true;
+                break;
+            }
+            total += hits.len();
+            counts.push((file, hits.len()));
+            continue;
         }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut matches = Vec::new();
-        let mut files = std::collections::HashSet::new();
-
-        for line in stdout.lines() {
-            if let Ok(json_val) = serde_json::from_str::<serde_json::Value>(line) {
-                if json_val.get("type").and_then(|t| t.as_str()) == Some("match") {
-                    let data = &json_val["data"];
-                    let file = data["path"]["text"].as_str().unwrap_or("");
-                    let line_num = data["line_number"].as_u64().unwrap_or(0);
-                    let text = data["lines"]["text"].as_str().unwrap_or("");
-
-                    files.insert(file.to_string());
-                    matches.push(json!({
-                        "file": file,
-                        "line": line_num,
-                        "text": text.trim()
-                    }));
-                }
+        let line = |i: usize| json!({ "line": i + 1, "text": lines[i].trim_end() });
+        for &i in &hits {
+            if matches.len() == options.max_results {
+                truncated = true;
+                break;
             }
+            total += 1;
+            let mut result = json!({ "file": file, "line": i + 1, "text": lines[i].trim_end() });
+            if options.before > 0 {
+                let start = i.saturating_sub(options.before);
+                result["before"] = (start..i).map(line).collect();
+            }
+            if options.after > 0 {
+                let end = (i + 1 + options.after).min(lines.len());
+                result["after"] = (i + 1..end).map(line).collect();
+            }
+            matches.push(result);
         }
+        if truncated {
+            break;
+        }
+    }
 
-        let observation = format!(
-            "Found {} match(es) in {} file(s) (using ripgrep)",
-            matches.len(),
-            files.len()
-        );
+    let limit_note = if truncated {
+        format!(" (stopped at max_results = {})", options.max_results)
+    } else {
+        String::new()
+    };
+    let (output, observation, display) = match options.mode {
+        GrepMode::Content => {
+            let files: HashSet<&str> = matches.iter().filter_map(|m| m["file"].as_str()).collect();
+            let observation = format!(
+                "Found {} match(es) in {} file(s){}",
+                matches.len(),
+                files.len(),
+                limit_note
+            );
+            let display = matches
+                .iter()
+                .take(20)
+                .map(format_match)
+                .collect::<Vec<_>>()
+                .join("\n");
+            (
+                json!({ "matches": matches, "truncated": truncated }),
+                observation,
+                display,
+            )
+        }
+        GrepMode::Files => {
+            let files: Vec<&str> = counts.iter().map(|(f, _)| f.as_str()).collect();
+            (
+                json!({ "files": files, "truncated": truncated }),
+                format!("Found matches in {} file(s){}", files.len(), limit_note),
+                files.join("\n"),
+            )
+        }
+        GrepMode::Count => {
+            let listed: Vec<serde_json::Value> = counts
+                .iter()
+                .map(|(file, count)| json!({ "file": file, "count": count }))
+                .collect();
+            (
+                json!({ "counts": listed, "total": total, "truncated": truncated }),
+                format!(
+                    "Found {} match(es) in {} file(s){}",
+                    total,
+                    counts.len(),
+                    limit_note
+                ),
+                counts
+                    .iter()
+                    .map(|(file, count)| format!("{}:{}", file, count))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+        }
+    };
+
+    let observation = if total == 0 {
+        "No matches found".to_string()
+    } else {
+        observation
+    };
+    Ok(ToolOutput {
+        output,
+        observation,
+        display: (!display.is_empty()).then_some(display),
+        status: "success".to_string(),
+    })
+}
 
-        let display = matches
-            .iter()
-            .take(20)
-            .map(|m| {
+/// `file:line: text` for the match, `file-line- text` for its context, like grep
+fn format_match(m: &serde_json::Value) -> String {
+    let file = m["file"].as_str().unwrap_or("");
+    let context = |key: &str| -> Vec<String> {
+        m[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|l| {
                 format!(
-                    "{}:{}: {}",
-                    m["file"].as_str().unwrap_or(""),
-                    m["line"],
-                    m["text"].as_str().unwrap_or("")
+                    "{}-{}- {}",
+                    file,
+                    l["line"],
+                    l["text"].as_str().unwrap_or("")
                 )
             })
-            .collect::<Vec<_>>()
-            .join("\n");
+            .collect()
+    };
+    let mut lines = context("before");
+    lines.push(format!(
+        "{}:{}: {}",
+        file,
+        m["line"],
+        m["text"].as_str().unwrap_or("")
+    ));
+    lines.extend(context("after"));
+    lines.join("\n")
+}
 
-        Ok(ToolOutput {
-            output: json!({ "matches": matches, "tool": "ripgrep" }),
-            observation,
-            display: Some(display),
-            status: "success".to_string(),
-        })
+/// One entry found by the walk
+struct TreeEntry {
+    path: PathBuf,
+    is_dir: bool,
+    size: u64,
+    /// Entries inside a directory that was not descended into
+    hidden_entries: Option<usize>,
+    collapsed: bool,
+}
+
+/// List `dir` as nested JSON nodes with sizes. Directories below `max_depth`, huge
+/// ones and dependency folders are summarized with their entry count instead.
+fn tree(dir: &Path, display: &str, max_depth: usize, include_ignored: bool) -> Result<ToolOutput> {
+    if !dir.is_dir() {
+        anyhow::bail!("Not a directory: {}", display);
     }
 
-    fn try_grep(
-        &self,
-        pattern: &str,
-        path: &str,
-        case_sensitive: bool,
-        max_results: Option<usize>,
-    ) -> Result<ToolOutput> {
-        let mut cmd = Command::new("grep");
-        cmd.arg("-n") // line numbers
-            .arg("-r") // recursive
-            .arg("-E") // extended regex
-            .arg(pattern)
-            .arg(path);
-
-        if !case_sensitive {
-            cmd.arg("-i");
+    let collapsed: Arc<Mutex<Vec<PathBuf>>> = Arc::default();
+    let mut builder = WalkBuilder::new(dir);
+    builder
+        .require_git(false)
+        .max_depth(Some(max_depth))
+        .sort_by_file_path(|a, b| a.cmp(b));
+    if include_ignored {
+        builder.standard_filters(false);
+    }
+    let skipped = collapsed.clone();
+    builder.filter_entry(move |entry| {
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        if !is_dir || entry.depth() == 0 || !should_collapse(entry.path()) {
+            return true;
+        }
+        skipped.lock().unwrap().push(entry.path().to_path_buf());
+        false
+    });
+
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    for entry in builder.build().filter_map(|e| e.ok()) {
+        if entry.depth() == 0 {
+            continue;
+        }
+        if entries.len() == MAX_TREE_ENTRIES {
+            truncated = true;
+            break;
         }
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let size = if is_dir {
+            0
+        } else {
+            entry.metadata().map_or(0, |m| m.len())
+        };
+        entries.push(TreeEntry {
+            path: entry.path().to_path_buf(),
+            is_dir,
+            size,
+            hidden_entries: (is_dir && entry.depth() == max_depth)
+                .then(|| count_entries(entry.path())),
+            collapsed: false,
+        });
+    }
+    for path in collapsed.lock().unwrap().drain(..) {
+        entries.push(TreeEntry {
+            hidden_entries: Some(count_entries(&path)),
+            path,
+            is_dir: true,
+            size: 0,
+            collapsed: true,
+        });
+    }
 
-        let output = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .context("grep not available")?;
+    let nodes = tree_nodes(dir, &entries);
+    let files = entries.iter().filter(|e| !e.is_dir).count();
+    let dirs = entries.len() - files;
+    let mut lines = vec![format!("{}/", display.trim_end_matches('/'))];
+    render_tree(&nodes, 1, &mut lines);
 
-        self.parse_grep_output(&output, max_results, "grep")
+    let mut observation = format!("Listed {} file(s) and {} director(ies)", files, dirs);
+    if truncated {
+        observation.push_str(&format!(" (stopped at {} entries)", MAX_TREE_ENTRIES));
     }
+    Ok(ToolOutput {
+        output: json!({ "path": display, "tree": nodes, "truncated": truncated }),
+        observation,
+        display: Some(lines.join("\n")),
+        status: "success".to_string(),
+    })
+}
 
-    fn try_findstr(
-        &self,
-        pattern: &str,
-        path: &str,
-        case_sensitive: bool,
-        max_results: Option<usize>,
-    ) -> Result<ToolOutput> {
-        // findstr uses simple wildcards, not regex - escape special chars
-        let escaped_pattern = pattern.replace('*', ".*");
+fn should_collapse(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    COLLAPSED_DIRS.contains(&name.as_ref()) || count_entries(path) > MAX_DIR_ENTRIES
+}
 
-        let mut cmd = Command::new("findstr");
-        cmd.arg("/N") // line numbers
-            .arg("/S"); // subdirectories
+fn count_entries(dir: &Path) -> usize {
+    std::fs::read_dir(dir).map_or(0, |entries| entries.count())
+}
 
-        if !case_sensitive {
-            cmd.arg("/I");
-        }
+/// Nodes for the direct children of `parent`, directories first. A directory's
+/// size is the total of the files listed under it.
+fn tree_nodes(parent: &Path, entries: &[TreeEntry]) -> Vec<serde_json::Value> {
+    let mut children: Vec<&TreeEntry> = entries
+        .iter()
+        .filter(|e| e.path.parent() == Some(parent))
+        .collect();
+    children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then(a.path.cmp(&b.path)));
+
+    children
+        .into_iter()
+        .map(|entry| {
+            let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+            if !entry.is_dir {
+                return json!({ "name": name, "type": "file", "size": entry.size });
+            }
+            let nested = tree_nodes(&entry.path, entries);
+            let size: u64 = nested.iter().filter_map(|n| n["size"].as_u64()).sum();
+            let mut node = json!({ "name": name, "type": "dir", "size": size });
+            if let Some(count) = entry.hidden_entries {
+                node["entries"] = json!(count);
+                if entry.collapsed {
+                    node["collapsed"] = json!(true);
+                }
+            } else {
+                node["children"] = json!(nested);
+            }
+            node
+        })
+        .collect()
+}
 
-        cmd.arg(&escaped_pattern).arg(format!("{}\\*", path));
+fn render_tree(nodes: &[serde_json::Value], level: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(level);
+    for node in nodes {
+        let name = node["name"].as_str().unwrap_or("");
+        let size = format_size(node["size"].as_u64().unwrap_or(0));
+        if node["type"] == "file" {
+            lines.push(format!("{}{} ({})", indent, name, size));
+            continue;
+        }
+        match node["entries"].as_u64() {
+            Some(count) if node["collapsed"] == true => lines.push(format!(
+                "{}{}/ (collapsed, {} entries)",
+                indent, name, count
+            )),
+            Some(count) => lines.push(format!("{}{}/ ({} entries not shown)", indent, name, count)),
+            None => {
+                lines.push(format!("{}{}/ ({})", indent, name, size));
+                if let Some(children) = node["children"].as_array() {
+                    render_tree(children, level + 1, lines);
+                }
+            }
+        }
+    }
+}
 
-        let output = cmd
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .context("findstr not available")?;
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
 
-        self.parse_grep_output(&output, max_results, "findstr")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempWorkspace;
+
+    async fn grep(workspace: &TempWorkspace, input: serde_json::Value) -> ToolOutput {
+        let mut input = input;
+        input["operation"] = json!("grep");
+        FileSearchTool
+            .execute(input, &ToolContext::new(workspace.path(), false))
+            .await
+            .unwrap()
     }
 
-    fn parse_grep_output(
-        &self,
-        output: &std::process::Output,
-        max_results: Option<usize>,
-        tool_name: &str,
-    ) -> Result<ToolOutput> {
-        if !output.status.success() && output.stdout.is_empty() {
-            return Ok(ToolOutput {
-                output: json!({ "matches": [], "tool": tool_name }),
-                observation: "No matches found".to_string(),
-                display: None,
-                status: "success".to_string(),
-            });
-        }
+    fn workspace() -> TempWorkspace {
+        let workspace = TempWorkspace::new();
+        workspace.write(".gitignore", "target/\n*.log\n");
+        workspace.write("src/a.rs", "fn main() {\n    todo!();\n}\n");
+        workspace.write("src/b.rs", "// todo: one\n// TODO: two\n");
+        workspace.write("notes.md", "todo\n");
+        workspace.write("target/debug/out.rs", "todo\n");
+        workspace.write("build.log", "todo\n");
+        workspace
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut matches = Vec::new();
-        let mut files = std::collections::HashSet::new();
-
-        // Parse grep/findstr output: "file:line:text" or "file:line text"
-        for line in stdout.lines() {
-            if let Some(first_colon) = line.find(':') {
-                let file_and_rest = line.split_at(first_colon);
-                let file = file_and_rest.0;
-                let rest = &file_and_rest.1[1..]; // skip the colon
-
-                if let Some(second_colon) = rest.find(':') {
-                    let line_and_text = rest.split_at(second_colon);
-                    if let Ok(line_num) = line_and_text.0.parse::<u64>() {
-                        let text = &line_and_text.1[1..]; // skip the colon
-
-                        files.insert(file.to_string());
-                        matches.push(json!({
-                            "file": file,
-                            "line": line_num,
-                            "text": text.trim()
-                        }));
-
-                        if let Some(max) = max_results {
-                            if matches.len() >= max {
-                                break;
-                            }
-                        }
-                    }
+    #[tokio::test]
+    async fn grep_skips_ignored_files_and_shows_context() {
+        let workspace = workspace();
+
+        let result = grep(
+            &workspace,
+            json!({ "pattern": "todo", "file_type": "rs", "context_lines": 1 }),
+        )
+        .await;
+        assert_eq!(
+            result.output["matches"],
+            json!([
+                {
+                    "file": "src/a.rs", "line": 2, "text": "    todo!();",
+                    "before": [{ "line": 1, "text": "fn main() {" }],
+                    "after": [{ "line": 3, "text": "}" }]
+                },
+                {
+                    "file": "src/b.rs", "line": 1, "text": "// todo: one",
+                    "before": [],
+                    "after": [{ "line": 2, "text": "// TODO: two" }]
                 }
-            }
-        }
+            ])
+        );
+        assert_eq!(
+            result.display.unwrap(),
+            "src/a.rs-1- fn main() {\nsrc/a.rs:2:     todo!();\nsrc/a.rs-3- }\n\
+             src/b.rs:1: // todo: one\nsrc/b.rs-2- // TODO: two"
+        );
+    }
 
-        let observation = format!(
-            "Found {} match(es) in {} file(s) (using {})",
-            matches.len(),
-            files.len(),
-            tool_name
+    #[tokio::test]
+    async fn tree_lists_sizes_and_collapses_dependency_folders() {
+        let workspace = workspace();
+        workspace.write("node_modules/left-pad/index.js", "x");
+        workspace.write("src/tools/deep/c.rs", "");
+
+        let result = FileSearchTool
+            .execute(
+                json!({ "operation": "tree", "max_depth": 2 }),
+                &ToolContext::new(workspace.path(), false),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.output["tree"],
+            json!([
+                { "name": "node_modules", "type": "dir", "size": 0, "entries": 1, "collapsed": true },
+                { "name": "src", "type": "dir", "size": 53, "children": [
+                    { "name": "tools", "type": "dir", "size": 0, "entries": 1 },
+                    { "name": "a.rs", "type": "file", "size": 27 },
+                    { "name": "b.rs", "type": "file", "size": 26 }
+                ] },
+                { "name": "notes.md", "type": "file", "size": 5 }
+            ])
+        );
+        assert_eq!(
+            result.display.unwrap(),
+            "./\n  node_modules/ (collapsed, 1 entries)\n  src/ (53 B)\n    \
+             tools/ (1 entries not shown)\n    a.rs (27 B)\n    b.rs (26 B)\n  notes.md (5 B)"
         );
 
-        let display = matches
+        let listing = FileSearchTool
+            .execute(
+                json!({ "operation": "list_dir", "path": "src", "include_ignored": true }),
+                &ToolContext::new(workspace.path(), false),
+            )
+            .await
+            .unwrap();
+        let names: Vec<&str> = listing.output["tree"]
+            .as_array()
+            .unwrap()
             .iter()
-            .take(20)
-            .map(|m| {
-                format!(
-                    "{}:{}: {}",
-                    m["file"].as_str().unwrap_or(""),
-                    m["line"],
-                    m["text"].as_str().unwrap_or("")
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
+            .map(|n| n["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["tools", "a.rs", "b.rs"]);
+        assert_eq!(listing.output["path"], "src");
+    }
 
-        Ok(ToolOutput {
-            output: json!({ "matches": matches, "tool": tool_name }),
-            observation,
-            display: Some(display),
-            status: "success".to_string(),
-        })
+    #[tokio::test]
+    async fn grep_modes_and_global_limit() {
+        let workspace = workspace();
+
+        let files = grep(
+            &workspace,
+            json!({ "pattern": "todo", "case_sensitive": false, "output_mode": "files_with_matches" }),
+        )
+        .await;
+        assert_eq!(
+            files.output["files"],
+            json!(["notes.md", "src/a.rs", "src/b.rs"])
+        );
+
+        let counts = grep(
+            &workspace,
+            json!({ "pattern": "todo", "case_sensitive": false, "output_mode": "count" }),
+        )
+        .await;
+        assert_eq!(counts.output["total"], 4);
+        assert_eq!(
+            counts.output["counts"][2],
+            json!({ "file": "src/b.rs", "count": 2 })
+        );
+
+        // The total only covers the files listed
+        let limited_counts = grep(
+            &workspace,
+            json!({ "pattern": "todo", "case_sensitive": false, "output_mode": "count", "max_results": 2 }),
+        )
+        .await;
+        assert_eq!(limited_counts.output["total"], 2);
+        assert_eq!(limited_counts.output["truncated"], true);
+
+        let limited = grep(
+            &workspace,
+            json!({ "pattern": "(?i)todo", "path": "src", "max_results": 2 }),
+        )
+        .await;
+        assert_eq!(limited.output["matches"].as_array().unwrap().len(), 2);
+        assert_eq!(limited.output["truncated"], true);
+        assert_eq!(
+            limited.observation,
+            "Found 2 match(es) in 2 file(s) (stopped at max_results = 2)"
+        );
     }
 }
@@ -1,9 +1,11 @@
-use super::{Tool, ToolOutput};
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::Result;
+use async_trait::async_trait;
 use serde_json::json;
 
 pub struct FinishTool;
 
+#[async_trait]
 impl Tool for FinishTool {
     fn name(&self) -> &str {
         "finish"
@@ -28,7 +30,7 @@ impl Tool for FinishTool {
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let answer = input
             .get("answer")
             .and_then(|v| v.as_str())
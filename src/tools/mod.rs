@@ -1,12 +1,23 @@
+pub mod apply_patch;
+pub mod background;
 pub mod bash;
+pub mod code_symbols;
+pub mod context;
+pub mod diff;
 pub mod edit_file;
 pub mod file_search;
 pub mod finish;
+mod shell;
 pub mod url_fetch;
 pub mod web_search;
 pub mod web_search_ddg;
 
 use anyhow::Result;
+use async_trait::async_trait;
+use background::BackgroundProcesses;
+use bash::BashConfig;
+pub use context::ToolContext;
+use edit_file::EditConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -25,41 +36,69 @@ pub struct ToolOutput {
     pub status: String,
 }
 
+#[async_trait]
 pub trait Tool: Send + Sync {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     fn input_schema(&self) -> serde_json::Value;
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput>;
+    async fn execute(&self, input: serde_json::Value, ctx: &ToolContext) -> Result<ToolOutput>;
+
+    /// Whether this call only reads state. Read-only calls from the same response
+    /// may run concurrently.
+    fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+        false
+    }
+
+    /// What the call would change, shown to the user before asking for approval.
+    /// For file edits this is a unified diff.
+    fn preview(&self, _input: &serde_json::Value, _ctx: &ToolContext) -> Result<Option<String>> {
+        Ok(None)
+    }
 }
 
 pub struct ToolRegistry {
     tools: HashMap<String, Box<dyn Tool>>,
+    background: BackgroundProcesses,
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
         let mut registry = Self {
             tools: HashMap::new(),
+            background: BackgroundProcesses::new(),
         };
 
         // Register built-in tools
         registry.register(Box::new(file_search::FileSearchTool));
-        registry.register(Box::new(edit_file::EditFileTool));
-        registry.register(Box::new(bash::BashTool));
+        registry.register(Box::new(
+            edit_file::EditFileTool::new(EditConfig::default()),
+        ));
+        registry.register(Box::new(apply_patch::ApplyPatchTool::new(
+            EditConfig::default(),
+        )));
+        registry.register(Box::new(code_symbols::CodeSymbolsTool));
+        registry.register_bash(BashConfig::default());
         registry.register(Box::new(finish::FinishTool));
 
         registry
     }
 
-    pub fn new_with_api_keys(web_search_api_key: Option<String>) -> Self {
+    pub fn new_with_api_keys(
+        web_search_api_key: Option<String>,
+        bash_config: BashConfig,
+        edit_config: EditConfig,
+    ) -> Self {
         let mut registry = Self {
             tools: HashMap::new(),
+            background: BackgroundProcesses::new(),
         };
 
         // Register built-in tools
         registry.register(Box::new(file_search::FileSearchTool));
-        registry.register(Box::new(edit_file::EditFileTool));
-        registry.register(Box::new(bash::BashTool));
+        registry.register(Box::new(edit_file::EditFileTool::new(edit_config.clone())));
+        registry.register(Box::new(apply_patch::ApplyPatchTool::new(edit_config)));
+        registry.register(Box::new(code_symbols::CodeSymbolsTool));
+        registry.register_bash(bash_config);
         registry.register(Box::new(finish::FinishTool));
 
         // Web search tools: Serper API (if key provided) or DuckDuckGo (free fallback)
@@ -77,8 +116,36 @@ impl ToolRegistry {
         self.tools.insert(tool.name().to_string(), tool);
     }
 
+    /// The bash tool and the process tool share the set of background processes
+    fn register_bash(&mut self, config: BashConfig) {
+        let background = self.background.clone();
+        self.register(Box::new(bash::BashTool::new(config, background.clone())));
+        self.register(Box::new(background::ProcessTool::new(background)));
+    }
+
+    /// Background processes started by the bash tool, killed when the registry is dropped
+    pub fn background_processes(&self) -> &BackgroundProcesses {
+        &self.background
+    }
+
     pub fn get(&self, name: &str) -> Option<&dyn Tool> {
-        self.tools.get(name).map(|b| b.as_ref())
+        self.tools.get(name).map(|t| t.as_ref())
+    }
+
+    pub fn is_read_only(&self, name: &str, input: &serde_json::Value) -> bool {
+        self.get(name).is_some_and(|tool| tool.is_read_only(input))
+    }
+
+    pub fn preview(
+        &self,
+        name: &str,
+        input: &serde_json::Value,
+        ctx: &ToolContext,
+    ) -> Result<Option<String>> {
+        match self.get(name) {
+            Some(tool) => tool.preview(input, ctx),
+            None => Ok(None),
+        }
     }
 
     pub fn list_definitions(&self) -> Vec<ToolDefinition> {
@@ -92,11 +159,37 @@ impl ToolRegistry {
             .collect()
     }
 
-    pub fn execute(&self, name: &str, input: serde_json::Value) -> Result<ToolOutput> {
+    pub async fn execute(
+        &self,
+        name: &str,
+        input: serde_json::Value,
+        ctx: &ToolContext,
+    ) -> Result<ToolOutput> {
         let tool = self
             .get(name)
             .ok_or_else(|| anyhow::anyhow!("Tool not found: {}", name))?;
-        tool.execute(input)
+        // Providers keep arguments that failed to parse as the raw string
+        if let serde_json::Value::String(raw) = &input {
+            anyhow::bail!(
+                "arguments were not valid JSON: {}. Send the call again with a JSON object",
+                raw
+            );
+        }
+        tool.execute(input, ctx).await
+    }
+
+    /// Run several tool calls at once. Results are returned in the order of `calls`.
+    pub async fn execute_concurrently(
+        &self,
+        calls: Vec<(String, serde_json::Value)>,
+        ctx: &ToolContext,
+    ) -> Vec<Result<ToolOutput>> {
+        futures::future::join_all(
+            calls
+                .into_iter()
+                .map(|(name, input)| async move { self.execute(&name, input, ctx).await }),
+        )
+        .await
     }
 }
 
@@ -105,3 +198,94 @@ impl Default for ToolRegistry {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+
+    /// Sleeps for `ms` milliseconds and reports its `id`
+    struct SleepTool;
+
+    #[async_trait]
+    impl Tool for SleepTool {
+        fn name(&self) -> &str {
+            "sleep"
+        }
+
+        fn description(&self) -> &str {
+            "Sleep"
+        }
+
+        fn input_schema(&self) -> serde_json::Value {
+            json!({"type": "object"})
+        }
+
+        async fn execute(
+            &self,
+            input: serde_json::Value,
+            _ctx: &ToolContext,
+        ) -> Result<ToolOutput> {
+            tokio::time::sleep(Duration::from_millis(input["ms"].as_u64().unwrap())).await;
+            Ok(ToolOutput {
+                output: json!({}),
+                observation: input["id"].as_str().unwrap().to_string(),
+                display: None,
+                status: "success".to_string(),
+            })
+        }
+
+        fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+            true
+        }
+    }
+
+    // Runs on tokio's default current-thread test runtime
+    #[tokio::test]
+    async fn concurrent_results_keep_call_order() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Box::new(SleepTool));
+
+        let started = Instant::now();
+        let results = registry
+            .execute_concurrently(
+                vec![
+                    ("sleep".to_string(), json!({"id": "a", "ms": 300})),
+                    ("sleep".to_string(), json!({"id": "b", "ms": 100})),
+                    ("missing".to_string(), json!({})),
+                    // Arguments that failed to parse never reach the tool
+                    ("sleep".to_string(), json!("{\"id\": \"d")),
+                    ("sleep".to_string(), json!({"id": "c", "ms": 200})),
+                ],
+                &ToolContext::new(".", false),
+            )
+            .await;
+
+        assert!(started.elapsed() < Duration::from_millis(550));
+        let observations: Vec<String> = results
+            .into_iter()
+            .map(|r| r.map(|o| o.observation).unwrap_or_else(|e| e.to_string()))
+            .collect();
+        assert_eq!(
+            observations,
+            vec![
+                "a",
+                "b",
+                "Tool not found: missing",
+                "arguments were not valid JSON: {\"id\": \"d. Send the call again with a JSON object",
+                "c"
+            ]
+        );
+    }
+
+    #[test]
+    fn read_only_depends_on_tool_and_input() {
+        let registry = ToolRegistry::new();
+        assert!(registry.is_read_only("file_search", &json!({"operation": "grep"})));
+        assert!(registry.is_read_only("edit_file", &json!({"operation": "read_file"})));
+        assert!(!registry.is_read_only("edit_file", &json!({"operation": "create_file"})));
+        assert!(!registry.is_read_only("bash", &json!({"command": "ls"})));
+        assert!(!registry.is_read_only("missing", &json!({})));
+    }
+}
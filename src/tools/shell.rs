@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use uuid::Uuid;
+
+use super::bash::{OutputCapture, ProcessGroup};
+
+/// Shells whose syntax the command wrapper below works with
+const POSIX_SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh"];
+
+/// How a command in the persistent shell ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellStatus {
+    /// The command finished and the shell is ready for the next one
+    Finished(i32),
+    /// The shell itself exited (e.g. `exit 3`), with this code
+    Exited(i32),
+}
+
+/// A long-lived shell that keeps `cd`, `export`, functions and sourced scripts between
+/// commands. Output of each command is delimited by a random sentinel line printed after
+/// it, which also carries the exit code. Dropping the session kills its process group.
+pub struct ShellSession {
+    shell: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: BufReader<ChildStderr>,
+    _group: ProcessGroup,
+}
+
+impl ShellSession {
+    /// Start the user's `$SHELL` if it is POSIX-compatible, `/bin/sh` otherwise, in `dir`
+    pub fn start(dir: &Path) -> Result<Self> {
+        let shell = std::env::var("SHELL")
+            .ok()
+            .filter(|path| {
+                std::path::Path::new(path)
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| POSIX_SHELLS.contains(&name))
+            })
+            .unwrap_or_else(|| "/bin/sh".to_string());
+
+        let mut cmd = Command::new(&shell);
+        cmd.current_dir(dir);
+        #[cfg(unix)]
+        cmd.process_group(0);
+
+        let mut child = cmd
+            .kill_on_drop(true)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start shell '{}'", shell))?;
+        let group = ProcessGroup::new(child.id());
+
+        Ok(Self {
+            shell,
+            stdin: child.stdin.take().context("Failed to open shell stdin")?,
+            stdout: BufReader::new(child.stdout.take().context("Failed to capture stdout")?),
+            stderr: BufReader::new(child.stderr.take().context("Failed to capture stderr")?),
+            child,
+            _group: group,
+        })
+    }
+
+    /// Run one command, collecting its output until the sentinels come back
+    pub async fn run(
+        &mut self,
+        command: &str,
+        cwd: Option<&Path>,
+        stdout: &mut OutputCapture,
+        stderr: &mut OutputCapture,
+        live: bool,
+    ) -> Result<ShellStatus> {
+        // A command that doesn't parse on its own (an unclosed quote or `if`) would
+        // swallow the sentinel lines and leave the shell waiting for more input
+        if let Some((code, message)) = self.check_syntax(command).await? {
+            if live {
+                eprint!("{}", String::from_utf8_lossy(&message).dimmed());
+            }
+            stderr.push(&message);
+            return Ok(ShellStatus::Finished(code));
+        }
+
+        let marker = format!("__CODEAGENT_{}_", Uuid::new_v4().simple());
+        self.stdin
+            .write_all(wrap_command(command, cwd, &marker).as_bytes())
+            .await
+            .context("Failed to send command to shell")?;
+        self.stdin.flush().await?;
+
+        let (code, _) = tokio::try_join!(
+            read_until_marker(&mut self.stdout, &marker, stdout, live),
+            read_until_marker(&mut self.stderr, &marker, stderr, live)
+        )?;
+
+        match code.and_then(|rest| parse_exit_code(&rest)) {
+            Some(code) => Ok(ShellStatus::Finished(code)),
+            None => {
+                let status = self.child.wait().await?;
+                Ok(ShellStatus::Exited(status.code().unwrap_or(-1)))
+            }
+        }
+    }
+
+    /// Parse `command` with `-n` in a separate shell. Returns the exit code and error
+    /// message if it is not valid syntax.
+    async fn check_syntax(&self, command: &str) -> Result<Option<(i32, Vec<u8>)>> {
+        let output = Command::new(&self.shell)
+            .args(["-n", "-c", command])
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Failed to check command syntax")?;
+        Ok((!output.status.success()).then(|| (output.status.code().unwrap_or(2), output.stderr)))
+    }
+}
+
+/// Run `command` through `eval` in a brace group (so state changes stick, and an
+/// unterminated heredoc ends with the command instead of taking in the sentinel lines)
+/// with stdin closed (so it can't read the sentinel lines), then print the sentinels
+/// with the exit code
+fn wrap_command(command: &str, cwd: Option<&Path>, marker: &str) -> String {
+    let cd = cwd
+        .map(|dir| format!("cd -- {} && ", single_quote(&dir.to_string_lossy())))
+        .unwrap_or_default();
+    format!(
+        "{cd}{{\neval {}\n}} < /dev/null\nprintf '{marker}%s__\\n' \"$?\"\nprintf '{marker}_\\n' >&2\n",
+        single_quote(command)
+    )
+}
+
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Copy lines into `capture` until one contains `marker`. Returns what follows the
+/// marker on that line, or None if the stream ended first (the shell exited).
+async fn read_until_marker(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    marker: &str,
+    capture: &mut OutputCapture,
+    live: bool,
+) -> Result<Option<String>> {
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(None);
+        }
+
+        // Output without a trailing newline ends up on the sentinel line
+        let (output, rest) = match line
+            .windows(marker.len())
+            .position(|w| w == marker.as_bytes())
+        {
+            Some(pos) => (
+                &line[..pos],
+                Some(String::from_utf8_lossy(&line[pos + marker.len()..]).into_owned()),
+            ),
+            None => (&line[..], None),
+        };
+
+        if live && !output.is_empty() {
+            print!("{}", String::from_utf8_lossy(output).dimmed());
+            let _ = std::io::stdout().flush();
+        }
+        capture.push(output);
+
+        if rest.is_some() {
+            return Ok(rest);
+        }
+    }
+}
+
+/// The stdout sentinel line reads `<marker><code>__`
+fn parse_exit_code(rest: &str) -> Option<i32> {
+    rest.trim_end().strip_suffix("__")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_command_with_sentinels() {
+        let script = wrap_command("echo 'hi'", Some(Path::new("/tmp/it's")), "__M_");
+        assert_eq!(
+            script,
+            "cd -- '/tmp/it'\\''s' && {\neval 'echo '\\''hi'\\'''\n} < /dev/null\nprintf '__M_%s__\\n' \"$?\"\nprintf '__M__\\n' >&2\n"
+        );
+        assert_eq!(parse_exit_code("127__\n"), Some(127));
+        assert_eq!(parse_exit_code("_\n"), None);
+    }
+}
@@ -1,5 +1,6 @@
-use super::{Tool, ToolOutput};
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use serde_json::json;
 
 pub struct URLFetchTool;
@@ -69,6 +70,7 @@ impl URLFetchTool {
     }
 }
 
+#[async_trait]
 impl Tool for URLFetchTool {
     fn name(&self) -> &str {
         "url_fetch"
@@ -100,7 +102,7 @@ impl Tool for URLFetchTool {
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let url = input
             .get("url")
             .and_then(|v| v.as_str())
@@ -114,9 +116,7 @@ impl Tool for URLFetchTool {
             .and_then(|v| v.as_u64())
             .unwrap_or(10000) as usize;
 
-        let content = tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(self.fetch_url(url, format, max_length))
-        })?;
+        let content = self.fetch_url(url, format, max_length).await?;
 
         let observation = format!("Fetched content from {} ({} format)", url, format);
 
@@ -131,4 +131,60 @@ impl Tool for URLFetchTool {
             status: "success".to_string(),
         })
     }
+
+    fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve one GET request with an HTML body
+    async fn mock_server(html: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/docs", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                html.len(),
+                html
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        url
+    }
+
+    // tokio::test uses a current-thread runtime, where block_in_place would panic
+    #[tokio::test]
+    async fn fetches_on_current_thread_runtime() {
+        let url =
+            mock_server("<html><body><h1>Guide</h1><p>Install with cargo.</p></body></html>").await;
+
+        let result = URLFetchTool
+            .execute(
+                json!({ "url": url, "max_length": 200 }),
+                &ToolContext::new(".", false),
+            )
+            .await
+            .unwrap();
+
+        let content = result.output["content"].as_str().unwrap();
+        assert!(content.contains("Guide"));
+        assert!(content.contains("Install with cargo."));
+        assert_eq!(result.status, "success");
+    }
 }
@@ -1,5 +1,6 @@
-use super::{Tool, ToolOutput};
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -11,10 +12,6 @@ pub struct WebSearchTool {
 struct SerperResponse {
     #[serde(rename = "organic")]
     organic: Option<Vec<OrganicResult>>,
-    #[serde(rename = "answerBox")]
-    answer_box: Option<serde_json::Value>,
-    #[serde(rename = "knowledgeGraph")]
-    knowledge_graph: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -78,6 +75,7 @@ impl WebSearchTool {
     }
 }
 
+#[async_trait]
 impl Tool for WebSearchTool {
     fn name(&self) -> &str {
         "web_search"
@@ -109,7 +107,7 @@ impl Tool for WebSearchTool {
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let query = input
             .get("query")
             .and_then(|v| v.as_str())
@@ -124,10 +122,7 @@ impl Tool for WebSearchTool {
             .and_then(|v| v.as_str())
             .unwrap_or("web");
 
-        // Use block_in_place to allow blocking on async code from within async context
-        let result = tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(self.search(query, num_results, search_type))
-        })?;
+        let result = self.search(query, num_results, search_type).await?;
 
         let results = result.organic.unwrap_or_default();
         let count = results.len();
@@ -160,4 +155,8 @@ impl Tool for WebSearchTool {
             status: "success".to_string(),
         })
     }
+
+    fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+        true
+    }
 }
@@ -1,5 +1,6 @@
-use super::{Tool, ToolOutput};
+use super::{Tool, ToolContext, ToolOutput};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use serde_json::json;
 use websearch::{providers::duckduckgo::DuckDuckGoProvider, SearchOptions};
 
@@ -33,6 +34,7 @@ impl WebSearchDDGTool {
     }
 }
 
+#[async_trait]
 impl Tool for WebSearchDDGTool {
     fn name(&self) -> &str {
         "web_search_ddg"
@@ -64,7 +66,7 @@ impl Tool for WebSearchDDGTool {
         })
     }
 
-    fn execute(&self, input: serde_json::Value) -> Result<ToolOutput> {
+    async fn execute(&self, input: serde_json::Value, _ctx: &ToolContext) -> Result<ToolOutput> {
         let query = input
             .get("query")
             .and_then(|v| v.as_str())
@@ -79,9 +81,7 @@ impl Tool for WebSearchDDGTool {
             .and_then(|v| v.as_str())
             .unwrap_or("web");
 
-        let results = tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(self.search(query, num_results, search_type))
-        })?;
+        let results = self.search(query, num_results, search_type).await?;
 
         let count = results.len();
 
@@ -118,4 +118,8 @@ impl Tool for WebSearchDDGTool {
             status: "success".to_string(),
         })
     }
+
+    fn is_read_only(&self, _input: &serde_json::Value) -> bool {
+        true
+    }
 }
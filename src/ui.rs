@@ -0,0 +1,261 @@
+use colored::Colorize;
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use crate::agent::{AgentEvents, Approval};
+use crate::provider::retry::RetryNotice;
+use crate::provider::{StreamChunk, ToolCall};
+use crate::tools::ToolOutput;
+
+/// Write a debug log entry to the specified file with timestamp
+fn write_debug_log(log_path: &str, entry: &str) {
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(log_path) {
+        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+        let _ = writeln!(file, "[{}] {}", timestamp, entry);
+    }
+}
+
+/// Terminal front-end: prints streamed text and tool activity, and optionally writes
+/// a debug log of raw model output and tool calls
+pub struct ConsoleUi {
+    debug_log: Option<String>,
+    header_printed: bool,
+    /// Calls whose diff was already shown in the approval prompt
+    previewed: HashSet<String>,
+}
+
+impl ConsoleUi {
+    pub fn new(debug_log: Option<String>) -> Self {
+        Self {
+            debug_log,
+            header_printed: false,
+            previewed: HashSet::new(),
+        }
+    }
+
+    fn log(&self, entry: impl FnOnce() -> String) {
+        if let Some(ref log_path) = self.debug_log {
+            write_debug_log(log_path, &entry());
+        }
+    }
+}
+
+/// Print a unified diff with added lines in green and removed lines in red
+fn print_diff(diff: &str) {
+    println!();
+    for line in diff.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("    {}", line.bold());
+        } else if line.starts_with("@@") {
+            println!("    {}", line.cyan());
+        } else if line.starts_with('+') {
+            println!("    {}", line.green());
+        } else if line.starts_with('-') {
+            println!("    {}", line.red());
+        } else {
+            println!("    {}", line.dimmed());
+        }
+    }
+}
+
+/// Print selected string arguments of a tool call under a label
+fn print_arguments(call: &ToolCall, fields: &[(&str, &str)]) {
+    for (key, label) in fields {
+        if let Some(value) = call.arguments.get(key).and_then(|v| v.as_str()) {
+            println!("    {}: {}", label, value.dimmed());
+        }
+    }
+}
+
+impl AgentEvents for ConsoleUi {
+    fn on_request(&mut self, message_count: usize) {
+        self.log(|| format!("SENDING: {} messages", message_count));
+    }
+
+    fn on_chunk(&mut self, chunk: &StreamChunk) {
+        self.log(|| {
+            let chunk_json =
+                serde_json::to_string(chunk).unwrap_or_else(|_| format!("{:?}", chunk));
+            format!("STREAM_CHUNK: {}", chunk_json)
+        });
+    }
+
+    fn on_retry(&mut self, notice: &RetryNotice) {
+        self.log(|| format!("RETRY: {}", notice.reason));
+        eprintln!(
+            "{}",
+            format!(
+                "{} Retrying in {:.1}s (attempt {}/{})",
+                notice.reason,
+                notice.delay.as_secs_f64(),
+                notice.attempt,
+                notice.max_attempts
+            )
+            .yellow()
+        );
+    }
+
+    fn on_text(&mut self, text: &str) {
+        // Only print the "Assistant:" header once we know there's text to show
+        if !self.header_printed {
+            println!("{}", "\nAssistant: ".bold().blue());
+            self.header_printed = true;
+        }
+        print!("{}", text);
+        let _ = std::io::stdout().flush();
+    }
+
+    fn on_response_end(&mut self) {
+        if self.header_printed {
+            println!(); // Newline after streaming
+            self.header_printed = false;
+        }
+    }
+
+    fn on_tools_start(&mut self, _calls: &[ToolCall]) {
+        println!("\n{}", "Executing tools...".yellow());
+    }
+
+    fn on_tool_call(&mut self, call: &ToolCall) {
+        println!("  {} {}", "→".blue(), call.name.bold());
+
+        match call.name.as_str() {
+            "bash" => print_arguments(
+                call,
+                &[
+                    ("command", "Command"),
+                    ("description", "Description"),
+                    ("cwd", "Working directory"),
+                ],
+            ),
+            "file_search" => print_arguments(
+                call,
+                &[
+                    ("operation", "Operation"),
+                    ("pattern", "Pattern"),
+                    ("path", "Path"),
+                    ("file_type", "File type"),
+                ],
+            ),
+            "process" => print_arguments(call, &[("operation", "Operation"), ("handle", "Handle")]),
+            "code_symbols" => print_arguments(
+                call,
+                &[
+                    ("operation", "Operation"),
+                    ("file_path", "File"),
+                    ("symbol", "Symbol"),
+                ],
+            ),
+            _ => {}
+        }
+
+        self.log(|| {
+            let args_json = serde_json::to_string(&call.arguments)
+                .unwrap_or_else(|_| format!("{:?}", call.arguments));
+            format!("TOOL_CALL: {} ({}) args={}", call.name, call.id, args_json)
+        });
+    }
+
+    fn approve_tool_call(
+        &mut self,
+        call: &ToolCall,
+        reason: &str,
+        preview: Option<&str>,
+    ) -> Approval {
+        println!("\n🔍 Approval Required ({}):", reason);
+        match call.name.as_str() {
+            "bash" => {
+                print_arguments(
+                    call,
+                    &[
+                        ("description", "Description"),
+                        ("command", "Command"),
+                        ("cwd", "Working directory"),
+                    ],
+                );
+                if call.arguments.get("run_in_background") == Some(&serde_json::json!(true)) {
+                    println!("    Runs in the background");
+                }
+            }
+            "edit_file" => {
+                print_arguments(call, &[("operation", "Operation"), ("file_path", "File")])
+            }
+            // The preview below shows every file the patch changes
+            "apply_patch" if preview.is_some() => {}
+            _ => println!("    Arguments: {}", call.arguments.to_string().dimmed()),
+        }
+        if let Some(diff) = preview.filter(|d| !d.is_empty()) {
+            print_diff(diff);
+            self.previewed.insert(call.id.clone());
+        }
+
+        let per_file = call.name == "edit_file";
+        if per_file {
+            print!("\nAllow this edit? [y]es / [n]o / [a]lways for this file: ");
+        } else {
+            print!("\nAllow this {} call? [y/N]: ", call.name);
+        }
+        let _ = std::io::stdout().flush();
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).is_err() {
+            return Approval::Reject;
+        }
+        let approval = match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => Approval::Approve,
+            "a" | "always" if per_file => Approval::AlwaysAllowFile,
+            _ => Approval::Reject,
+        };
+        self.log(|| format!("APPROVAL: {} {:?}", call.name, approval));
+        approval
+    }
+
+    fn on_tool_result(&mut self, call: &ToolCall, result: &ToolOutput) {
+        self.log(|| {
+            let output_json = serde_json::to_string(&result.output)
+                .unwrap_or_else(|_| format!("{:?}", result.output));
+            format!(
+                "TOOL_RESULT: {} status={} output={}",
+                call.name, result.status, output_json
+            )
+        });
+
+        println!("    {}", result.observation.green());
+        if let Some(display) = &result.display {
+            if !display.is_empty() {
+                println!("\n{}\n", display.dimmed());
+            }
+        }
+        let previewed = self.previewed.remove(&call.id);
+        let diff = result.output.get("diff").and_then(|v| v.as_str());
+        if let Some(diff) = diff.filter(|d| !d.is_empty() && !previewed) {
+            print_diff(diff);
+        }
+    }
+
+    fn on_tool_error(&mut self, call: &ToolCall, error: &anyhow::Error) {
+        self.log(|| format!("TOOL_ERROR: {} error={}", call.name, error));
+        self.previewed.remove(&call.id);
+        println!("    {}", format!("Tool execution failed: {}", error).red());
+    }
+
+    fn on_compaction_start(&mut self) {
+        println!("{}", "Compacting conversation history...".yellow());
+    }
+
+    fn on_compaction_end(&mut self, result: &anyhow::Result<usize>) {
+        match result {
+            Ok(0) => println!("{}", "Nothing to compact yet.".dimmed()),
+            Ok(count) => println!(
+                "{}",
+                format!("Summarized {} earlier messages.", count).green()
+            ),
+            Err(e) => println!("{}", format!("Compaction failed: {:#}", e).red()),
+        }
+    }
+
+    fn on_interrupted(&mut self) {
+        println!("\n{}", "Interrupted by user.".yellow());
+    }
+}